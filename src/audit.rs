@@ -0,0 +1,413 @@
+/**
+ * 監査ログ（tools/call ごとに1行のJSONL）
+ *
+ * 概要:
+ *   エージェントが実行した操作を後から確認できるよう、tools/call 1回ごとに
+ *   日時・クライアント名・ツール名・引数・所要時間・結果・生成ファイルを1行のJSONで記録する。
+ *
+ * 主な仕様:
+ *   - 出力先は AFFINITY_MCP_AUDIT_LOG（未設定なら記録しない）
+ *   - ファイルが AFFINITY_MCP_AUDIT_MAX_BYTES（既定: 10MiB、0 でローテーションなし）を超えると
+ *     <パス>.1 〜 <パス>.<AFFINITY_MCP_AUDIT_MAX_FILES>（既定: 5）へ順に繰り下げる
+ *   - 引数のうち秘密情報らしいキー（key / token / secret / password など）と
+ *     APIキー形式（sk-）の値は [REDACTED] に置き換える
+ *   - 1行ごとにフラッシュする（終了時は flush() で念のため書き出す）
+ *   - ファイルは所有者のみ読み書きできる権限（0600）で作成する
+ *
+ * エラー処理:
+ *   - 書き込みの失敗はツール呼び出しを失敗させず、stderr にログを出す
+ */
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::mcp::{CallToolResult, Content};
+use crate::resources;
+use crate::session::Session;
+
+/// ローテーションするサイズの既定値
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// 残す世代数の既定値
+const DEFAULT_MAX_FILES: usize = 5;
+/// 秘密情報とみなす引数名（小文字で部分一致）
+const SECRET_KEYS: &[&str] = &["key", "token", "secret", "password", "passwd", "authorization", "credential", "cookie"];
+/// 置き換え後の値
+const REDACTED: &str = "[REDACTED]";
+
+/**
+ * 監査ログの出力先の設定
+ */
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// 出力先ファイル
+    pub path: PathBuf,
+    /// ローテーションするサイズ（0 ならローテーションしない）
+    pub max_bytes: u64,
+    /// 残す世代数（<パス>.1 〜 <パス>.N）
+    pub max_files: usize,
+}
+
+impl AuditConfig {
+    /**
+     * 環境変数から設定を読み込む
+     *
+     * 戻り値:
+     *   Option<AuditConfig> - AFFINITY_MCP_AUDIT_LOG が未設定なら None
+     */
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("AFFINITY_MCP_AUDIT_LOG").filter(|p| !p.is_empty())?;
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        Some(Self {
+            path: PathBuf::from(path),
+            max_bytes: number("AFFINITY_MCP_AUDIT_MAX_BYTES").unwrap_or(DEFAULT_MAX_BYTES),
+            max_files: number("AFFINITY_MCP_AUDIT_MAX_FILES").map_or(DEFAULT_MAX_FILES, |n| n as usize),
+        })
+    }
+}
+
+/**
+ * 監査ログファイル
+ */
+struct AuditLog {
+    config: AuditConfig,
+    file: BufWriter<File>,
+    /// 現在のファイルサイズ
+    size: u64,
+}
+
+impl AuditLog {
+    fn open(config: AuditConfig) -> Result<Self> {
+        if let Some(dir) = config.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("監査ログのディレクトリを作成できません: {}", dir.display()))?;
+        }
+        let file = open_append(&config.path)?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self {
+            config,
+            file: BufWriter::new(file),
+            size,
+        })
+    }
+
+    /// 1行追記する（必要ならローテーションしてから書く）
+    fn append(&mut self, line: &str) -> Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.config.max_bytes > 0 && self.size > 0 && self.size + length > self.config.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line).context("監査ログの書き込みに失敗しました")?;
+        self.file.flush().context("監査ログのフラッシュに失敗しました")?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// <パス>.N-1 → <パス>.N、…、<パス> → <パス>.1 と繰り下げて新しいファイルを開く
+    fn rotate(&mut self) -> Result<()> {
+        self.file.flush().context("監査ログのフラッシュに失敗しました")?;
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            std::fs::remove_file(path).ok();
+        } else {
+            for generation in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, generation);
+                if from.exists() {
+                    std::fs::rename(&from, rotated_path(path, generation + 1))
+                        .with_context(|| format!("監査ログのローテーションに失敗しました: {}", from.display()))?;
+                }
+            }
+            std::fs::rename(path, rotated_path(path, 1))
+                .with_context(|| format!("監査ログのローテーションに失敗しました: {}", path.display()))?;
+        }
+        self.file = BufWriter::new(open_append(path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, generation: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_os_string();
+    rotated.push(format!(".{}", generation));
+    PathBuf::from(rotated)
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .with_context(|| format!("監査ログを開けません: {}", path.display()))
+}
+
+/// プロセス全体の監査ログ（未初期化・無効なら None）
+fn log() -> &'static OnceLock<Mutex<AuditLog>> {
+    static LOG: OnceLock<Mutex<AuditLog>> = OnceLock::new();
+    &LOG
+}
+
+/**
+ * 環境変数の設定で監査ログを有効にする
+ *
+ * 戻り値:
+ *   Result<bool> - 有効にしたかどうか（AFFINITY_MCP_AUDIT_LOG が未設定なら false）
+ *
+ * エラー:
+ *   ログファイルを開けない場合はエラーを返す
+ */
+pub fn init_from_env() -> Result<bool> {
+    let Some(config) = AuditConfig::from_env() else {
+        return Ok(false);
+    };
+    let audit_log = AuditLog::open(config)?;
+    let _ = log().set(Mutex::new(audit_log));
+    Ok(true)
+}
+
+/**
+ * テスト用の監査ログを有効にしてそのパスを返す（プロセス内で1回だけ作成する）
+ *
+ * 概要:
+ *   並行するテストの記録も同じファイルに入るため、テストはリクエストIDで自分の行を探す。
+ */
+#[cfg(test)]
+pub fn enable_for_tests() -> &'static Path {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("affinity-mcp-audit-calls-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit_log = AuditLog::open(AuditConfig {
+            path: path.clone(),
+            max_bytes: 0,
+            max_files: 0,
+        })
+        .unwrap();
+        let _ = log().set(Mutex::new(audit_log));
+        path
+    })
+}
+
+/**
+ * テスト用の監査ログから、リクエストIDが一致する行を返す
+ */
+#[cfg(test)]
+pub fn entry_for_tests(request_id: &str) -> Option<Value> {
+    std::fs::read_to_string(enable_for_tests())
+        .ok()?
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|entry| entry["request_id"] == request_id)
+}
+
+/// 監査ログをフラッシュする（終了処理から呼ぶ）
+pub fn flush() {
+    if let Some(audit_log) = log().get() {
+        let mut audit_log = audit_log.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = audit_log.file.flush() {
+            warn!(error = %e, "監査ログのフラッシュに失敗しました");
+        }
+    }
+}
+
+fn write(entry: &Value) {
+    let Some(audit_log) = log().get() else {
+        return;
+    };
+    let mut audit_log = audit_log.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = audit_log.append(&entry.to_string()) {
+        warn!(error = %format!("{:#}", e), "監査ログを記録できませんでした");
+    }
+}
+
+/**
+ * 記録中の tools/call（開始時に作成し、終了時に1行書き出す）
+ */
+pub struct CallRecord {
+    timestamp: SystemTime,
+    started: Instant,
+    client: Option<String>,
+    request_id: Option<Value>,
+    /// ツール名（解決後は登録名）
+    pub tool: String,
+    arguments: Value,
+}
+
+impl CallRecord {
+    /**
+     * 記録を開始
+     *
+     * 引数:
+     *   session: 呼び出し元のセッション（クライアント名を記録する）
+     *   request_id: JSON-RPCリクエストID
+     *   tool: 要求されたツール名
+     *   arguments: 引数（秘密情報は置き換えて保持する）
+     */
+    pub fn start(session: &Arc<Session>, request_id: Option<&Value>, tool: &str, arguments: &Value) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            started: Instant::now(),
+            client: session.client_name(),
+            request_id: request_id.cloned(),
+            tool: tool.to_string(),
+            arguments: redact(arguments),
+        }
+    }
+
+    /// 実行結果を記録
+    pub fn finish(self, result: &CallToolResult) {
+        let error = result
+            .is_error
+            .then(|| result.structured_content.as_ref().and_then(|s| s.get("error")).cloned())
+            .flatten();
+        let produced: Vec<String> = result
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                Content::ResourceLink { uri, .. } => resources::file_path(uri),
+                Content::Text { .. } => None,
+            })
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        let outcome = if result.is_error { "error" } else { "success" };
+        self.write(outcome, error, produced);
+    }
+
+    /// 実行前に拒否された呼び出し（未登録のツール・引数不正・終了処理中）を記録
+    pub fn reject(self, err: &anyhow::Error) {
+        self.write("rejected", Some(crate::error::error_details(err)), Vec::new());
+    }
+
+    fn write(self, outcome: &str, error: Option<Value>, produced_paths: Vec<String>) {
+        let mut entry = json!({
+            "timestamp": format_timestamp(self.timestamp),
+            "client": self.client,
+            "request_id": self.request_id,
+            "tool": self.tool,
+            "arguments": self.arguments,
+            "duration_ms": self.started.elapsed().as_millis() as u64,
+            "outcome": outcome,
+            "produced_paths": produced_paths,
+        });
+        if let Some(error) = error {
+            entry["error"] = json!({ "kind": error.get("kind"), "message": error.get("message") });
+        }
+        write(&entry);
+    }
+}
+
+/**
+ * 引数の秘密情報を置き換える
+ */
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let lower = key.to_lowercase();
+                    let value = if SECRET_KEYS.iter().any(|secret| lower.contains(secret)) {
+                        json!(REDACTED)
+                    } else {
+                        redact(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::String(s) if s.starts_with("sk-") => json!(REDACTED),
+        other => other.clone(),
+    }
+}
+
+/**
+ * 時刻を RFC 3339 形式（UTC、ミリ秒）にする
+ */
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, rest) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // 1970-01-01 からの日数を年月日に変換（グレゴリオ暦）
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let redacted = redact(&json!({
+            "path": "/tmp/a.png",
+            "api_key": "abc",
+            "options": { "accessToken": "xyz", "quality": 80 },
+            "headers": ["sk-live-123", "plain"],
+        }));
+        assert_eq!(redacted["path"], "/tmp/a.png");
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(redacted["options"]["accessToken"], REDACTED);
+        assert_eq!(redacted["options"]["quality"], 80);
+        assert_eq!(redacted["headers"], json!([REDACTED, "plain"]));
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339_utc() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn log_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-audit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("audit.jsonl");
+        let mut audit_log = AuditLog::open(AuditConfig {
+            path: path.clone(),
+            max_bytes: 25,
+            max_files: 2,
+        })
+        .unwrap();
+
+        for i in 0..4 {
+            audit_log.append(&format!("{{\"line\":{}}}", i)).unwrap();
+            audit_log.append(&format!("{{\"more\":{}}}", i)).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"line\":3}\n{\"more\":3}\n");
+        assert_eq!(std::fs::read_to_string(rotated_path(&path, 1)).unwrap(), "{\"line\":2}\n{\"more\":2}\n");
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
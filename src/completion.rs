@@ -0,0 +1,226 @@
+/**
+ * 引数の補完（completion/complete）
+ *
+ * 概要:
+ *   プロンプト引数・ツール引数の入力途中の値から候補を返す。
+ *   補完関数（Completer）は引数ごとに登録する
+ *   （ツールは ToolSpec::complete、プロンプトはテンプレート定義）。
+ *
+ * 主な仕様:
+ *   - ref/prompt: プロンプト引数（MCP標準）
+ *   - ref/tool: ツール引数（このサーバーの拡張、name は tools/list のどの形式でも可）
+ *   - ref/resource: 補完対象の引数がないため常に空
+ *   - 候補は最大100件（超えた分は total / hasMore で示す）
+ *   - 補完関数が登録されていない引数は空の候補を返す
+ *   - パスの候補は呼び出し元に許可されたルートの内側（とルートへ至る親ディレクトリ）に限る
+ *     （tools::roots 参照。ルートを取得できなければ候補なし）
+ */
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+use crate::tools::registry::input_schema_for;
+use crate::tools::roots;
+
+/// completion/complete で返す候補の上限（MCP仕様）
+pub const MAX_VALUES: usize = 100;
+
+/**
+ * 補完関数
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum Completer {
+    /// 入力途中の値から候補（上限で切り詰める前のすべて）を返す関数
+    Values(fn(&str) -> Vec<String>),
+    /// ファイルシステムのパス（許可されたルートで絞り込む）
+    Paths,
+}
+
+impl Completer {
+    /**
+     * 候補を返す
+     *
+     * 引数:
+     *   value: 入力途中の値
+     *
+     * 戻り値:
+     *   Vec<String> - 候補（上限で切り詰める前のすべて）
+     */
+    pub async fn complete(&self, value: &str) -> Vec<String> {
+        match self {
+            Completer::Values(complete) => complete(value),
+            Completer::Paths => roots::filter_reachable(paths(value)).await,
+        }
+    }
+}
+
+/**
+ * completion/complete の結果（completion フィールド）
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    /// 候補（最大100件）
+    pub values: Vec<String>,
+    /// 候補の総数
+    pub total: usize,
+    /// 切り詰めた候補があるかどうか
+    pub has_more: bool,
+}
+
+impl Completion {
+    /// 候補を上限で切り詰める
+    pub fn from_candidates(mut values: Vec<String>) -> Self {
+        let total = values.len();
+        values.truncate(MAX_VALUES);
+        Self {
+            has_more: total > values.len(),
+            values,
+            total,
+        }
+    }
+}
+
+/**
+ * 候補のうち入力値で始まるもの（大文字小文字を区別しない）
+ */
+pub fn matching(candidates: &[&str], value: &str) -> Vec<String> {
+    let value = value.to_lowercase();
+    candidates
+        .iter()
+        .filter(|c| c.to_lowercase().starts_with(&value))
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/**
+ * 列挙型のスキーマが許可する値を補完する
+ *
+ * 概要:
+ *   ExportFormat や AffinityApp のように serde の名前がそのまま引数値になる列挙型向け。
+ */
+pub fn schema_values<T: JsonSchema>(value: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    collect_enum_values(&input_schema_for::<T>(), &mut candidates);
+    let candidates: Vec<&str> = candidates.iter().map(String::as_str).collect();
+    matching(&candidates, value)
+}
+
+/// スキーマの列挙値（enum / oneOf / anyOf の各形式）を集める
+fn collect_enum_values(schema: &Value, values: &mut Vec<String>) {
+    if let Some(items) = schema.get("enum").and_then(|v| v.as_array()) {
+        values.extend(items.iter().filter_map(|v| v.as_str()).map(str::to_string));
+    }
+    for key in ["oneOf", "anyOf"] {
+        for variant in schema.get(key).and_then(|v| v.as_array()).into_iter().flatten() {
+            collect_enum_values(variant, values);
+        }
+    }
+}
+
+/**
+ * ファイルシステムのパスを補完する
+ *
+ * 概要:
+ *   入力値の最後の / までをディレクトリとして一覧し、残りで始まる項目を返す。
+ *   ディレクトリには末尾に / を付ける。ドットで始まる項目は入力がドットで始まる場合のみ返す。
+ *   読み取れないディレクトリは候補なしとして扱う。
+ */
+pub fn paths(value: &str) -> Vec<String> {
+    let (dir, partial) = match value.rfind('/') {
+        Some(i) => (&value[..=i], &value[i + 1..]),
+        None => return Vec::new(),
+    };
+    let Ok(entries) = std::fs::read_dir(Path::new(dir)) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(partial) || (name.starts_with('.') && !partial.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::affinity::{AffinityApp, ExportFormat};
+
+    #[test]
+    fn enum_values_are_completed_from_the_schema() {
+        assert_eq!(schema_values::<ExportFormat>("p"), vec!["pdf", "png"]);
+        assert_eq!(schema_values::<AffinityApp>("d"), vec!["Designer"]);
+        assert_eq!(schema_values::<AffinityApp>("").len(), 4);
+    }
+
+    #[test]
+    fn paths_are_completed_from_the_directory() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-completion-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("designs")).unwrap();
+        std::fs::write(dir.join("design.afdesign"), b"").unwrap();
+        std::fs::write(dir.join(".hidden"), b"").unwrap();
+        std::fs::write(dir.join("photo.afphoto"), b"").unwrap();
+
+        let prefix = format!("{}/", dir.display());
+        assert_eq!(
+            paths(&format!("{}des", prefix)),
+            vec![format!("{}design.afdesign", prefix), format!("{}designs/", prefix)]
+        );
+        assert_eq!(paths(&prefix).len(), 3);
+        assert_eq!(paths(&format!("{}.h", prefix)), vec![format!("{}.hidden", prefix)]);
+        assert!(paths("relative").is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn path_candidates_are_limited_to_the_callers_roots() {
+        use crate::mcp::ProtocolVersion;
+        use crate::session::Session;
+        use crate::tools::context::CallContext;
+        use serde_json::json;
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-completion-roots-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("allowed")).unwrap();
+        std::fs::create_dir_all(dir.join("other")).unwrap();
+        let session = Arc::new(Session::default());
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "roots": {} }));
+        session.set_roots(Some(vec![dir.join("allowed")]));
+        let context = Arc::new(CallContext::new("completion/complete", session, None));
+
+        // ルートの中とルートへ至る親ディレクトリだけを返す
+        let prefix = format!("{}/", dir.display());
+        let candidates = context.clone().scope(Completer::Paths.complete(&prefix)).await;
+        assert_eq!(candidates, vec![format!("{}allowed/", prefix)]);
+        assert!(context.scope(Completer::Paths.complete("/etc/")).await.is_empty());
+
+        // ルートを取得できなければ候補なし（送信先のないセッションでは roots/list が失敗する）
+        let session = Arc::new(Session::default());
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "roots": {} }));
+        let context = Arc::new(CallContext::new("completion/complete", session, None));
+        assert!(context.scope(Completer::Paths.complete(&prefix)).await.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn candidates_are_truncated_to_the_limit() {
+        let completion = Completion::from_candidates((0..150).map(|i| i.to_string()).collect());
+        assert_eq!(completion.values.len(), MAX_VALUES);
+        assert_eq!(completion.total, 150);
+        assert!(completion.has_more);
+    }
+}
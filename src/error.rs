@@ -0,0 +1,233 @@
+/**
+ * エラー型定義
+ *
+ * 概要:
+ *   ツール実行で発生する既知のエラーを型として区別し、
+ *   JSON-RPCエラーコードとクライアント向けの詳細情報に変換する。
+ *
+ * 主な仕様:
+ *   - ToolError は anyhow::Error のチェーンに埋め込んで伝搬する
+ *     （.context() で付与した説明はそのまま残る）
+ *   - classify() でチェーンから ToolError を取り出す
+ *   - JSON-RPCエラーの data にはエラー種別とコンテキストチェーンを格納する
+ *
+ * エラーコード:
+ *   -32602 InvalidParams / -32001 UnknownTool / -32002 ResourceNotFound（MCP仕様）
+ *   -32003 AppNotInstalled / -32004 NoOpenDocument / -32005 PermissionDenied
+ *   -32006 ScriptTimeout / -32007 UnsupportedPlatform / -32008 Cancelled / -32009 NotConfirmed
+ *   -32010 OutsideRoots / -32011 ShuttingDown / -32012 ToolTimeout
+ *   -32603 その他（内部エラー）
+ */
+use jsonrpc_core::{Error as JsonRpcError, ErrorCode};
+use serde_json::{json, Value};
+
+/**
+ * ツール実行エラー
+ */
+// AppleScript由来の種別はmacOS以外では生成されない
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+#[derive(Debug, thiserror::Error)]
+pub enum ToolError {
+    /// 引数が不正
+    #[error("引数が不正です: {0}")]
+    InvalidParams(String),
+    /// 未登録のツール
+    #[error("不明なツールです: {0}")]
+    UnknownTool(String),
+    /// 未知のリソース
+    #[error("リソースが見つかりません: {0}")]
+    ResourceNotFound(String),
+    /// 現在のOSでは利用できない
+    #[error("この操作は現在のプラットフォームでは利用できません: {0}")]
+    UnsupportedPlatform(String),
+    /// アプリケーションがインストールされていない
+    #[error("アプリケーションが見つかりません: {0}")]
+    AppNotInstalled(String),
+    /// 開いているドキュメントがない
+    #[error("開いているドキュメントがありません")]
+    NoOpenDocument,
+    /// オートメーション／アクセシビリティ権限がない
+    #[error("操作が許可されていません: {0}")]
+    PermissionDenied(String),
+    /// スクリプトがタイムアウトした
+    #[error("スクリプトがタイムアウトしました: {0}")]
+    ScriptTimeout(String),
+    /// クライアントによりキャンセルされた
+    #[error("キャンセルされました: {0}")]
+    Cancelled(String),
+    /// 破壊的な操作がユーザーに承認されなかった
+    #[error("操作が確認されなかったため中止しました: {0}")]
+    NotConfirmed(String),
+    /// 許可されたルートの外のパス
+    #[error("許可されたルートの外のパスです: {0}")]
+    OutsideRoots(String),
+    /// サーバーが終了処理中
+    #[error("サーバーを終了しています")]
+    ShuttingDown,
+    /// ツールの実行時間が上限を超えた
+    #[error("{tool} がタイムアウトしました（{elapsed_ms}ミリ秒経過）")]
+    ToolTimeout { tool: String, elapsed_ms: u64 },
+}
+
+impl ToolError {
+    /// JSON-RPCエラーコード
+    pub fn code(&self) -> i64 {
+        match self {
+            ToolError::InvalidParams(_) => -32602,
+            ToolError::UnknownTool(_) => -32001,
+            ToolError::ResourceNotFound(_) => -32002,
+            ToolError::UnsupportedPlatform(_) => -32007,
+            ToolError::AppNotInstalled(_) => -32003,
+            ToolError::NoOpenDocument => -32004,
+            ToolError::PermissionDenied(_) => -32005,
+            ToolError::ScriptTimeout(_) => -32006,
+            ToolError::Cancelled(_) => -32008,
+            ToolError::NotConfirmed(_) => -32009,
+            ToolError::OutsideRoots(_) => -32010,
+            ToolError::ShuttingDown => -32011,
+            ToolError::ToolTimeout { .. } => -32012,
+        }
+    }
+
+    /// 機械可読なエラー種別
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ToolError::InvalidParams(_) => "invalid_params",
+            ToolError::UnknownTool(_) => "unknown_tool",
+            ToolError::ResourceNotFound(_) => "resource_not_found",
+            ToolError::UnsupportedPlatform(_) => "unsupported_platform",
+            ToolError::AppNotInstalled(_) => "app_not_installed",
+            ToolError::NoOpenDocument => "no_open_document",
+            ToolError::PermissionDenied(_) => "permission_denied",
+            ToolError::ScriptTimeout(_) => "script_timeout",
+            ToolError::Cancelled(_) => "cancelled",
+            ToolError::NotConfirmed(_) => "not_confirmed",
+            ToolError::OutsideRoots(_) => "outside_roots",
+            ToolError::ShuttingDown => "shutting_down",
+            ToolError::ToolTimeout { .. } => "tool_timeout",
+        }
+    }
+
+    /**
+     * osascript の stderr からエラー種別を判定
+     *
+     * 引数:
+     *   script: 実行したスクリプト（アプリ名の抽出に使用）
+     *   stderr: osascript の標準エラー出力
+     *
+     * 戻り値:
+     *   Option<ToolError> - 既知のエラーであれば Some
+     */
+    pub fn from_applescript_stderr(script: &str, stderr: &str) -> Option<ToolError> {
+        if stderr.contains("開いているドキュメントがありません") {
+            return Some(ToolError::NoOpenDocument);
+        }
+        // -1743: Apple Events 送信権限なし / -1719: アクセシビリティ権限なし
+        if stderr.contains("-1743") || stderr.contains("-1719") || stderr.contains("Not authorized") {
+            return Some(ToolError::PermissionDenied(stderr.trim().to_string()));
+        }
+        // -1712: AppleEvent タイムアウト
+        if stderr.contains("-1712") {
+            return Some(ToolError::ScriptTimeout(stderr.trim().to_string()));
+        }
+        // -10814: アプリケーションが見つからない
+        if stderr.contains("-10814") || stderr.contains("Can’t get application") || stderr.contains("Can't get application") {
+            let app = script
+                .split("tell application \"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .unwrap_or("Affinity");
+            return Some(ToolError::AppNotInstalled(app.to_string()));
+        }
+        None
+    }
+}
+
+/**
+ * エラーチェーンから ToolError を取り出す
+ */
+pub fn classify(err: &anyhow::Error) -> Option<&ToolError> {
+    err.chain().find_map(|e| e.downcast_ref::<ToolError>())
+}
+
+/**
+ * エラーの詳細情報（JSON-RPCエラーの data / isError 結果に格納）
+ *
+ * 戻り値:
+ *   Value - { code, kind, message, chain }
+ */
+pub fn error_details(err: &anyhow::Error) -> Value {
+    let (code, kind) = match classify(err) {
+        Some(tool_error) => (tool_error.code(), tool_error.kind()),
+        None => (ErrorCode::InternalError.code(), "internal_error"),
+    };
+    let chain: Vec<String> = err.chain().map(|e| e.to_string()).collect();
+    json!({
+        "code": code,
+        "kind": kind,
+        "message": err.to_string(),
+        "chain": chain,
+    })
+}
+
+/**
+ * anyhow::Error を JSON-RPCエラーに変換
+ *
+ * 概要:
+ *   ToolError を含む場合はその種別のコード、それ以外は内部エラーとし、
+ *   コンテキストチェーンを data に格納する。
+ */
+pub fn to_json_rpc_error(err: &anyhow::Error) -> JsonRpcError {
+    let code = classify(err)
+        .map(|tool_error| ErrorCode::from(tool_error.code()))
+        .unwrap_or(ErrorCode::InternalError);
+    JsonRpcError {
+        code,
+        message: format!("{:#}", err),
+        data: Some(error_details(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn applescript_stderr_is_classified() {
+        let script = r#"tell application "Affinity Designer" to activate"#;
+        assert!(matches!(
+            ToolError::from_applescript_stderr(script, "execution error: 開いているドキュメントがありません (-2700)"),
+            Some(ToolError::NoOpenDocument)
+        ));
+        assert!(matches!(
+            ToolError::from_applescript_stderr(script, "execution error: Not authorized to send Apple events to System Events. (-1743)"),
+            Some(ToolError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            ToolError::from_applescript_stderr(script, "execution error: AppleEvent timed out. (-1712)"),
+            Some(ToolError::ScriptTimeout(_))
+        ));
+        match ToolError::from_applescript_stderr(script, "execution error: Can’t get application \"Affinity Designer\". (-10814)") {
+            Some(ToolError::AppNotInstalled(app)) => assert_eq!(app, "Affinity Designer"),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(ToolError::from_applescript_stderr(script, "execution error: something else (-1)").is_none());
+    }
+
+    #[test]
+    fn json_rpc_error_keeps_code_and_context_chain() {
+        let err = Err::<(), _>(ToolError::NoOpenDocument)
+            .context("エクスポートに失敗しました: /tmp/out.png")
+            .unwrap_err();
+        let rpc = to_json_rpc_error(&err);
+        assert_eq!(rpc.code.code(), -32004);
+        let data = rpc.data.unwrap();
+        assert_eq!(data["kind"], "no_open_document");
+        assert_eq!(data["chain"][0], "エクスポートに失敗しました: /tmp/out.png");
+        assert_eq!(data["chain"][1], "開いているドキュメントがありません");
+
+        let internal = to_json_rpc_error(&anyhow::anyhow!("unexpected"));
+        assert_eq!(internal.code, ErrorCode::InternalError);
+    }
+}
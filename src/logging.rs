@@ -0,0 +1,350 @@
+/**
+ * MCPロギング（tracing → notifications/message）
+ *
+ * 概要:
+ *   tracing のイベントを MCP の notifications/message としてクライアントへ転送する
+ *   tracing-subscriber のレイヤー。stderr への出力（fmt レイヤー）とは独立して動作する。
+ *
+ * 主な仕様:
+ *   - クライアントごとの転送レベルは logging/setLevel で設定する（既定: warning）
+ *   - リクエストの処理中（ツール実行の前後を含む）のイベントは呼び出し元のセッションにだけ送り、
+ *     リクエストに由来しないイベント（監視・終了処理など）は初期化済みのすべてのセッションに送る
+ *   - tracing の ERROR/WARN/INFO/DEBUG/TRACE は error/warning/info/debug/debug に対応付ける
+ *   - 転送するのはこのクレート内のイベントのみ
+ *
+ * 制限事項:
+ *   - notice/critical/alert/emergency に対応する tracing のレベルはないため送信されない
+ *     （setLevel の閾値としては指定できる）
+ */
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::session::Session;
+use crate::tools::context::CallContext;
+
+/**
+ * MCPログレベル（RFC 5424 の重大度、弱い順）
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LogLevel {
+    /// setLevel 前の既定レベル
+    pub const DEFAULT: LogLevel = LogLevel::Warning;
+
+    fn from_tracing(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warning,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG | Level::TRACE => LogLevel::Debug,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Debug,
+            1 => LogLevel::Info,
+            2 => LogLevel::Notice,
+            3 => LogLevel::Warning,
+            4 => LogLevel::Error,
+            5 => LogLevel::Critical,
+            6 => LogLevel::Alert,
+            _ => LogLevel::Emergency,
+        }
+    }
+}
+
+tokio::task_local! {
+    /// 処理中のリクエストの送信元セッション
+    static ORIGIN: Arc<Session>;
+}
+
+/**
+ * リクエストの処理中に発生したイベントの送信先をそのセッションに限定する
+ *
+ * 概要:
+ *   mcp.rs の RequestOrigin ミドルウェアがリクエストごとに future を包んで呼ぶ。
+ */
+pub async fn with_origin<F: std::future::Future>(session: Arc<Session>, future: F) -> F::Output {
+    ORIGIN.scope(session, future).await
+}
+
+/// 転送対象のセッション
+fn sessions() -> &'static Mutex<Vec<Weak<Session>>> {
+    static SESSIONS: OnceLock<Mutex<Vec<Weak<Session>>>> = OnceLock::new();
+    SESSIONS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// すべてのセッションのうち最も低い転送レベル（イベントの事前フィルタ用）
+static THRESHOLD: AtomicU8 = AtomicU8::new(LogLevel::DEFAULT as u8);
+
+/**
+ * セッションをログ転送の対象に追加
+ *
+ * 概要:
+ *   initialize 完了時に呼ぶ。切断されたセッションは自動的に除外される。
+ */
+pub fn register(session: &Arc<Session>) {
+    let mut sessions = sessions().lock().unwrap_or_else(|e| e.into_inner());
+    if !sessions.iter().any(|s| std::ptr::eq(s.as_ptr(), Arc::as_ptr(session))) {
+        sessions.push(Arc::downgrade(session));
+    }
+    drop(sessions);
+    update_threshold();
+}
+
+/**
+ * セッションをログ転送の対象から外す
+ *
+ * 概要:
+ *   セッションの終了時（HTTP の DELETE・期限切れ、stdio の EOF）に呼び、閾値を再計算する。
+ */
+pub fn unregister(session: &Arc<Session>) {
+    sessions()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|s| !std::ptr::eq(s.as_ptr(), Arc::as_ptr(session)));
+    update_threshold();
+}
+
+/**
+ * セッションの転送レベルを設定（logging/setLevel）
+ */
+pub fn set_level(session: &Arc<Session>, level: LogLevel) {
+    session.set_log_level(level);
+    register(session);
+}
+
+/// 破棄されたセッションを除外して、事前フィルタの閾値を再計算
+fn update_threshold() {
+    let mut sessions = sessions().lock().unwrap_or_else(|e| e.into_inner());
+    sessions.retain(|s| s.strong_count() > 0);
+    let threshold = sessions
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|s| s.log_level())
+        .min()
+        .unwrap_or(LogLevel::DEFAULT);
+    THRESHOLD.store(threshold as u8, Ordering::Relaxed);
+}
+
+/**
+ * いずれかのクライアントへ転送され得るイベントかどうか
+ *
+ * 概要:
+ *   このクレートのイベントだけを対象にする（依存クレートの内部ログは stderr のみ）。
+ *   stderr 側のフィルタには影響しない。
+ */
+fn is_forwarded(metadata: &Metadata<'_>) -> bool {
+    metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+        && LogLevel::from_tracing(metadata.level()) >= LogLevel::from_u8(THRESHOLD.load(Ordering::Relaxed))
+}
+
+/**
+ * クライアント転送レイヤーを作成
+ *
+ * 概要:
+ *   閾値は setLevel で変わるため、コールサイトごとにキャッシュしない動的フィルタを付ける。
+ */
+pub fn client_layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    ClientLogLayer.with_filter(filter::dynamic_filter_fn(|metadata, _| is_forwarded(metadata)))
+}
+
+/**
+ * tracing イベントをクライアントへ転送するレイヤー
+ */
+struct ClientLogLayer;
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = LogLevel::from_tracing(metadata.level());
+
+        let accepts = |session: &Session| session.protocol_version().is_some() && level >= session.log_level();
+        let message = || {
+            let mut visitor = FieldVisitor::default();
+            event.record(&mut visitor);
+            json!({
+                "level": level,
+                "logger": metadata.target(),
+                "data": Value::Object(visitor.fields),
+            })
+        };
+
+        // ツール実行中のイベントは呼び出し元にだけ送る
+        if let Some(context) = CallContext::current() {
+            if accepts(&context.session) {
+                context.notify("notifications/message", message());
+            }
+            return;
+        }
+        // ツール実行の外でもリクエストの処理中なら送信元にだけ送る
+        if let Ok(origin) = ORIGIN.try_with(Arc::clone) {
+            if accepts(&origin) {
+                origin.notify("notifications/message", message());
+            }
+            return;
+        }
+
+        let (live, registered) = {
+            let sessions = sessions().lock().unwrap_or_else(|e| e.into_inner());
+            let live: Vec<Arc<Session>> = sessions.iter().filter_map(Weak::upgrade).collect();
+            (live, sessions.len())
+        };
+        // 終了処理を経ずに破棄されたセッションがあれば、閾値をそのセッションの設定から戻す
+        if live.len() < registered {
+            update_threshold();
+        }
+        let targets: Vec<Arc<Session>> = live.into_iter().filter(|s| accepts(s)).collect();
+        if targets.is_empty() {
+            return;
+        }
+        let params = message();
+        for session in targets {
+            session.notify("notifications/message", params.clone());
+        }
+    }
+}
+
+/**
+ * イベントのフィールドをJSONオブジェクトに集める
+ */
+#[derive(Default)]
+struct FieldVisitor {
+    fields: Map<String, Value>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::ProtocolVersion;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn events_are_forwarded_at_the_requested_level() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let session = Arc::new(Session::with_outbound(tx));
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        set_level(&session, LogLevel::Info);
+
+        let subscriber = tracing_subscriber::registry().with(client_layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("not forwarded");
+            tracing::info!(path = "/tmp/a.png", "exported");
+        });
+
+        let message: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/message");
+        assert_eq!(message["params"]["level"], "info");
+        assert_eq!(message["params"]["data"]["message"], "exported");
+        assert_eq!(message["params"]["data"]["path"], "/tmp/a.png");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn threshold_follows_sessions_that_end() {
+        let threshold = || LogLevel::from_u8(THRESHOLD.load(Ordering::Relaxed));
+        let verbose = || {
+            let session = Arc::new(Session::default());
+            session.initialize(ProtocolVersion::V2025_06_18, None);
+            set_level(&session, LogLevel::Debug);
+            session
+        };
+
+        // 明示的に外したセッション
+        let session = verbose();
+        assert_eq!(threshold(), LogLevel::Debug);
+        unregister(&session);
+        assert!(threshold() > LogLevel::Debug);
+
+        // 外されないまま破棄されたセッションは、次のイベントで除外される
+        drop(verbose());
+        assert_eq!(threshold(), LogLevel::Debug);
+        let subscriber = tracing_subscriber::registry().with(client_layer());
+        tracing::subscriber::with_default(subscriber, || tracing::debug!("after drop"));
+        assert!(threshold() > LogLevel::Debug);
+    }
+
+    #[tokio::test]
+    async fn request_events_stay_in_their_session() {
+        use crate::tools::backend::{MockBackend, ScriptOutput};
+
+        let server = crate::mcp::test_server(
+            MockBackend::default().then(ScriptOutput::failed("execution error: 開いているドキュメントがありません (-2700)")),
+        )
+        .await;
+        let (caller_tx, mut caller_rx) = mpsc::unbounded_channel();
+        let (other_tx, mut other_rx) = mpsc::unbounded_channel();
+        let caller = server.session(Some(caller_tx));
+        let other = server.session(Some(other_tx));
+        for session in [&caller, &other] {
+            register(session);
+        }
+
+        let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(client_layer()));
+        server
+            .call(&caller, json!(1), json!({ "name": "affinity.get_active_document", "arguments": { "app": "Photo" } }))
+            .await;
+
+        // ツールの失敗のログは呼び出し元にだけ届く
+        let logged: Vec<Value> = std::iter::from_fn(|| caller_rx.try_recv().ok())
+            .map(|m| serde_json::from_str(&m).unwrap())
+            .collect();
+        assert!(logged.iter().any(|m| m["params"]["data"]["tool_name"] == "affinity.get_active_document"));
+        assert!(other_rx.try_recv().is_err());
+
+        // リクエストに由来しないイベントはすべてのセッションに届く
+        tracing::warn!("watcher stopped");
+        assert!(caller_rx.try_recv().is_ok());
+        assert!(other_rx.try_recv().is_ok());
+    }
+}
@@ -2,26 +2,48 @@
  * AffinityMCP メインエントリーポイント
  * 
  * 概要:
- *   RustベースのMCPサーバー。STDIO経由でJSON-RPC通信を行い、
- *   Canva連携ツールとAffinityブリッジを提供する。
+ *   RustベースのMCPサーバー。STDIO（既定）、Streamable HTTP、Unixソケットのいずれかで
+ *   JSON-RPC通信を行い、Canva連携ツールとAffinityブリッジを提供する。
  * 
  * 主な仕様:
  *   - STDIO経由でJSON-RPCリクエスト/レスポンスを処理
  *   - 環境変数 MCP_NAME でサーバー名を設定可能（デフォルト: affinity-mcp）
- *   - stderr にログを出力（tracing-subscriber）
- *   - MCPプロトコル（initialize、tools/list、tools/call）を実装
+ *   - --transport http|unix / AFFINITY_MCP_TRANSPORT でトランスポートを選択（transport モジュール参照）
+ *   - stderr にログを出力（tracing-subscriber）し、MCPクライアントへも notifications/message で転送
+ *   - SIGINT / SIGTERM / stdio の EOF で実行中のツール呼び出しを期限まで待ってから終了（shutdown モジュール参照）
+ *   - AFFINITY_MCP_AUDIT_LOG を設定すると tools/call を JSONL の監査ログに記録（audit モジュール参照）
+ *   - MCPプロトコル（initialize、tools/list、tools/call、resources/list、resources/read、
+ *     resources/subscribe、prompts/list、prompts/get、completion/complete、logging/setLevel）を実装
  * 
  * エラー処理:
  *   - 詳細なエラーメッセージを出力
  *   - 関数名、引数、パラメータを含む
  */
+use jsonrpc_core::MetaIoHandler;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 use anyhow::Context;
 use std::io::IsTerminal;
 
+mod audit;
+mod completion;
+mod error;
+mod logging;
 mod mcp;
+mod prompts;
+mod resources;
+mod session;
+mod shutdown;
 mod tools;
+mod transport;
+mod watcher;
+
+use tools::registry::ToolNaming;
+use transport::TransportKind;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -37,32 +59,144 @@ async fn main() -> anyhow::Result<()> {
                    env::var("NO_COLOR").is_err() &&
                    std::io::stderr().is_terminal();
     
-    tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
-        .with_max_level(log_level)
-        .with_ansi(use_ansi)
-        .with_target(false)
-        .compact()
+    // stderr への出力に加えて、logging/setLevel を指定したクライアントへも転送する
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(use_ansi)
+                .with_target(false)
+                .compact()
+                .with_filter(LevelFilter::from_level(log_level)),
+        )
+        .with(logging::client_layer())
         .init();
 
-    tracing::debug!(server = %name, "Starting AffinityMCP server (STDIO).");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let transport_kind = TransportKind::from_args_and_env(&args)
+        .context("トランスポートの設定が不正です")?;
+
+    tracing::debug!(server = %name, transport = ?transport_kind, "Starting AffinityMCP server.");
+
+    // 監査ログ（AFFINITY_MCP_AUDIT_LOG を設定した場合のみ）
+    if audit::init_from_env().context("監査ログを開けません")? {
+        tracing::debug!("Audit log enabled.");
+    }
+
+    // AppleScript の実行方法（osascript / JXA 経由、カセットでの記録・再生）
+    let backend = tools::backend::from_env()
+        .context("スクリプト実行バックエンドの設定が不正です")?;
+    tracing::debug!(backend = backend.name(), "Script backend selected.");
 
     // ツール初期化
-    tools::register_all().await?;
+    let naming = ToolNaming::from_env(&name)
+        .context("ツール名の形式の設定が不正です")?;
+    let registry = tools::register_all(backend.clone()).await
+        .context("ツールの登録に失敗しました")?
+        .with_naming(naming)
+        .with_timeouts_from_env()
+        .context("ツールのタイムアウトの設定が不正です")?;
+    tracing::debug!(tool_count = registry.len(), "Tools registered.");
 
     // MCPサーバー構築
-    let io = mcp::build_server(name.clone())
+    let io = mcp::build_server(name.clone(), Arc::new(registry), backend)
         .context("MCPサーバーの構築に失敗しました")?;
 
-    // STDIOサーバー起動
-    tracing::debug!(server = %name, "MCP server ready. Listening for JSON-RPC requests on STDIO.");
-    
-    let server = jsonrpc_stdio_server::ServerBuilder::new(io)
-        .build();
+    // トランスポート起動（CLI引数 --transport または AFFINITY_MCP_TRANSPORT で選択）
+    let io = Arc::new(io);
+    let mut server = tokio::spawn(serve(io, transport_kind, name.clone()));
+
+    // 終了シグナル・stdio の EOF・トランスポートの異常終了のいずれかで終了処理に入る
+    let (finished, by_signal) = tokio::select! {
+        result = &mut server => (Some(result), false),
+        signal = shutdown::signal() => {
+            tracing::info!(signal, "終了シグナルを受信しました");
+            (None, true)
+        }
+        _ = shutdown::requested() => (None, false),
+    };
+    shutdown::request();
+
+    // シグナルで終了処理に入った場合、2回目のシグナルでは待機せずに終了する
+    if by_signal {
+        tokio::spawn(async {
+            let signal = shutdown::signal().await;
+            tracing::warn!(signal, "終了処理中にシグナルを受信したため強制終了します");
+            audit::flush();
+            flush_outputs();
+            std::process::exit(130);
+        });
+    }
+
+    shutdown::drain(shutdown::timeout_from_env()).await;
+
+    // 実行中だった呼び出しの応答をトランスポートが書き出すのを待つ
+    let finished = match finished {
+        Some(result) => Some(result),
+        None => tokio::time::timeout(SERVER_STOP_GRACE, &mut server).await.ok(),
+    };
 
-    server.await;
+    match finished {
+        Some(result) => result.context("サーバータスクが異常終了しました")??,
+        None => tracing::debug!("トランスポートの停止を待たずに終了します"),
+    }
 
     tracing::debug!("MCP server shutting down.");
+    audit::flush();
+    flush_outputs();
+    // 標準入力の読み取りはブロッキングスレッドで行われ、ランタイムの破棄が入力を待ち続けるため、
+    // 後始末が済んだらここでプロセスを終了する
+    std::process::exit(0)
+}
+
+/// 終了処理でトランスポートの停止を待つ猶予
+const SERVER_STOP_GRACE: Duration = Duration::from_secs(2);
+
+/**
+ * 選択したトランスポートでサーバーを実行
+ *
+ * 引数:
+ *   io: JSON-RPCハンドラー
+ *   transport_kind: トランスポートの種類
+ *   name: サーバー名（ログ用）
+ *
+ * エラー:
+ *   トランスポートの実行に失敗した場合はエラーを返す
+ */
+async fn serve(
+    io: Arc<MetaIoHandler<mcp::RequestContext, mcp::RequestOrigin>>,
+    transport_kind: TransportKind,
+    name: String,
+) -> anyhow::Result<()> {
+    match transport_kind {
+        TransportKind::Stdio => {
+            tracing::debug!(server = %name, "MCP server ready. Listening for JSON-RPC requests on STDIO.");
+            transport::stdio::serve(io).await
+                .context("STDIOサーバーの実行に失敗しました")?;
+        }
+        TransportKind::Http(addr) => {
+            tracing::debug!(server = %name, addr = %addr, "MCP server ready. Listening for Streamable HTTP requests.");
+            transport::http::serve(io, addr).await
+                .context("HTTPサーバーの実行に失敗しました")?;
+        }
+        #[cfg(unix)]
+        TransportKind::Unix(path) => {
+            tracing::debug!(server = %name, path = %path.display(), "MCP server ready. Listening for JSON-RPC requests on Unix socket.");
+            transport::unix::serve(io, &path).await
+                .context("Unixソケットサーバーの実行に失敗しました")?;
+        }
+        #[cfg(not(unix))]
+        TransportKind::Unix(_) => anyhow::bail!("Unixソケットはこのプラットフォームでは利用できません"),
+    }
     Ok(())
 }
 
+/**
+ * 標準出力・標準エラー出力をフラッシュ
+ */
+fn flush_outputs() {
+    use std::io::Write;
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
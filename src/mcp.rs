@@ -3,7 +3,9 @@
  * 
  * 概要:
  *   JSON-RPC 2.0ベースのMCPプロトコルを実装し、
- *   initialize、tools/list、tools/callなどのメソッドを提供する。
+ *   initialize、tools/list、tools/call、resources/list、resources/read、
+ *   resources/subscribe、prompts/list、prompts/get、completion/complete、logging/setLevel などの
+ *   メソッドを提供する。
  * 
  * 主な仕様:
  *   - STDIO経由でJSON-RPCリクエスト/レスポンスを処理
@@ -13,26 +15,209 @@
  * 制限事項:
  *   - 現在は基本的なMCPメソッドのみ実装
  */
-use anyhow::{Context, Result};
-use jsonrpc_core::{IoHandler, Params, Value, Error as JsonRpcError};
+use anyhow::Result;
+use futures::future::{BoxFuture, Either, FutureExt};
+use jsonrpc_core::middleware::Middleware;
+use jsonrpc_core::{MetaIoHandler, Metadata, Params, Value, Error as JsonRpcError};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::error;
 
-use crate::tools::{affinity, canva};
+use crate::audit;
+use crate::completion::Completion;
+use crate::error::{self, ToolError};
+use crate::logging::{self, LogLevel};
+use crate::prompts;
+use crate::resources;
+use crate::session::Session;
+use crate::shutdown;
+use crate::tools::backend::ScriptBackend;
+use crate::tools::context::CallContext;
+use crate::tools::registry::ToolRegistry;
+use crate::watcher::ResourceWatcher;
 
 /**
- * MCP Initialize リクエスト
+ * リクエストコンテキスト（JSON-RPCメタデータ）
+ * 
+ * 概要:
+ *   トランスポートがリクエストごとに作成し、各メソッドに渡す。
+ */
+#[derive(Clone)]
+pub struct RequestContext {
+    /// リクエスト元のセッション
+    pub session: Arc<Session>,
+    /// JSON-RPCリクエストID（通知・バッチの場合は None）
+    pub request_id: Option<Value>,
+    /// このリクエストに関連する通知の送信先（HTTPのSSE応答など）。
+    /// None ならセッションの送信キューを使う
+    pub stream: Option<UnboundedSender<String>>,
+}
+
+impl Metadata for RequestContext {}
+
+impl RequestContext {
+    /**
+     * 受信メッセージからコンテキストを作成
+     *
+     * 引数:
+     *   session: 受信したセッション
+     *   message: 受信したJSON-RPCメッセージ（リクエストIDの取得に使用）
+     */
+    pub fn for_message(session: Arc<Session>, message: &str) -> Self {
+        let request_id = serde_json::from_str::<Value>(message)
+            .ok()
+            .and_then(|m| m.get("id").cloned())
+            .filter(|id| !id.is_null());
+        Self {
+            session,
+            request_id,
+            stream: None,
+        }
+    }
+
+    /// リクエストに関連する通知の送信先を設定
+    pub fn with_stream(mut self, stream: UnboundedSender<String>) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+}
+
+/**
+ * リクエストの送信元をログの送信先にするミドルウェア
+ *
+ * 概要:
+ *   リクエストの処理全体を logging::with_origin で包み、処理中の tracing イベントが
+ *   ほかのセッションへ notifications/message として送られないようにする。
+ */
+#[derive(Debug, Default)]
+pub struct RequestOrigin;
+
+impl Middleware<RequestContext> for RequestOrigin {
+    type Future = BoxFuture<'static, Option<jsonrpc_core::Response>>;
+    type CallFuture = jsonrpc_core::middleware::NoopCallFuture;
+
+    fn on_request<F, X>(&self, request: jsonrpc_core::Request, context: RequestContext, next: F) -> Either<Self::Future, X>
+    where
+        F: Fn(jsonrpc_core::Request, RequestContext) -> X + Send + Sync,
+        X: std::future::Future<Output = Option<jsonrpc_core::Response>> + Send + 'static,
+    {
+        let session = context.session.clone();
+        Either::Left(logging::with_origin(session, next(request, context)).boxed())
+    }
+}
+
+/**
+ * MCPプロトコルバージョン
+ * 
+ * 概要:
+ *   対応するバージョンを古い順に定義する（比較演算子で新旧を判定できる）。
+ *   バージョンごとの機能差は supports_* で判定する。
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    /// 2024-11-05（初版）
+    V2024_11_05,
+    /// 2025-03-26（ツールアノテーション）
+    V2025_03_26,
+    /// 2025-06-18（構造化出力・リソースリンク）
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    /// 対応バージョン（新しい順）
+    pub const SUPPORTED: [ProtocolVersion; 3] = [
+        ProtocolVersion::V2025_06_18,
+        ProtocolVersion::V2025_03_26,
+        ProtocolVersion::V2024_11_05,
+    ];
+    pub const OLDEST: ProtocolVersion = ProtocolVersion::V2024_11_05;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolVersion::V2024_11_05 => "2024-11-05",
+            ProtocolVersion::V2025_03_26 => "2025-03-26",
+            ProtocolVersion::V2025_06_18 => "2025-06-18",
+        }
+    }
+
+    /**
+     * クライアントの要求バージョンからネゴシエーション
+     * 
+     * 概要:
+     *   - 対応バージョンと一致すればそのバージョン
+     *   - 未知のバージョンなら、それ以前で最新の対応バージョン
+     *     （クライアントより新しいバージョンは返さない）
+     *   - 最も古い対応バージョンより古い、または日付形式でない場合はエラー
+     * 
+     * 戻り値:
+     *   Result<ProtocolVersion, JsonRpcError> - 合意したバージョン
+     */
+    pub fn negotiate(requested: &str) -> Result<ProtocolVersion, JsonRpcError> {
+        // バージョンは YYYY-MM-DD 形式のため文字列比較で新旧を判定できる
+        let is_date = requested.len() == 10
+            && requested.chars().enumerate().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+        let negotiated = if is_date {
+            Self::SUPPORTED.into_iter().find(|v| v.as_str() <= requested)
+        } else {
+            None
+        };
+        negotiated.ok_or_else(|| {
+            let supported: Vec<&str> = Self::SUPPORTED.iter().map(|v| v.as_str()).collect();
+            JsonRpcError {
+                code: jsonrpc_core::ErrorCode::InvalidParams,
+                message: format!(
+                    "Unsupported protocol version: {}（対応バージョン: {}）",
+                    requested,
+                    supported.join(", ")
+                ),
+                data: Some(json!({ "supported": supported, "requested": requested })),
+            }
+        })
+    }
+
+    /// ツールアノテーション（2025-03-26〜）
+    pub fn supports_tool_annotations(&self) -> bool {
+        *self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// notifications/progress の message フィールド（2025-03-26〜）
+    pub fn supports_progress_message(&self) -> bool {
+        *self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// 構造化出力 structuredContent（2025-06-18〜）
+    pub fn supports_structured_content(&self) -> bool {
+        *self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// resource_link コンテンツ（2025-06-18〜）
+    pub fn supports_resource_links(&self) -> bool {
+        *self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// elicitation/create（2025-06-18〜）
+    pub fn supports_elicitation(&self) -> bool {
+        *self >= ProtocolVersion::V2025_06_18
+    }
+}
+
+/**
+ * MCP Initialize リクエスト（camelCase/snake_case両対応）
  */
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InitializeParams {
     /// プロトコルバージョン
+    #[serde(alias = "protocol_version")]
     pub protocol_version: String,
+    /// クライアントの機能
+    #[serde(default)]
+    pub capabilities: Value,
     /// クライアント情報
-    #[allow(dead_code)]
-    pub capabilities: Option<Value>,
-    /// クライアント情報
+    #[serde(default, alias = "client_info")]
     pub client_info: Option<ClientInfo>,
 }
 
@@ -43,9 +228,10 @@ pub struct InitializeParams {
 #[serde(rename_all = "camelCase")]
 pub struct ClientInfo {
     /// クライアント名
-    pub name: String,
+    #[serde(default)]
+    pub name: Option<String>,
     /// クライアントバージョン
-    #[allow(dead_code)]
+    #[serde(default)]
     pub version: Option<String>,
 }
 
@@ -83,6 +269,48 @@ pub struct ServerInfo {
 pub struct ServerCapabilities {
     /// ツール機能
     pub tools: ToolsCapability,
+    /// リソース機能
+    pub resources: ResourcesCapability,
+    /// プロンプト機能
+    pub prompts: PromptsCapability,
+    /// ロギング機能（notifications/message、logging/setLevel）
+    pub logging: LoggingCapability,
+    /// 引数補完機能（completion/complete）
+    pub completions: CompletionsCapability,
+}
+
+/**
+ * ロギング機能（設定項目なし）
+ */
+#[derive(Debug, Serialize)]
+pub struct LoggingCapability {}
+
+/**
+ * 引数補完機能（設定項目なし）
+ */
+#[derive(Debug, Serialize)]
+pub struct CompletionsCapability {}
+
+/**
+ * プロンプト機能
+ */
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptsCapability {
+    /// リスト変更通知
+    pub list_changed: bool,
+}
+
+/**
+ * リソース機能
+ */
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcesCapability {
+    /// resources/subscribe 対応
+    pub subscribe: bool,
+    /// リスト変更通知
+    pub list_changed: bool,
 }
 
 /**
@@ -98,7 +326,8 @@ pub struct ToolsCapability {
 /**
  * MCP Tool定義
  */
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Tool {
     /// ツール名
     pub name: String,
@@ -106,6 +335,193 @@ pub struct Tool {
     pub description: String,
     /// 入力スキーマ
     pub input_schema: Value,
+    /// ツールアノテーション
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/**
+ * MCP Toolアノテーション
+ * 
+ * 概要:
+ *   クライアントが自動承認や確認ダイアログの判断に使うヒント。
+ *   未設定の項目は出力しない。
+ */
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// 表示用タイトル
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// 環境を変更しない
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    /// 破壊的な変更を行う可能性がある
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    /// 同じ引数での繰り返し呼び出しが追加の影響を持たない
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    /// 外部エンティティとやり取りする
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /**
+     * 環境を変更しないツール
+     *
+     * 引数:
+     *   title: 表示用タイトル
+     */
+    pub fn read_only(title: &str) -> Self {
+        Self {
+            title: Some(title.to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        }
+    }
+
+    /**
+     * 環境を変更するツール（ローカルのアプリ・ファイルのみを操作）
+     *
+     * 引数:
+     *   title: 表示用タイトル
+     *   destructive: 既存の内容を上書き・削除・破棄する可能性があるか
+     *   idempotent: 同じ引数で繰り返し呼んでも追加の影響がないか
+     */
+    pub fn mutating(title: &str, destructive: bool, idempotent: bool) -> Self {
+        Self {
+            title: Some(title.to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(destructive),
+            idempotent_hint: Some(idempotent),
+            open_world_hint: Some(false),
+        }
+    }
+
+    /// 外部サービスとやり取りするツールとしてマークする
+    pub fn open_world(mut self) -> Self {
+        self.open_world_hint = Some(true);
+        self
+    }
+}
+
+/**
+ * MCP Tool Call 結果
+ * 
+ * 概要:
+ *   content にはクライアントがそのまま表示できるテキスト要約、
+ *   structuredContent にはツールの結果構造体を格納する。
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    /// 表示用コンテンツ
+    pub content: Vec<Content>,
+    /// 構造化された結果（JSONオブジェクト）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    /// ツール実行が失敗したかどうか
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    /**
+     * 成功結果を作成
+     * 
+     * 引数:
+     *   summary: テキスト要約
+     *   links: 生成したファイルなどへのリンク
+     *   structured: 結果構造体（オブジェクト以外は { "result": ... } に包む）
+     */
+    pub fn success(summary: String, links: Vec<Content>, structured: Value) -> Self {
+        let structured = match structured {
+            Value::Object(_) => structured,
+            other => json!({ "result": other }),
+        };
+        let mut content = vec![Content::text(summary)];
+        content.extend(links);
+        Self {
+            content,
+            structured_content: Some(structured),
+            is_error: false,
+        }
+    }
+
+    /**
+     * 失敗結果を作成
+     * 
+     * 概要:
+     *   テキストにはコンテキストチェーン全体を、structuredContent.error には
+     *   エラーコード・種別・チェーンを格納する。
+     */
+    pub fn error(err: &anyhow::Error) -> Self {
+        Self {
+            content: vec![Content::text(format!("{:#}", err))],
+            structured_content: Some(json!({ "error": error::error_details(err) })),
+            is_error: true,
+        }
+    }
+
+    /**
+     * ネゴシエーション済みバージョンに合わせて変換
+     * 
+     * 概要:
+     *   - structuredContent 非対応なら、JSONをテキストとして追加して削除する
+     *   - resource_link 非対応なら、リンクをテキストに置き換える
+     */
+    pub fn for_version(mut self, version: ProtocolVersion) -> Self {
+        if !version.supports_resource_links() {
+            self.content = self
+                .content
+                .into_iter()
+                .map(|content| match content {
+                    Content::ResourceLink { uri, name, .. } => Content::text(format!("{}: {}", name, uri)),
+                    other => other,
+                })
+                .collect();
+        }
+        if !version.supports_structured_content() {
+            if let Some(structured) = self.structured_content.take() {
+                let json = serde_json::to_string_pretty(&structured).unwrap_or_else(|_| structured.to_string());
+                self.content.push(Content::text(json));
+            }
+        }
+        self
+    }
+}
+
+/**
+ * MCP コンテンツ
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    /// テキスト
+    Text {
+        /// テキスト内容
+        text: String,
+    },
+    /// リソースへのリンク
+    #[serde(rename_all = "camelCase")]
+    ResourceLink {
+        /// リソースURI
+        uri: String,
+        /// リソース名
+        name: String,
+        /// MIMEタイプ
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+impl Content {
+    pub fn text(text: impl Into<String>) -> Self {
+        Content::Text { text: text.into() }
+    }
 }
 
 /**
@@ -116,8 +532,12 @@ pub struct Tool {
 pub struct ToolCallParams {
     /// ツール名
     pub name: String,
-    /// 引数
-    pub arguments: Option<Value>,
+    /// 引数（省略時は null）
+    #[serde(default)]
+    pub arguments: Value,
+    /// 進捗トークン・実行時間の上限などのメタデータ
+    #[serde(default, rename = "_meta")]
+    pub meta: Option<Value>,
 }
 
 /**
@@ -125,48 +545,57 @@ pub struct ToolCallParams {
  * 
  * 引数:
  *   name: サーバー名
+ *   registry: 登録済みツール（tools/list と tools/call で共有）
+ *   backend: リソースの読み取り・監視に使うスクリプト実行バックエンド
  * 
  * 戻り値:
- *   Result<IoHandler> - JSON-RPCハンドラー
+ *   Result<MetaIoHandler<RequestContext, RequestOrigin>> - JSON-RPCハンドラー
+ *   （セッションはトランスポートが RequestContext として渡す）
  * 
  * エラー:
  *   サーバー構築に失敗した場合はエラーを返す
  */
-pub fn build_server(name: String) -> Result<IoHandler> {
-    let mut io = IoHandler::new();
+pub fn build_server(
+    name: String,
+    registry: Arc<ToolRegistry>,
+    backend: Arc<dyn ScriptBackend>,
+) -> Result<MetaIoHandler<RequestContext, RequestOrigin>> {
+    let mut io = MetaIoHandler::with_middleware(RequestOrigin);
     let server_name = name.clone();
+    let watcher = ResourceWatcher::from_env(backend.clone());
 
     // initialize メソッド
-    io.add_method("initialize", move |params: Params| {
+    let initialize_watcher = watcher.clone();
+    io.add_method_with_meta("initialize", move |params: Params, context: RequestContext| {
         let name = server_name.clone();
+        let watcher = initialize_watcher.clone();
         async move {
-            // パラメータを柔軟にパース（camelCase/snake_case両対応）
-            let params_value: Value = params.parse()?;
-            
-            // camelCase形式に統一
-            let protocol_version = params_value
-                .get("protocolVersion")
-                .or_else(|| params_value.get("protocol_version"))
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| JsonRpcError::invalid_params("missing protocolVersion"))?;
-            
-            let client_info = params_value
-                .get("clientInfo")
-                .or_else(|| params_value.get("client_info"));
-            
-            let client_name = client_info
-                .and_then(|ci| ci.get("name"))
-                .and_then(|n| n.as_str())
-                .unwrap_or("unknown");
-            
+            // パラメータをパース（camelCase/snake_case両対応）
+            let InitializeParams { protocol_version, capabilities, client_info } = params.parse()?;
+            let (client_name, client_version) = client_info
+                .map(|ci| (ci.name, ci.version))
+                .unwrap_or_default();
+            let client_name = client_name.unwrap_or_else(|| "unknown".to_string());
+
+            let negotiated = ProtocolVersion::negotiate(&protocol_version)?;
+            context.session.initialize(negotiated, Some(client_name.clone()));
+            context.session.set_client_capabilities(capabilities);
+            logging::register(&context.session);
+            // 監視フォルダの変更（resources/list_changed）はすべてのセッションへ通知する
+            if watcher.watches_folders() {
+                watcher.watch(&context.session);
+            }
+
             tracing::debug!(
                 protocol_version = %protocol_version,
+                negotiated_version = %negotiated.as_str(),
                 client_name = %client_name,
+                client_version = ?client_version,
                 "MCP initialize called"
             );
 
             let result = InitializeResult {
-                protocol_version: "2024-11-05".to_string(),
+                protocol_version: negotiated.as_str().to_string(),
                 server_info: ServerInfo {
                     name: name.clone(),
                     version: "0.1.0".to_string(),
@@ -175,6 +604,15 @@ pub fn build_server(name: String) -> Result<IoHandler> {
                     tools: ToolsCapability {
                         list_changed: false,
                     },
+                    resources: ResourcesCapability {
+                        subscribe: true,
+                        list_changed: watcher.watches_folders(),
+                    },
+                    prompts: PromptsCapability {
+                        list_changed: false,
+                    },
+                    logging: LoggingCapability {},
+                    completions: CompletionsCapability {},
                 },
             };
 
@@ -187,485 +625,519 @@ pub fn build_server(name: String) -> Result<IoHandler> {
     io.add_notification("initialized", |_params: Params| {
         tracing::debug!("MCP initialized notification received");
     });
+    io.add_alias("notifications/initialized", "initialized");
 
-    // tools/list メソッド
-    io.add_method("tools/list", |_params: Params| {
-        async move {
-            let tools = get_all_tools();
-            tracing::debug!(tool_count = tools.len(), "tools/list called");
-            Ok(json!({ "tools": tools }))
-        }
+    // notifications/cancelled 通知（実行中の tools/call をキャンセル）
+    io.add_notification_with_meta("notifications/cancelled", |params: Params, context: RequestContext| {
+        let params_value: Value = params.parse().unwrap_or(Value::Null);
+        let Some(request_id) = params_value.get("requestId") else {
+            tracing::debug!("notifications/cancelled without requestId ignored");
+            return;
+        };
+        let reason = params_value.get("reason").and_then(|r| r.as_str()).unwrap_or("");
+        // 完了済み・未知のリクエストIDは仕様どおり無視する
+        let cancelled = context.session.cancel_request(request_id);
+        tracing::debug!(request_id = %request_id, reason = %reason, cancelled, "notifications/cancelled received");
     });
 
-    // tools/call メソッド
-    io.add_method("tools/call", |params: Params| {
+    // notifications/roots/list_changed 通知（キャッシュしたルートを破棄し、次回のアクセス時に再取得する）
+    io.add_notification_with_meta("notifications/roots/list_changed", |_params: Params, context: RequestContext| {
+        context.session.set_roots(None);
+        tracing::debug!("notifications/roots/list_changed received");
+    });
+
+    // logging/setLevel メソッド
+    io.add_method_with_meta("logging/setLevel", |params: Params, context: RequestContext| {
         async move {
             let params_value: Value = params.parse()?;
-            
-            // camelCase/snake_case両対応
-            let tool_name = params_value
-                .get("name")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| JsonRpcError::invalid_params("missing tool name"))?;
-            
-            let arguments = params_value
-                .get("arguments")
+            let level: LogLevel = params_value
+                .get("level")
                 .cloned()
-                .unwrap_or(Value::Null);
-            
-            tracing::debug!(
-                tool_name = %tool_name,
-                "tools/call called"
-            );
+                .ok_or_else(|| JsonRpcError::invalid_params("missing level"))
+                .and_then(|level| {
+                    serde_json::from_value(level)
+                        .map_err(|e| JsonRpcError::invalid_params(format!("invalid level: {}", e)))
+                })?;
 
-            match handle_tool_call(tool_name, arguments).await {
-                Ok(result) => Ok(result),
-                Err(e) => {
-                    error!(
-                        tool_name = %tool_name,
-                        error = %e,
-                        "ツール実行エラー"
-                    );
-                    Err(JsonRpcError::internal_error())
-                }
-            }
+            logging::set_level(&context.session, level);
+            tracing::debug!(requested_level = ?level, "logging/setLevel called");
+            Ok(json!({}))
         }
     });
 
-    Ok(io)
-}
-
-/**
- * すべてのツール定義を取得
- * 
- * 戻り値:
- *   Vec<Tool> - ツール定義のリスト
- */
-fn get_all_tools() -> Vec<Tool> {
-    let mut tools = Vec::new();
-
-    // Affinityツール
-    tools.push(Tool {
-        name: "affinity.open_file".to_string(),
-        description: "Affinityアプリケーションでファイルを開く（自然言語で「ファイルを開いて」などの指示に対応）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "path": {
-                    "type": "string",
-                    "description": "開くファイルのパス（絶対パスまたは相対パス）"
-                },
-                "app": {
-                    "type": "string",
-                    "enum": ["Photo", "Designer", "Publisher"],
-                    "description": "使用するAffinityアプリ（省略時は自動判定）"
-                }
-            },
-            "required": ["path"]
-        }),
+    // resources/list メソッド
+    io.add_method_with_meta("resources/list", |_params: Params, context: RequestContext| {
+        async move {
+            // 許可されたルートは呼び出し元のセッションごとに異なる
+            let context = Arc::new(CallContext::new("resources/list", context.session, None).with_stream(context.stream));
+            let resources = context.scope(resources::list_resources()).await;
+            tracing::debug!(resource_count = resources.len(), "resources/list called");
+            Ok(json!({ "resources": resources }))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.create_new".to_string(),
-        description: "新しいAffinityドキュメントを作成（自然言語で「新しいドキュメントを作成して」などの指示に対応）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "width": {
-                    "type": "number",
-                    "description": "幅（ピクセル、省略時はデフォルト）"
-                },
-                "height": {
-                    "type": "number",
-                    "description": "高さ（ピクセル、省略時はデフォルト）"
-                },
-                "app": {
-                    "type": "string",
-                    "enum": ["Photo", "Designer", "Publisher"],
-                    "description": "使用するAffinityアプリ"
-                }
-            },
-            "required": ["app"]
-        }),
+    // resources/read メソッド
+    let resource_backend = backend.clone();
+    io.add_method_with_meta("resources/read", move |params: Params, context: RequestContext| {
+        let backend = resource_backend.clone();
+        async move {
+            let uri = parse_uri(params)?;
+
+            tracing::debug!(uri = %uri, "resources/read called");
+
+            let context = Arc::new(CallContext::new("resources/read", context.session, None).with_stream(context.stream));
+            context.scope(resources::read_resource(&uri, &backend)).await.map_err(|e| {
+                error!(uri = %uri, error = %format!("{:#}", e), "リソース読み取りエラー");
+                error::to_json_rpc_error(&e)
+            })
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.export".to_string(),
-        description: "現在開いているAffinityドキュメントをエクスポート（自然言語で「PDFでエクスポートして」などの指示に対応）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "path": {
-                    "type": "string",
-                    "description": "エクスポート先のファイルパス"
-                },
-                "format": {
-                    "type": "string",
-                    "enum": ["pdf", "png", "jpg", "tiff", "svg"],
-                    "description": "エクスポートフォーマット"
-                },
-                "quality": {
-                    "type": "number",
-                    "minimum": 1,
-                    "maximum": 100,
-                    "description": "品質（1-100、画像形式の場合）"
-                }
-            },
-            "required": ["path", "format"]
-        }),
+    // resources/subscribe メソッド
+    io.add_method_with_meta("resources/subscribe", move |params: Params, context: RequestContext| {
+        let watcher = watcher.clone();
+        async move {
+            let uri = parse_uri(params)?;
+            if !resources::is_known_uri(&uri) {
+                return Err(error::to_json_rpc_error(&ToolError::ResourceNotFound(uri).into()));
+            }
+
+            tracing::debug!(uri = %uri, "resources/subscribe called");
+            context.session.subscribe(&uri);
+            watcher.watch(&context.session);
+            Ok(json!({}))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.apply_filter".to_string(),
-        description: "画像にフィルターを適用（自然言語で「ぼかしを適用して」などの指示に対応）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "filter_name": {
-                    "type": "string",
-                    "description": "フィルター名（例: blur, sharpen, desaturate）"
-                },
-                "intensity": {
-                    "type": "number",
-                    "minimum": 0,
-                    "maximum": 100,
-                    "description": "強度（0-100）"
-                }
-            },
-            "required": ["filter_name"]
-        }),
+    // resources/unsubscribe メソッド
+    io.add_method_with_meta("resources/unsubscribe", |params: Params, context: RequestContext| {
+        async move {
+            let uri = parse_uri(params)?;
+            tracing::debug!(uri = %uri, "resources/unsubscribe called");
+            context.session.unsubscribe(&uri);
+            Ok(json!({}))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.get_active_document".to_string(),
-        description: "現在アクティブなドキュメントの情報を取得".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {}
-        }),
+    // prompts/list メソッド
+    io.add_method("prompts/list", |_params: Params| {
+        async move {
+            let prompts = prompts::list_prompts();
+            tracing::debug!(prompt_count = prompts.len(), "prompts/list called");
+            Ok(json!({ "prompts": prompts }))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.close_document".to_string(),
-        description: "現在開いているドキュメントを閉じる".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {}
-        }),
+    // prompts/get メソッド
+    let prompt_naming = registry.naming().clone();
+    io.add_method("prompts/get", move |params: Params| {
+        let naming = prompt_naming.clone();
+        async move {
+            let params_value: Value = params.parse()?;
+            let name = params_value
+                .get("name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| JsonRpcError::invalid_params("missing prompt name"))?;
+            let arguments = params_value.get("arguments").cloned().unwrap_or(Value::Null);
+
+            tracing::debug!(prompt_name = %name, "prompts/get called");
+
+            prompts::get_prompt(name, &arguments, &naming).map_err(|e| error::to_json_rpc_error(&e))
+        }
     });
 
-    // 16並列バッチ処理ツール
-    tools.push(Tool {
-        name: "affinity.batch_open_files".to_string(),
-        description: "複数のファイルを16並列で同時に開く（自然言語: 「複数のファイルを同時に開いて」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "paths": {
-                    "type": "array",
-                    "items": { "type": "string" },
-                    "description": "開くファイルのパスリスト（最大16個まで）",
-                    "maxItems": 16
-                },
-                "app": {
-                    "type": "string",
-                    "enum": ["Photo", "Designer", "Publisher"],
-                    "description": "使用するAffinityアプリ（省略時は自動判定）"
+    // completion/complete メソッド
+    let completion_registry = registry.clone();
+    io.add_method_with_meta("completion/complete", move |params: Params, context: RequestContext| {
+        let registry = completion_registry.clone();
+        async move {
+            let params_value: Value = params.parse()?;
+            let reference = params_value
+                .get("ref")
+                .ok_or_else(|| JsonRpcError::invalid_params("missing ref"))?;
+            let argument = params_value
+                .get("argument")
+                .ok_or_else(|| JsonRpcError::invalid_params("missing argument"))?;
+            let argument_name = argument
+                .get("name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| JsonRpcError::invalid_params("missing argument name"))?;
+            let value = argument.get("value").and_then(|v| v.as_str()).unwrap_or("");
+            let ref_name = || {
+                reference
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| JsonRpcError::invalid_params("missing ref name"))
+            };
+
+            // パスの候補は呼び出し元のセッションのルートで絞り込む
+            let context = Arc::new(
+                CallContext::new("completion/complete", context.session, None).with_stream(context.stream),
+            );
+            let candidates = match reference.get("type").and_then(|v| v.as_str()) {
+                Some("ref/prompt") => context
+                    .scope(prompts::complete_argument(ref_name()?, argument_name, value))
+                    .await
+                    .map_err(|e| error::to_json_rpc_error(&e))?,
+                // ツール引数の補完（MCP標準の ref 種別にはない拡張）
+                Some("ref/tool") => {
+                    let name = ref_name()?;
+                    let tool = registry
+                        .get(name)
+                        .ok_or_else(|| error::to_json_rpc_error(&ToolError::UnknownTool(name.to_string()).into()))?;
+                    context.scope(tool.complete(argument_name, value)).await
+                }
+                Some("ref/resource") => Vec::new(),
+                other => {
+                    return Err(JsonRpcError::invalid_params(format!(
+                        "unsupported ref type: {}",
+                        other.unwrap_or("(none)")
+                    )))
                 }
-            },
-            "required": ["paths"]
-        }),
+            };
+
+            let completion = Completion::from_candidates(candidates);
+            tracing::debug!(
+                argument = %argument_name,
+                total = completion.total,
+                "completion/complete called"
+            );
+            Ok(json!({ "completion": completion }))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.batch_export".to_string(),
-        description: "複数のドキュメントを16並列で同時にエクスポート（自然言語: 「複数のファイルを同時にエクスポートして」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "exports": {
-                    "type": "array",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "path": { "type": "string", "description": "エクスポート先のファイルパス" },
-                            "format": {
-                                "type": "string",
-                                "enum": ["pdf", "png", "jpg", "tiff", "svg"],
-                                "description": "エクスポートフォーマット"
-                            },
-                            "quality": {
-                                "type": "number",
-                                "minimum": 1,
-                                "maximum": 100,
-                                "description": "品質（1-100、画像形式の場合）"
-                            }
-                        },
-                        "required": ["path", "format"]
-                    },
-                    "description": "エクスポート設定のリスト（最大16個まで）",
-                    "maxItems": 16
-                }
-            },
-            "required": ["exports"]
-        }),
+    // tools/list メソッド
+    let list_registry = registry.clone();
+    io.add_method_with_meta("tools/list", move |_params: Params, context: RequestContext| {
+        let registry = list_registry.clone();
+        async move {
+            let annotations = context.session.effective_version().supports_tool_annotations();
+            let tools: Vec<Tool> = registry
+                .tools()
+                .map(|t| Tool {
+                    name: registry.naming().display_name(&t.definition.name),
+                    annotations: if annotations { t.definition.annotations.clone() } else { None },
+                    ..t.definition.clone()
+                })
+                .collect();
+            tracing::debug!(tool_count = tools.len(), "tools/list called");
+            Ok(json!({ "tools": tools }))
+        }
     });
 
-    tools.push(Tool {
-        name: "affinity.draw_pikachu".to_string(),
-        description: "ピカチュウを描画してAffinityで開く（自然言語: 「ピカチュウを描いて」「ピカチュウを作って」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "output_path": {
-                    "type": "string",
-                    "description": "出力先のファイルパス（省略時は一時ファイル）"
-                },
-                "width": {
-                    "type": "number",
-                    "description": "キャンバスサイズ（幅、省略時は800）"
-                },
-                "height": {
-                    "type": "number",
-                    "description": "キャンバスサイズ（高さ、省略時は800）"
+    // tools/call メソッド
+    io.add_method_with_meta("tools/call", move |params: Params, context: RequestContext| {
+        let registry = registry.clone();
+        async move {
+            let RequestContext { session, request_id, stream } = context;
+            // 引数の形式が不正な呼び出しも監査ログに残すため、解釈する前に記録を始める
+            let raw: Value = params.clone().into();
+            let mut record = audit::CallRecord::start(
+                &session,
+                request_id.as_ref(),
+                raw.get("name").and_then(|v| v.as_str()).unwrap_or_default(),
+                raw.get("arguments").unwrap_or(&Value::Null),
+            );
+            let ToolCallParams { name, arguments, meta } = match params.parse() {
+                Ok(params) => params,
+                Err(rpc_error) => {
+                    record.reject(&ToolError::InvalidParams(rpc_error.message.clone()).into());
+                    return Err(rpc_error);
                 }
+            };
+            let tool_name = name.as_str();
+
+            tracing::debug!(
+                tool_name = %tool_name,
+                client_name = ?session.client_name(),
+                "tools/call called"
+            );
+
+            // 終了処理中は新しい呼び出しを受け付けない
+            if shutdown::is_requested() {
+                let err = ToolError::ShuttingDown.into();
+                let rpc_error = error::to_json_rpc_error(&err);
+                record.reject(&err);
+                return Err(rpc_error);
             }
-        }),
-    });
 
-    // 実際のAffinity操作ツール
-    tools.push(Tool {
-        name: "affinity.draw_shape".to_string(),
-        description: "Affinityアプリケーション内で図形を描画（自然言語: 「円を描いて」「矩形を作って」「線を引いて」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "shape_type": {
-                    "type": "string",
-                    "enum": ["circle", "rectangle", "ellipse", "line"],
-                    "description": "図形の種類"
-                },
-                "x": {
-                    "type": "number",
-                    "description": "位置X（ピクセル、省略時は100）"
-                },
-                "y": {
-                    "type": "number",
-                    "description": "位置Y（ピクセル、省略時は100）"
-                },
-                "width": {
-                    "type": "number",
-                    "description": "幅（ピクセル、省略時は200）"
-                },
-                "height": {
-                    "type": "number",
-                    "description": "高さ（ピクセル、省略時は200）"
-                },
-                "color": {
-                    "type": "string",
-                    "description": "色（HEX形式、例: #FFD700、省略時は黄色）"
-                },
-                "stroke_color": {
-                    "type": "string",
-                    "description": "ストローク色（HEX形式、省略時は黒）"
-                },
-                "stroke_width": {
-                    "type": "number",
-                    "description": "ストローク幅（ピクセル、省略時は2）"
+            // 未登録のツールと引数不正はプロトコルエラーとして返す
+            let Some(tool) = registry.get(tool_name) else {
+                error!(tool_name = %tool_name, "Unknown tool");
+                let err = ToolError::UnknownTool(tool_name.to_string()).into();
+                let rpc_error = error::to_json_rpc_error(&err);
+                record.reject(&err);
+                return Err(rpc_error);
+            };
+            // 別名（underscore / prefixed）で呼ばれた場合も以降は登録名で扱う
+            let tool_name = tool.definition.name.as_str();
+            record.tool = tool_name.to_string();
+            if let Err(e) = tool.validate(&arguments) {
+                let rpc_error = error::to_json_rpc_error(&e);
+                record.reject(&e);
+                return Err(rpc_error);
+            }
+            let timeout = match call_timeout(meta.as_ref()) {
+                Ok(timeout) => timeout.unwrap_or(tool.timeout),
+                Err(e) => {
+                    let rpc_error = error::to_json_rpc_error(&e);
+                    record.reject(&e);
+                    return Err(rpc_error);
                 }
-            },
-            "required": ["shape_type"]
-        }),
-    });
+            };
 
-    tools.push(Tool {
-        name: "affinity.add_text".to_string(),
-        description: "Affinityアプリケーション内にテキストを追加（自然言語: 「テキストを追加して」「文字を書いて」「ここにタイトルを書いて」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "text": {
-                    "type": "string",
-                    "description": "テキスト内容"
-                },
-                "x": {
-                    "type": "number",
-                    "description": "位置X（ピクセル、省略時は100）"
-                },
-                "y": {
-                    "type": "number",
-                    "description": "位置Y（ピクセル、省略時は100）"
-                },
-                "font_size": {
-                    "type": "number",
-                    "description": "フォントサイズ（ポイント、省略時は24）"
-                },
-                "color": {
-                    "type": "string",
-                    "description": "色（HEX形式、省略時は黒）"
+            // ツール実行の失敗はJSON-RPCエラーではなく isError の結果として返し、
+            // モデルがエラー内容を読んで対処できるようにする
+            let context = Arc::new(
+                CallContext::new(tool_name, session.clone(), meta.as_ref()).with_stream(stream),
+            );
+            let _in_flight = request_id
+                .as_ref()
+                .map(|id| session.track_request(id, context.cancellation.clone()));
+            let _active = shutdown::track(context.cancellation.clone());
+            // 上限を超えた呼び出しは future ごと破棄する（osascript は kill_on_drop で終了する）
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout(timeout, context.scope(tool.call(arguments))).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(ToolError::ToolTimeout {
+                    tool: tool_name.to_string(),
+                    elapsed_ms: started.elapsed().as_millis() as u64,
                 }
-            },
-            "required": ["text"]
-        }),
-    });
-
-    tools.push(Tool {
-        name: "affinity.change_color".to_string(),
-        description: "Affinityアプリケーション内で色を変更（自然言語: 「色を黄色に変更して」「選択範囲を赤くして」など）".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "color": {
-                    "type": "string",
-                    "description": "変更する色（HEX形式、例: #FFD700）"
-                },
-                "fill_selection": {
-                    "type": "boolean",
-                    "description": "選択範囲の色を変更するか（省略時はfalse）"
+                .into()),
+            };
+            let result = match outcome {
+                Ok(result) => result,
+                Err(e) => {
+                    error!(
+                        tool_name = %tool_name,
+                        error = %format!("{:#}", e),
+                        "ツール実行エラー"
+                    );
+                    CallToolResult::error(&e)
                 }
-            },
-            "required": ["color"]
-        }),
-    });
+            };
+            record.finish(&result);
+            let result = result.for_version(session.effective_version());
 
-    // Canvaツール（既存）
-    tools.push(Tool {
-        name: "canva.create_design".to_string(),
-        description: "Canvaでデザインを作成".to_string(),
-        input_schema: json!({
-            "type": "object",
-            "properties": {
-                "title": { "type": "string" },
-                "template_id": { "type": "string" },
-                "width": { "type": "number" },
-                "height": { "type": "number" }
-            },
-            "required": ["title"]
-        }),
+            serde_json::to_value(result)
+                .map_err(|e| JsonRpcError::invalid_params(format!("JSON serialization error: {}", e)))
+        }
     });
 
-    tools
+    Ok(io)
 }
 
 /**
- * ツールコールを処理
- * 
- * 引数:
- *   name: ツール名
- *   arguments: 引数（JSON Value）
- * 
- * 戻り値:
- *   Result<Value> - 実行結果
- * 
+ * tools/call の _meta.timeoutMs（実行時間の上限の上書き）を取得
+ *
  * エラー:
- *   ツール実行に失敗した場合はエラーを返す
+ *   正の整数でない場合は InvalidParams を返す
  */
-async fn handle_tool_call(name: &str, arguments: Value) -> Result<Value> {
-    match name {
-        "affinity.open_file" => {
-            let params: affinity::OpenFileParams = serde_json::from_value(arguments)
-                .context("affinity.open_file: 引数のパースに失敗しました")?;
-            let result = affinity::open_file(params).await
-                .context("affinity.open_file: ファイルを開く処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.create_new" => {
-            let params: affinity::CreateNewParams = serde_json::from_value(arguments)
-                .context("affinity.create_new: 引数のパースに失敗しました")?;
-            let result = affinity::create_new(params).await
-                .context("affinity.create_new: 新規作成処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.export" => {
-            let params: affinity::ExportParams = serde_json::from_value(arguments)
-                .context("affinity.export: 引数のパースに失敗しました")?;
-            let result = affinity::export(params).await
-                .context("affinity.export: エクスポート処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.apply_filter" => {
-            let params: affinity::ApplyFilterParams = serde_json::from_value(arguments)
-                .context("affinity.apply_filter: 引数のパースに失敗しました")?;
-            let result = affinity::apply_filter(params).await
-                .context("affinity.apply_filter: フィルター適用処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.get_active_document" => {
-            let result = affinity::get_active_document().await
-                .context("affinity.get_active_document: ドキュメント情報取得に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.close_document" => {
-            let result = affinity::close_document().await
-                .context("affinity.close_document: ドキュメント閉じる処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.batch_open_files" => {
-            let params: affinity::BatchOpenFilesParams = serde_json::from_value(arguments)
-                .context("affinity.batch_open_files: 引数のパースに失敗しました")?;
-            let result = affinity::batch_open_files(params).await
-                .context("affinity.batch_open_files: 16並列ファイルオープン処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.batch_export" => {
-            let params: affinity::BatchExportParams = serde_json::from_value(arguments)
-                .context("affinity.batch_export: 引数のパースに失敗しました")?;
-            let result = affinity::batch_export(params).await
-                .context("affinity.batch_export: 16並列エクスポート処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.draw_pikachu" => {
-            let params: affinity::DrawPikachuParams = serde_json::from_value(arguments)
-                .context("affinity.draw_pikachu: 引数のパースに失敗しました")?;
-            let result = affinity::draw_pikachu(params).await
-                .context("affinity.draw_pikachu: ピカチュウ描画処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.draw_shape" => {
-            let params: affinity::DrawShapeParams = serde_json::from_value(arguments)
-                .context("affinity.draw_shape: 引数のパースに失敗しました")?;
-            let result = affinity::draw_shape(params).await
-                .context("affinity.draw_shape: 図形描画処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.add_text" => {
-            let params: affinity::AddTextParams = serde_json::from_value(arguments)
-                .context("affinity.add_text: 引数のパースに失敗しました")?;
-            let result = affinity::add_text(params).await
-                .context("affinity.add_text: テキスト追加処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "affinity.change_color" => {
-            let params: affinity::ChangeColorParams = serde_json::from_value(arguments)
-                .context("affinity.change_color: 引数のパースに失敗しました")?;
-            let result = affinity::change_color(params).await
-                .context("affinity.change_color: 色変更処理に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        "canva.create_design" => {
-            let params: canva::CreateDesignIn = serde_json::from_value(arguments)
-                .context("canva.create_design: 引数のパースに失敗しました")?;
-            let result = canva::create_design(params).await
-                .context("canva.create_design: デザイン作成に失敗しました")?;
-            serde_json::to_value(result)
-                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))
-        }
-        _ => {
-            error!(tool_name = %name, "Unknown tool");
-            anyhow::bail!("Unknown tool: {}", name)
-        }
+fn call_timeout(meta: Option<&Value>) -> Result<Option<Duration>> {
+    let Some(value) = meta.and_then(|m| m.get("timeoutMs")) else {
+        return Ok(None);
+    };
+    let ms = value
+        .as_u64()
+        .filter(|ms| *ms > 0)
+        .ok_or_else(|| ToolError::InvalidParams(format!("_meta.timeoutMs は正の整数で指定してください: {}", value)))?;
+    Ok(Some(Duration::from_millis(ms)))
+}
+
+/**
+ * リソース系メソッドの uri パラメータを取得
+ */
+fn parse_uri(params: Params) -> Result<String, JsonRpcError> {
+    let params_value: Value = params.parse()?;
+    params_value
+        .get("uri")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| JsonRpcError::invalid_params("missing uri"))
+}
+
+/**
+ * テスト用のサーバー
+ *
+ * 概要:
+ *   MockBackend でツールを登録したサーバーを包み、初期化済みのセッションの作成と
+ *   tools/call の送信をまとめる（mcp / logging / transport のテストで共有する）。
+ */
+#[cfg(test)]
+pub(crate) struct TestServer {
+    pub io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>,
+}
+
+/**
+ * テスト用のサーバーを構築
+ *
+ * 引数:
+ *   backend: ツールが使うモックのバックエンド
+ */
+#[cfg(test)]
+pub(crate) async fn test_server(backend: crate::tools::backend::MockBackend) -> TestServer {
+    let backend: Arc<dyn ScriptBackend> = Arc::new(backend);
+    let registry = crate::tools::register_all(backend.clone()).await.unwrap();
+    let io = build_server("test".to_string(), Arc::new(registry), backend).unwrap();
+    TestServer { io: Arc::new(io) }
+}
+
+#[cfg(test)]
+impl TestServer {
+    /// 初期化済みのセッションを作成（outbound を渡すとサーバーからの送信を受け取れる）
+    pub fn session(&self, outbound: Option<UnboundedSender<String>>) -> Arc<Session> {
+        let session = Arc::new(outbound.map(Session::with_outbound).unwrap_or_default());
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session
+    }
+
+    /// セッションから tools/call を送り、応答を返す
+    pub async fn call(&self, session: &Arc<Session>, id: Value, params: Value) -> Value {
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": params }).to_string();
+        let context = RequestContext::for_message(session.clone(), &message);
+        let response = self.io.handle_request(&message, context).await.unwrap();
+        serde_json::from_str(&response).unwrap()
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_version_negotiation() {
+        assert_eq!(ProtocolVersion::negotiate("2024-11-05").unwrap(), ProtocolVersion::V2024_11_05);
+        assert_eq!(ProtocolVersion::negotiate("2025-03-26").unwrap(), ProtocolVersion::V2025_03_26);
+        assert_eq!(ProtocolVersion::negotiate("2025-06-18").unwrap(), ProtocolVersion::V2025_06_18);
+        // 未知の新しいバージョンには最新の対応バージョンを返す
+        assert_eq!(ProtocolVersion::negotiate("2026-01-01").unwrap(), ProtocolVersion::V2025_06_18);
+        // 対応バージョンの間の未知バージョンには、それ以前で最新のものを返す
+        assert_eq!(ProtocolVersion::negotiate("2025-01-01").unwrap(), ProtocolVersion::V2024_11_05);
+
+        let err = ProtocolVersion::negotiate("2024-01-01").unwrap_err();
+        assert_eq!(err.code, jsonrpc_core::ErrorCode::InvalidParams);
+        assert_eq!(err.data.unwrap()["supported"], json!(["2025-06-18", "2025-03-26", "2024-11-05"]));
+        assert!(ProtocolVersion::negotiate("latest").is_err());
+    }
+
+    #[test]
+    fn request_params_accept_both_casings() {
+        let params: InitializeParams = serde_json::from_value(json!({
+            "protocol_version": "2025-06-18",
+            "client_info": { "name": "cli" }
+        }))
+        .unwrap();
+        assert_eq!(params.protocol_version, "2025-06-18");
+        assert_eq!(params.client_info.unwrap().name.as_deref(), Some("cli"));
+        assert!(params.capabilities.is_null());
+        assert!(serde_json::from_value::<InitializeParams>(json!({ "clientInfo": {} })).is_err());
+
+        let call: ToolCallParams = serde_json::from_value(json!({
+            "name": "affinity.export",
+            "_meta": { "timeoutMs": 1000 }
+        }))
+        .unwrap();
+        assert!(call.arguments.is_null());
+        assert_eq!(call_timeout(call.meta.as_ref()).unwrap(), Some(Duration::from_millis(1000)));
+    }
+
+    #[tokio::test]
+    async fn tool_failures_are_returned_as_error_results() {
+        use crate::tools::backend::{MockBackend, ScriptOutput};
+
+        let server = test_server(
+            MockBackend::default().then(ScriptOutput::failed("execution error: 開いているドキュメントがありません (-2700)")),
+        )
+        .await;
+        let session = server.session(None);
+
+        // ツールの失敗は isError の結果として返り、モデルが読めるテキストと種別を含む
+        let response = server
+            .call(&session, json!(1), json!({ "name": "affinity.get_active_document", "arguments": { "app": "Photo" } }))
+            .await;
+        let result = &response["result"];
+        assert_eq!(result["isError"], true);
+        assert!(result["content"][0]["text"].as_str().unwrap().contains("開いているドキュメントがありません"));
+        assert_eq!(result["structuredContent"]["error"]["code"], -32004);
+
+        // 未登録のツールはプロトコルエラーのまま
+        let response = server.call(&session, json!(2), json!({ "name": "affinity.no_such_tool" })).await;
+        assert_eq!(response["error"]["code"], -32001);
+        assert!(response.get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn audit_records_exported_files() {
+        use crate::tools::backend::MockBackend;
+
+        audit::enable_for_tests();
+        let server = test_server(MockBackend::default()).await;
+        let session = server.session(None);
+
+        let path = "/tmp/affinity-mcp-audit-test/書き出し 1.png";
+        let response = server
+            .call(
+                &session,
+                json!("audit-export"),
+                json!({
+                    "name": "affinity.export",
+                    "arguments": { "path": path, "format": "png", "app": "Photo" },
+                }),
+            )
+            .await;
+        assert_eq!(response["result"]["isError"], false);
+
+        let entry = audit::entry_for_tests("audit-export").expect("監査ログに記録されていません");
+        assert_eq!(entry["tool"], "affinity.export");
+        assert_eq!(entry["outcome"], "success");
+        assert_eq!(entry["produced_paths"], json!([path]));
+    }
+
+    #[tokio::test]
+    async fn audit_records_rejected_calls() {
+        use crate::tools::backend::MockBackend;
+
+        audit::enable_for_tests();
+        let server = test_server(MockBackend::default()).await;
+        let session = server.session(None);
+        let call = |id: &str, params: Value| server.call(&session, json!(id), params);
+
+        // 不正な _meta.timeoutMs
+        let response = call(
+            "audit-timeout",
+            json!({ "name": "affinity.get_active_document", "arguments": {}, "_meta": { "timeoutMs": -1 } }),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], -32602);
+        let entry = audit::entry_for_tests("audit-timeout").expect("監査ログに記録されていません");
+        assert_eq!(entry["tool"], "affinity.get_active_document");
+        assert_eq!(entry["outcome"], "rejected");
+        assert_eq!(entry["error"]["kind"], "invalid_params");
+
+        // 形式が不正なパラメータ
+        let response = call("audit-malformed", json!({ "name": 42 })).await;
+        assert_eq!(response["error"]["code"], -32602);
+        let entry = audit::entry_for_tests("audit-malformed").expect("監査ログに記録されていません");
+        assert_eq!(entry["outcome"], "rejected");
+    }
+
+    #[test]
+    fn call_tool_result_is_downgraded_for_old_versions() {
+        let result = CallToolResult::success(
+            "done".to_string(),
+            vec![crate::resources::file_link("/tmp/out.png")],
+            json!({ "exported": true }),
+        );
+
+        let latest = serde_json::to_value(result.clone().for_version(ProtocolVersion::V2025_06_18)).unwrap();
+        assert_eq!(latest["content"][1]["type"], "resource_link");
+        assert_eq!(latest["content"][1]["mimeType"], "image/png");
+        assert_eq!(latest["structuredContent"], json!({ "exported": true }));
+
+        let oldest = serde_json::to_value(result.for_version(ProtocolVersion::V2024_11_05)).unwrap();
+        assert!(oldest.get("structuredContent").is_none());
+        let content = oldest["content"].as_array().unwrap();
+        assert!(content.iter().all(|c| c["type"] == "text"));
+        assert!(content[2]["text"].as_str().unwrap().contains("\"exported\": true"));
+    }
+}
@@ -0,0 +1,387 @@
+/**
+ * MCPプロンプト
+ *
+ * 概要:
+ *   既存のツールを組み合わせたデザインワークフローを、
+ *   引数付きのプロンプトテンプレートとして公開する（prompts/list, prompts/get）。
+ *
+ * 提供するプロンプト:
+ *   - export_all_for_web         開いているドキュメントをすべてWeb向けにエクスポート
+ *   - instagram_post_from_text   テキストからInstagram投稿画像を作成
+ *   - prepare_print_pdf          アクティブドキュメントを印刷用PDFとして書き出す
+ *
+ * 主な仕様:
+ *   - 引数はMCP仕様どおりすべて文字列で受け取る
+ *   - 生成するメッセージは user ロールのテキスト1件
+ *
+ * エラー処理:
+ *   - 未知のプロンプト名・必須引数の不足・不正な値は ToolError::InvalidParams
+ */
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::completion::{self, Completer};
+use crate::error::ToolError;
+use crate::tools::registry::ToolNaming;
+
+/**
+ * プロンプト定義（prompts/list の要素）
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct Prompt {
+    /// プロンプト名
+    pub name: &'static str,
+    /// 説明
+    pub description: &'static str,
+    /// 引数
+    pub arguments: Vec<PromptArgument>,
+}
+
+/**
+ * プロンプト引数
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptArgument {
+    /// 引数名
+    pub name: &'static str,
+    /// 説明
+    pub description: &'static str,
+    /// 必須かどうか
+    pub required: bool,
+}
+
+/**
+ * プロンプトテンプレート
+ */
+struct PromptTemplate {
+    name: &'static str,
+    description: &'static str,
+    /// (引数名, 説明, 必須)
+    arguments: &'static [(&'static str, &'static str, bool)],
+    /// 引数ごとの補完関数
+    completions: &'static [(&'static str, Completer)],
+    render: fn(&Arguments) -> Result<String>,
+}
+
+const TEMPLATES: &[PromptTemplate] = &[
+    PromptTemplate {
+        name: "export_all_for_web",
+        description: "開いているAffinityドキュメントをすべてWeb向けの画像としてエクスポートする",
+        arguments: &[
+            ("output_dir", "エクスポート先フォルダ（絶対パス）", true),
+            ("format", "画像形式（png または jpg、既定: png）", false),
+            ("quality", "JPEG品質（1-100、既定: 80）", false),
+        ],
+        completions: &[("output_dir", Completer::Paths), ("format", Completer::Values(complete_web_format))],
+        render: render_export_all_for_web,
+    },
+    PromptTemplate {
+        name: "instagram_post_from_text",
+        description: "テキストから1080×1080のInstagram投稿画像を作成してPNGで書き出す",
+        arguments: &[
+            ("text", "投稿に載せるテキスト", true),
+            ("output_path", "書き出し先のPNGファイルパス（絶対パス）", true),
+            ("color", "文字色（HEX形式、既定: #FFFFFF）", false),
+            ("background", "背景色（HEX形式、既定: #000000）", false),
+        ],
+        completions: &[("output_path", Completer::Paths)],
+        render: render_instagram_post_from_text,
+    },
+    PromptTemplate {
+        name: "prepare_print_pdf",
+        description: "アクティブドキュメントを確認し、印刷入稿用のPDFとして書き出す",
+        arguments: &[
+            ("output_path", "書き出し先のPDFファイルパス（絶対パス）", true),
+        ],
+        completions: &[("output_path", Completer::Paths)],
+        render: render_prepare_print_pdf,
+    },
+];
+
+/**
+ * prompts/get の引数
+ */
+struct Arguments<'a> {
+    prompt: &'a str,
+    values: &'a Map<String, Value>,
+}
+
+impl Arguments<'_> {
+    /// 任意の引数（空文字は未指定として扱う）
+    fn optional(&self, name: &str) -> Option<&str> {
+        self.values
+            .get(name)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// 必須の引数
+    fn required(&self, name: &str) -> Result<&str> {
+        self.optional(name).ok_or_else(|| {
+            ToolError::InvalidParams(format!("{}: 引数 {} は必須です", self.prompt, name)).into()
+        })
+    }
+}
+
+/**
+ * プロンプト一覧
+ */
+pub fn list_prompts() -> Vec<Prompt> {
+    TEMPLATES
+        .iter()
+        .map(|t| Prompt {
+            name: t.name,
+            description: t.description,
+            arguments: t
+                .arguments
+                .iter()
+                .map(|&(name, description, required)| PromptArgument { name, description, required })
+                .collect(),
+        })
+        .collect()
+}
+
+/**
+ * プロンプトを展開する
+ *
+ * 引数:
+ *   name: プロンプト名
+ *   arguments: 引数（文字列値のオブジェクト、省略可）
+ *   naming: ツール名の公開形式（本文中のツール名を tools/list と揃える）
+ *
+ * 戻り値:
+ *   Result<Value> - prompts/get の結果（{ description, messages }）
+ *
+ * エラー:
+ *   未知のプロンプト名、必須引数の不足、不正な値の場合はエラーを返す
+ */
+pub fn get_prompt(name: &str, arguments: &Value, naming: &ToolNaming) -> Result<Value> {
+    let template = TEMPLATES
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| ToolError::InvalidParams(format!("不明なプロンプトです: {}", name)))?;
+
+    let empty = Map::new();
+    let values = match arguments {
+        Value::Object(values) => values,
+        Value::Null => &empty,
+        _ => {
+            return Err(ToolError::InvalidParams(format!(
+                "{}: arguments はオブジェクトで指定してください",
+                name
+            ))
+            .into())
+        }
+    };
+
+    let text = rename_tools(&(template.render)(&Arguments { prompt: name, values })?, naming);
+    Ok(json!({
+        "description": template.description,
+        "messages": [{
+            "role": "user",
+            "content": { "type": "text", "text": text },
+        }],
+    }))
+}
+
+/**
+ * プロンプト引数の補完候補を返す（completion/complete の ref/prompt）
+ *
+ * 戻り値:
+ *   Result<Vec<String>> - 候補（補完関数のない引数は空）
+ *
+ * エラー:
+ *   未知のプロンプト名の場合はエラーを返す
+ */
+pub async fn complete_argument(name: &str, argument: &str, value: &str) -> Result<Vec<String>> {
+    let template = TEMPLATES
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| ToolError::InvalidParams(format!("不明なプロンプトです: {}", name)))?;
+    Ok(match template.completions.iter().find(|(n, _)| *n == argument) {
+        Some((_, completer)) => completer.complete(value).await,
+        None => Vec::new(),
+    })
+}
+
+/// export_all_for_web の format の補完
+fn complete_web_format(value: &str) -> Vec<String> {
+    completion::matching(&["png", "jpg"], value)
+}
+
+/**
+ * 本文中のツール名（affinity.*）を公開形式の名前に置き換える
+ */
+fn rename_tools(text: &str, naming: &ToolNaming) -> String {
+    const TOOL_PREFIX: &str = "affinity.";
+    let mut renamed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(TOOL_PREFIX) {
+        renamed.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let end = tail
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+            .unwrap_or(tail.len());
+        let name = tail[..end].trim_end_matches('.');
+        renamed.push_str(&naming.display_name(name));
+        rest = &tail[name.len()..];
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+fn render_export_all_for_web(args: &Arguments) -> Result<String> {
+    let output_dir = args.required("output_dir")?.trim_end_matches('/');
+    let format = match args.optional("format").unwrap_or("png").to_lowercase().as_str() {
+        "png" => "png",
+        "jpg" | "jpeg" => "jpg",
+        other => {
+            return Err(ToolError::InvalidParams(format!(
+                "{}: format は png または jpg で指定してください: {}",
+                args.prompt, other
+            ))
+            .into())
+        }
+    };
+    let quality = match args.optional("quality") {
+        Some(v) => v
+            .parse::<u8>()
+            .ok()
+            .filter(|q| (1..=100).contains(q))
+            .ok_or_else(|| {
+                ToolError::InvalidParams(format!("{}: quality は1-100で指定してください: {}", args.prompt, v))
+            })?,
+        None => 80,
+    };
+    let quality_note = if format == "jpg" {
+        format!("、quality は {}", quality)
+    } else {
+        String::new()
+    };
+
+    Ok(format!(
+        "開いているAffinityドキュメントをすべてWeb向けにエクスポートしてください。\n\
+         \n\
+         手順:\n\
+         1. affinity.get_active_document で現在のドキュメントを確認する。開いていなければその旨を伝えて終了する。\n\
+         2. ほかに開いているドキュメントのファイルパスが分からない場合は、ユーザーに一覧を確認する。\n\
+         3. ドキュメントごとに affinity.open_file でそのファイルを前面に出し、\n\
+            affinity.export で {dir}/<ファイル名（拡張子を除く）>.{fmt} に format \"{fmt}\"{quality} としてエクスポートする。\n\
+         4. 失敗した項目があればエラー内容とともに一覧にし、成功したファイルのパスを報告する。\n\
+         \n\
+         ドキュメントは閉じたり上書き保存したりしないでください。",
+        dir = output_dir,
+        fmt = format,
+        quality = quality_note,
+    ))
+}
+
+fn render_instagram_post_from_text(args: &Arguments) -> Result<String> {
+    let text = args.required("text")?;
+    let output_path = args.required("output_path")?;
+    let color = args.optional("color").unwrap_or("#FFFFFF");
+    let background = args.optional("background").unwrap_or("#000000");
+
+    Ok(format!(
+        "次のテキストからInstagram投稿用の正方形画像を作成してください。\n\
+         \n\
+         テキスト:\n\
+         {text}\n\
+         \n\
+         手順:\n\
+         1. affinity.create_new で app は \"Designer\"、width 1080・height 1080 の新規ドキュメントを作成する。\n\
+         2. affinity.draw_shape で (0, 0) から 1080×1080 の rectangle を color {background} で描く。\n\
+         3. テキストが長い場合は読みやすい長さの行に分け、見出しと本文に整理する（内容は変えない）。\n\
+         4. affinity.add_text で文字色 {color} のテキストを中央付近に配置する。見出しは大きめのフォントサイズにする。\n\
+         5. affinity.export で {output} に format \"png\" としてエクスポートし、書き出したパスを報告する。",
+        text = text,
+        background = background,
+        color = color,
+        output = output_path,
+    ))
+}
+
+fn render_prepare_print_pdf(args: &Arguments) -> Result<String> {
+    let output_path = args.required("output_path")?;
+
+    Ok(format!(
+        "アクティブなAffinityドキュメントを印刷入稿用のPDFとして書き出してください。\n\
+         \n\
+         手順:\n\
+         1. affinity.get_active_document でドキュメント名・パス・未保存の変更の有無を確認する。開いていなければその旨を伝えて終了する。\n\
+         2. 未保存の変更がある場合は、書き出す前にユーザーへ確認する。\n\
+         3. affinity.export で {output} に format \"pdf\" としてエクスポートする。\n\
+         4. 書き出したPDFのパスと、塗り足し・カラーモード（CMYK）・フォントのアウトライン化はAffinity側の書き出し設定で確認が必要な点を報告する。",
+        output = output_path,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::backend::MockBackend;
+    use crate::tools::registry::NamingScheme;
+    use std::sync::Arc;
+
+    #[test]
+    fn prompts_render_and_reference_registered_tools() {
+        let registry = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(crate::tools::register_all(Arc::new(MockBackend::default())))
+            .unwrap();
+
+        for prompt in list_prompts() {
+            let arguments: Map<String, Value> = prompt
+                .arguments
+                .iter()
+                .filter(|a| a.required)
+                .map(|a| (a.name.to_string(), json!("/tmp/value")))
+                .collect();
+            let arguments = Value::Object(arguments);
+            let result = get_prompt(prompt.name, &arguments, &ToolNaming::default()).unwrap();
+            let text = result["messages"][0]["content"]["text"].as_str().unwrap();
+
+            // テンプレートが参照するツール名が実在することを確認する
+            for word in text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')) {
+                if word.starts_with("affinity.") {
+                    assert!(registry.get(word).is_some(), "{}: 未登録のツール {}", prompt.name, word);
+                }
+            }
+
+            // 公開形式を変えた場合は tools/list と同じ名前で参照する
+            let naming = ToolNaming {
+                scheme: NamingScheme::Underscore,
+                ..ToolNaming::default()
+            };
+            let result = get_prompt(prompt.name, &arguments, &naming).unwrap();
+            let text = result["messages"][0]["content"]["text"].as_str().unwrap();
+            assert!(!text.contains("affinity."), "{}: ドット区切りの名前が残っています", prompt.name);
+            for word in text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+                if word.starts_with("affinity_") {
+                    assert!(registry.get(word).is_some(), "{}: 未登録のツール {}", prompt.name, word);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn prompt_arguments_are_completed() {
+        assert_eq!(complete_argument("export_all_for_web", "format", "J").await.unwrap(), vec!["jpg"]);
+        assert!(complete_argument("export_all_for_web", "quality", "").await.unwrap().is_empty());
+        assert!(complete_argument("no_such_prompt", "format", "").await.is_err());
+    }
+
+    #[test]
+    fn invalid_prompt_requests_are_rejected() {
+        let missing = get_prompt("prepare_print_pdf", &Value::Null, &ToolNaming::default()).unwrap_err();
+        assert!(matches!(crate::error::classify(&missing), Some(ToolError::InvalidParams(_))));
+
+        let unknown = get_prompt("no_such_prompt", &json!({}), &ToolNaming::default()).unwrap_err();
+        assert!(matches!(crate::error::classify(&unknown), Some(ToolError::InvalidParams(_))));
+
+        let bad_format = get_prompt("export_all_for_web", &json!({ "output_dir": "/tmp", "format": "gif" }), &ToolNaming::default());
+        assert!(bad_format.is_err());
+    }
+}
@@ -0,0 +1,361 @@
+/**
+ * MCPリソース
+ *
+ * 概要:
+ *   開いているAffinityドキュメントと、ツールが生成したファイルを
+ *   affinity:// スキームのリソースとして公開する。
+ *
+ * URI:
+ *   - affinity://documents/active      アクティブドキュメントのメタデータ（JSON）
+ *   - affinity://files/<絶対パス>       export / batch_export / draw_pikachu が生成したファイル（blob）
+ *
+ * 主な仕様:
+ *   - ファイルURIのパスは各セグメントをパーセントエンコードする（空白・#・?・日本語など）
+ *   - 読み取れるファイルはインデックスに登録されたものに限る（任意のパスは読まない）
+ *   - 一覧・読み取りとも、呼び出し元のセッションに許可されたルートの内側のファイルに限る
+ *     （tools::roots 参照。インデックスは全セッションで共有するため）
+ *   - インデックスはプロセス全体で共有し、同じパスは最新の登録で上書きする
+ *
+ * エラー処理:
+ *   - 未知のURI・インデックスにないファイルは ToolError::ResourceNotFound
+ */
+use anyhow::{Context, Result};
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::error::ToolError;
+use crate::mcp::Content;
+use crate::tools::affinity;
+use crate::tools::backend::ScriptBackend;
+use crate::tools::roots;
+
+/// 監視フォルダで見つけたファイルの登録元（record_file の produced_by）
+pub const WATCHED_FOLDER: &str = "watcher";
+/// アクティブドキュメントのURI
+pub const ACTIVE_DOCUMENT_URI: &str = "affinity://documents/active";
+/// 生成ファイルURIの接頭辞
+const FILE_URI_PREFIX: &str = "affinity://files";
+/// パスのセグメントでエンコードしない文字（RFC 3986 の unreserved）
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+/// インデックスに保持する最大ファイル数（古いものから削除）
+const MAX_INDEXED_FILES: usize = 256;
+
+/**
+ * リソース定義（resources/list の要素）
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    /// リソースURI
+    pub uri: String,
+    /// リソース名
+    pub name: String,
+    /// 説明
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIMEタイプ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/**
+ * 生成ファイルのインデックス項目
+ */
+#[derive(Debug, Clone)]
+struct IndexedFile {
+    /// 絶対パス
+    path: PathBuf,
+    /// 生成したツール名
+    produced_by: String,
+}
+
+fn index() -> &'static Mutex<Vec<IndexedFile>> {
+    static INDEX: OnceLock<Mutex<Vec<IndexedFile>>> = OnceLock::new();
+    INDEX.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn absolute_path(path: &str) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| {
+        let path = PathBuf::from(path);
+        if path.is_absolute() {
+            path
+        } else {
+            std::env::current_dir().map(|dir| dir.join(&path)).unwrap_or(path)
+        }
+    })
+}
+
+/**
+ * 生成ファイルをインデックスに登録
+ *
+ * 引数:
+ *   path: 生成したファイルのパス
+ *   produced_by: 生成したツール名
+ */
+pub fn record_file(path: &str, produced_by: &str) {
+    let path = absolute_path(path);
+    let mut files = index().lock().unwrap_or_else(|e| e.into_inner());
+    files.retain(|f| f.path != path);
+    files.push(IndexedFile {
+        path,
+        produced_by: produced_by.to_string(),
+    });
+    if files.len() > MAX_INDEXED_FILES {
+        let overflow = files.len() - MAX_INDEXED_FILES;
+        files.drain(..overflow);
+    }
+}
+
+/// ファイルパスからリソースURIを作成（各セグメントをパーセントエンコード）
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let segments: Vec<String> = path.split('/').map(|s| utf8_percent_encode(s, SEGMENT).to_string()).collect();
+    format!("{}{}", FILE_URI_PREFIX, segments.join("/"))
+}
+
+/**
+ * リソースURIからファイルパスを取り出す（file_uri の逆変換）
+ *
+ * 戻り値:
+ *   Option<PathBuf> - affinity://files/ の絶対パスでなければ None
+ */
+pub fn file_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix(FILE_URI_PREFIX)?;
+    if !encoded.starts_with('/') {
+        return None;
+    }
+    let decoded = percent_decode_str(encoded).decode_utf8().ok()?;
+    Some(PathBuf::from(decoded.as_ref()))
+}
+
+/// 拡張子からMIMEタイプを推定（None ならリソースの対象外）
+pub fn mime_type_for(path: &Path) -> Option<&'static str> {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("pdf") => Some("application/pdf"),
+        Some("png") => Some("image/png"),
+        Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+        Some("tif") | Some("tiff") => Some("image/tiff"),
+        Some("svg") => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+/**
+ * 生成ファイルへの resource_link コンテンツを作成
+ *
+ * 引数:
+ *   path: 生成したファイルのパス
+ */
+pub fn file_link(path: &str) -> Content {
+    let path = absolute_path(path);
+    Content::ResourceLink {
+        uri: file_uri(&path),
+        name: file_name(&path),
+        mime_type: mime_type_for(&path).map(str::to_string),
+    }
+}
+
+/**
+ * リソース一覧
+ *
+ * 戻り値:
+ *   Vec<Resource> - アクティブドキュメント + 許可されたルートの内側の生成ファイル（新しい順）
+ */
+pub async fn list_resources() -> Vec<Resource> {
+    let mut resources = vec![Resource {
+        uri: ACTIVE_DOCUMENT_URI.to_string(),
+        name: "アクティブドキュメント".to_string(),
+        description: Some("現在アクティブなAffinityドキュメントのメタデータ".to_string()),
+        mime_type: Some("application/json".to_string()),
+    }];
+
+    let files = index().lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let paths = files.iter().map(|f| f.path.to_string_lossy().into_owned()).collect();
+    let allowed = roots::filter_allowed(paths).await;
+    let visible = files
+        .iter()
+        .rev()
+        .filter(|f| f.path.exists() && allowed.iter().any(|p| Path::new(p) == f.path));
+    resources.extend(visible.map(|f| Resource {
+        uri: file_uri(&f.path),
+        name: file_name(&f.path),
+        description: Some(match f.produced_by.as_str() {
+            WATCHED_FOLDER => "監視フォルダに追加されたファイル".to_string(),
+            tool => format!("{} が生成したファイル", tool),
+        }),
+        mime_type: mime_type_for(&f.path).map(str::to_string),
+    }));
+    resources
+}
+
+/**
+ * 生成ファイルの状態（変更監視用）
+ *
+ * 引数:
+ *   uri: affinity://files/... のURI
+ *
+ * 戻り値:
+ *   Option<String> - 更新日時とサイズ（削除済みなら "missing"）。インデックスにないURIは None
+ */
+pub async fn file_fingerprint(uri: &str) -> Option<String> {
+    let path = indexed_path(uri)?;
+    match tokio::fs::metadata(&path).await {
+        Ok(metadata) => Some(format!("{:?}|{}", metadata.modified().ok(), metadata.len())),
+        Err(_) => Some("missing".to_string()),
+    }
+}
+
+/**
+ * 購読可能なURIかどうか
+ */
+pub fn is_known_uri(uri: &str) -> bool {
+    uri == ACTIVE_DOCUMENT_URI || indexed_path(uri).is_some()
+}
+
+/// インデックスに登録済みの生成ファイルのパス
+fn indexed_path(uri: &str) -> Option<PathBuf> {
+    let path = file_path(uri)?;
+    let files = index().lock().unwrap_or_else(|e| e.into_inner());
+    files.iter().any(|f| f.path == path).then_some(path)
+}
+
+/**
+ * リソースを読み取る
+ *
+ * 引数:
+ *   uri: リソースURI
+ *   backend: アクティブドキュメントの取得に使うスクリプト実行バックエンド
+ *
+ * 戻り値:
+ *   Result<Value> - resources/read の結果（{ contents: [...] }）
+ *
+ * エラー:
+ *   未知のURI、インデックスにないファイル、読み取り失敗時はエラーを返す。
+ *   許可されたルートの外のファイルは ToolError::OutsideRoots を返す
+ */
+pub async fn read_resource(uri: &str, backend: &Arc<dyn ScriptBackend>) -> Result<Value> {
+    if uri == ACTIVE_DOCUMENT_URI {
+        let document = affinity::get_active_document(backend.clone(), affinity::DocumentParams::default()).await
+            .context("アクティブドキュメント情報の取得に失敗しました")?;
+        let text = serde_json::to_string_pretty(&document)?;
+        return Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": "application/json",
+                "text": text,
+            }]
+        }));
+    }
+
+    let path = indexed_path(uri)
+        .ok_or_else(|| ToolError::ResourceNotFound(uri.to_string()))?;
+    roots::check_paths(&[path.to_string_lossy().as_ref()]).await?;
+
+    let bytes = tokio::fs::read(&path).await
+        .map_err(|e| ToolError::ResourceNotFound(format!("{}（{}）", uri, e)))?;
+    let mut content = json!({
+        "uri": uri,
+        "blob": base64::engine::general_purpose::STANDARD.encode(bytes),
+    });
+    if let Some(mime_type) = mime_type_for(&path) {
+        content["mimeType"] = json!(mime_type);
+    }
+    Ok(json!({ "contents": [content] }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::backend::MockBackend;
+
+    fn backend() -> Arc<dyn ScriptBackend> {
+        Arc::new(MockBackend::default())
+    }
+
+    #[tokio::test]
+    async fn recorded_files_are_listed_and_readable() {
+        let path = std::env::temp_dir().join(format!("affinity-mcp-{}.png", uuid::Uuid::new_v4()));
+        std::fs::write(&path, [0x89, b'P', b'N', b'G']).unwrap();
+        let path_str = path.to_string_lossy().to_string();
+        record_file(&path_str, "affinity.export");
+
+        let uri = match file_link(&path_str) {
+            Content::ResourceLink { uri, mime_type, .. } => {
+                assert_eq!(mime_type.as_deref(), Some("image/png"));
+                uri
+            }
+            other => panic!("unexpected: {:?}", other),
+        };
+        assert!(uri.starts_with("affinity://files/"));
+        assert!(list_resources().await.iter().any(|r| r.uri == uri));
+
+        let result = read_resource(&uri, &backend()).await.unwrap();
+        assert_eq!(result["contents"][0]["mimeType"], "image/png");
+        assert_eq!(result["contents"][0]["blob"], "iVBORw==");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_uris_are_percent_encoded() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-{} 書き出し", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ポスター #1?.svg");
+        std::fs::write(&path, "<svg/>").unwrap();
+        record_file(&path.to_string_lossy(), "affinity.export");
+
+        let uri = file_uri(&absolute_path(&path.to_string_lossy()));
+        assert!(!uri.contains(' ') && !uri.contains('#') && !uri.contains('?') && uri.is_ascii(), "{}", uri);
+        assert!(uri.ends_with("/%E3%83%9D%E3%82%B9%E3%82%BF%E3%83%BC%20%231%3F.svg"), "{}", uri);
+        assert_eq!(file_path(&uri), Some(absolute_path(&path.to_string_lossy())));
+
+        let result = read_resource(&uri, &backend()).await.unwrap();
+        assert_eq!(result["contents"][0]["mimeType"], "image/svg+xml");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn files_outside_the_callers_roots_are_hidden() {
+        use crate::mcp::ProtocolVersion;
+        use crate::session::Session;
+        use crate::tools::context::CallContext;
+
+        let path = std::env::temp_dir().join(format!("affinity-mcp-{}.png", uuid::Uuid::new_v4()));
+        std::fs::write(&path, [0x89, b'P', b'N', b'G']).unwrap();
+        record_file(&path.to_string_lossy(), "affinity.export");
+        let uri = file_uri(&absolute_path(&path.to_string_lossy()));
+
+        // 別の場所だけをルートに宣言したセッションからは見えない
+        let session = Arc::new(Session::default());
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "roots": {} }));
+        session.set_roots(Some(vec![PathBuf::from("/nonexistent/affinity-mcp-designs")]));
+        let context = Arc::new(CallContext::new("resources/read", session, None));
+
+        let listed = context.clone().scope(list_resources()).await;
+        assert!(listed.iter().all(|r| r.uri != uri));
+        assert!(listed.iter().any(|r| r.uri == ACTIVE_DOCUMENT_URI));
+        let err = context.scope(read_resource(&uri, &backend())).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::OutsideRoots(_))));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unindexed_files_are_not_readable() {
+        let err = read_resource("affinity://files/etc/passwd", &backend()).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::ResourceNotFound(_))));
+        assert!(read_resource("file:///etc/passwd", &backend()).await.is_err());
+    }
+}
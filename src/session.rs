@@ -0,0 +1,425 @@
+/**
+ * MCPセッション状態
+ *
+ * 概要:
+ *   1つのクライアント接続ごとの状態（initialize で確定した情報、
+ *   リソース購読、実行中のリクエスト、サーバーからクライアントへの送信経路）を保持する。
+ *
+ * 主な仕様:
+ *   - initialize 前は protocol_version() が None を返す
+ *   - 複数のリクエストから同時に参照されるため内部はロックで保護する
+ *   - notify() はトランスポートの送信キューにJSON-RPC通知を積む
+ *   - 実行中の tools/call はリクエストIDごとに Cancellation を登録し、
+ *     notifications/cancelled で対象のリクエストだけをキャンセルする
+ *   - request() はサーバーからクライアントへのリクエスト（elicitation/create など）を送り、
+ *     トランスポートが resolve_response() に渡した応答を待つ
+ */
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+
+use crate::logging::LogLevel;
+use crate::mcp::ProtocolVersion;
+use crate::tools::context::Cancellation;
+
+/**
+ * セッション
+ */
+#[derive(Debug, Default)]
+pub struct Session {
+    state: RwLock<SessionState>,
+    /// 購読中のリソースURI
+    subscriptions: Mutex<BTreeSet<String>>,
+    /// 実行中のリクエスト（リクエストIDのJSON表現 → 登録の世代とキャンセル状態）
+    in_flight: Mutex<HashMap<String, (u64, Cancellation)>>,
+    /// 実行中リクエストの登録の連番（同じIDが再利用されても後の登録を消さないため）
+    next_generation: AtomicU64,
+    /// 応答待ちのサーバー発リクエスト（リクエストIDのJSON表現 → 応答の受け渡し先）
+    pending: Mutex<HashMap<String, oneshot::Sender<Result<Value, RequestFailure>>>>,
+    /// サーバー発リクエストの連番
+    next_request_id: AtomicU64,
+    /// クライアントへの送信キュー（JSON文字列）
+    outbound: Option<UnboundedSender<String>>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// ネゴシエーション済みのプロトコルバージョン
+    protocol_version: Option<ProtocolVersion>,
+    /// クライアント名（initialize の clientInfo.name）
+    client_name: Option<String>,
+    /// クライアント機能（initialize の capabilities）
+    client_capabilities: Value,
+    /// クライアントのルート（roots/list の結果のキャッシュ）
+    roots: Option<Vec<PathBuf>>,
+    /// ログ転送レベル（logging/setLevel、未設定なら既定値）
+    log_level: Option<LogLevel>,
+}
+
+impl Session {
+    /**
+     * 送信キュー付きのセッションを作成
+     *
+     * 引数:
+     *   outbound: トランスポートがクライアントへ書き出すメッセージのキュー
+     */
+    pub fn with_outbound(outbound: UnboundedSender<String>) -> Self {
+        Self {
+            outbound: Some(outbound),
+            ..Self::default()
+        }
+    }
+
+    /**
+     * initialize の結果を記録
+     *
+     * 引数:
+     *   protocol_version: ネゴシエーション済みのバージョン
+     *   client_name: クライアント名
+     */
+    pub fn initialize(&self, protocol_version: ProtocolVersion, client_name: Option<String>) {
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        state.protocol_version = Some(protocol_version);
+        state.client_name = client_name;
+    }
+
+    /// ネゴシエーション済みのプロトコルバージョン
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).protocol_version
+    }
+
+    /**
+     * 機能判定に使うプロトコルバージョン
+     *
+     * 概要:
+     *   initialize 前は最も古い対応バージョンとして振る舞う。
+     */
+    pub fn effective_version(&self) -> ProtocolVersion {
+        self.protocol_version().unwrap_or(ProtocolVersion::OLDEST)
+    }
+
+    /// クライアント名
+    pub fn client_name(&self) -> Option<String> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).client_name.clone()
+    }
+
+    /// クライアント機能を記録（initialize の capabilities）
+    pub fn set_client_capabilities(&self, capabilities: Value) {
+        self.state.write().unwrap_or_else(|e| e.into_inner()).client_capabilities = capabilities;
+    }
+
+    /// クライアントが elicitation/create に対応しているかどうか
+    pub fn supports_elicitation(&self) -> bool {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state.protocol_version.is_some_and(|v| v.supports_elicitation())
+            && state.client_capabilities.get("elicitation").is_some()
+    }
+
+    /// クライアントが roots/list に対応しているかどうか
+    pub fn supports_roots(&self) -> bool {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).client_capabilities.get("roots").is_some()
+    }
+
+    /// キャッシュ済みのクライアントのルート
+    pub fn roots(&self) -> Option<Vec<PathBuf>> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).roots.clone()
+    }
+
+    /// クライアントのルートのキャッシュを更新（None で破棄）
+    pub fn set_roots(&self, roots: Option<Vec<PathBuf>>) {
+        self.state.write().unwrap_or_else(|e| e.into_inner()).roots = roots;
+    }
+
+    /// ログ転送レベル
+    pub fn log_level(&self) -> LogLevel {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).log_level.unwrap_or(LogLevel::DEFAULT)
+    }
+
+    /// ログ転送レベルを設定
+    pub fn set_log_level(&self, level: LogLevel) {
+        self.state.write().unwrap_or_else(|e| e.into_inner()).log_level = Some(level);
+    }
+
+    /**
+     * クライアントへJSON-RPC通知を送信
+     *
+     * 引数:
+     *   method: 通知メソッド名（例: notifications/resources/updated）
+     *   params: 通知パラメータ
+     *
+     * 戻り値:
+     *   bool - 送信キューに積めたかどうか（接続が閉じていれば false）
+     */
+    pub fn notify(&self, method: &str, params: Value) -> bool {
+        match &self.outbound {
+            Some(outbound) => outbound.send(notification(method, params)).is_ok(),
+            None => false,
+        }
+    }
+
+    /**
+     * クライアントへJSON-RPCリクエストを送信し、応答を待つ
+     *
+     * 引数:
+     *   method: メソッド名（例: elicitation/create）
+     *   params: パラメータ
+     *   stream: 送信先（None ならセッションの送信キュー）
+     *
+     * 戻り値:
+     *   Result<Value> - 応答の result
+     *
+     * エラー:
+     *   送信できない場合、エラー応答の場合、応答前に接続が閉じた場合はエラーを返す
+     */
+    pub async fn request(&self, method: &str, params: Value, stream: Option<&UnboundedSender<String>>) -> Result<Value> {
+        let id = json!(format!("server-{}", self.next_request_id.fetch_add(1, Ordering::Relaxed) + 1));
+        let key = id.to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).insert(key.clone(), tx);
+        let _pending = PendingGuard { session: self, key };
+
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let sent = match stream.or(self.outbound.as_ref()) {
+            Some(outbound) => outbound.send(message).is_ok(),
+            None => false,
+        };
+        if !sent {
+            anyhow::bail!("クライアントへリクエストを送信できません: {}", method);
+        }
+
+        match rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(RequestFailure::Client(error))) => anyhow::bail!("クライアントがエラーを返しました（{}）: {}", method, error),
+            Ok(Err(RequestFailure::Undelivered(reason))) => {
+                anyhow::bail!("クライアントへリクエストを届けられません（{}）: {}", method, reason)
+            }
+            Err(_) => anyhow::bail!("応答を受け取る前に接続が終了しました: {}", method),
+        }
+    }
+
+    /**
+     * クライアントからの応答を応答待ちのリクエストに渡す
+     *
+     * 概要:
+     *   トランスポートは受信メッセージを JSON-RPC ハンドラーに渡す前にこれを呼ぶ。
+     *   バッチ（配列）の場合は要素ごとに判定する。
+     *
+     * 戻り値:
+     *   bool - メッセージが応答だけで構成されていたかどうか（true ならハンドラーに渡さない）
+     */
+    pub fn resolve_response(&self, message: &str) -> bool {
+        let Ok(message) = serde_json::from_str::<Value>(message) else {
+            return false;
+        };
+        let messages = match &message {
+            Value::Array(items) if !items.is_empty() => items.iter().collect(),
+            Value::Object(_) => vec![&message],
+            _ => return false,
+        };
+        if !messages.iter().all(|m| is_response(m)) {
+            return false;
+        }
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for message in messages {
+            let Some(tx) = pending.remove(&message["id"].to_string()) else {
+                tracing::debug!(id = %message["id"], "応答待ちでないIDの応答を無視しました");
+                continue;
+            };
+            let outcome = match message.get("error") {
+                Some(error) => Err(RequestFailure::Client(error.clone())),
+                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            let _ = tx.send(outcome);
+        }
+        true
+    }
+
+    /**
+     * 届けられなかったサーバー発リクエストを失敗させる
+     *
+     * 概要:
+     *   送信キューのメッセージをクライアントへ書き出せなかったトランスポートが呼び、
+     *   応答を待つ側がタイムアウトまで待たずにエラーを受け取れるようにする。
+     *   通知・応答のメッセージは何もしない。
+     *
+     * 引数:
+     *   message: 届けられなかったメッセージ（JSON文字列）
+     *   reason: 届けられなかった理由
+     */
+    pub fn fail_undelivered(&self, message: &str, reason: &str) {
+        let Ok(message) = serde_json::from_str::<Value>(message) else {
+            return;
+        };
+        let (Some(_), Some(id)) = (message.get("method"), message.get("id")) else {
+            return;
+        };
+        if let Some(tx) = self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&id.to_string()) {
+            let _ = tx.send(Err(RequestFailure::Undelivered(reason.to_string())));
+        }
+    }
+
+    /**
+     * 応答待ちのリクエストをすべて失敗させる
+     *
+     * 概要:
+     *   接続の終了時に呼び、応答を待っているツールが終了を待つ側と
+     *   互いに待ち続けないようにする。
+     */
+    pub fn fail_pending(&self) {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// リソースを購読
+    pub fn subscribe(&self, uri: &str) {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner()).insert(uri.to_string());
+    }
+
+    /// リソースの購読を解除
+    pub fn unsubscribe(&self, uri: &str) {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner()).remove(uri);
+    }
+
+    /// 購読中のリソースURI
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /**
+     * 実行中のリクエストを登録
+     *
+     * 概要:
+     *   同じIDのリクエストが実行中に再び届いた場合は後の登録で置き換える。
+     *   登録ごとに世代を付け、先に終わったリクエストのガードが後の登録を解除しないようにする。
+     *
+     * 引数:
+     *   request_id: JSON-RPCリクエストID
+     *   cancellation: リクエストのキャンセル状態
+     *
+     * 戻り値:
+     *   InFlightGuard - 破棄時に登録を解除する
+     */
+    pub fn track_request(self: &Arc<Self>, request_id: &Value, cancellation: Cancellation) -> InFlightGuard {
+        let key = request_id.to_string();
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.clone(), (generation, cancellation));
+        InFlightGuard {
+            session: self.clone(),
+            key,
+            generation,
+        }
+    }
+
+    /**
+     * 実行中のリクエストをキャンセル
+     *
+     * 戻り値:
+     *   bool - 対象のリクエストが実行中だったかどうか
+     */
+    pub fn cancel_request(&self, request_id: &Value) -> bool {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        match in_flight.get(&request_id.to_string()) {
+            Some((_, cancellation)) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/**
+ * サーバー発リクエストが結果を得られなかった理由
+ */
+#[derive(Debug)]
+enum RequestFailure {
+    /// クライアントのエラー応答
+    Client(Value),
+    /// トランスポートがクライアントへ届けられなかった
+    Undelivered(String),
+}
+
+/**
+ * JSON-RPC通知メッセージを作成
+ */
+pub fn notification(method: &str, params: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+    })
+    .to_string()
+}
+
+/// JSON-RPC応答（method がなく、id と result / error を持つ）かどうか
+fn is_response(message: &Value) -> bool {
+    message.get("method").is_none()
+        && message.get("id").is_some()
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+/**
+ * 応答待ちの登録（破棄時に解除）
+ */
+struct PendingGuard<'a> {
+    session: &'a Session,
+    key: String,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.session.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.key);
+    }
+}
+
+/**
+ * 実行中リクエストの登録（破棄時に解除）
+ */
+pub struct InFlightGuard {
+    session: Arc<Session>,
+    key: String,
+    generation: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.session.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        // 同じIDで後から登録されたリクエストは解除しない
+        if in_flight.get(&self.key).is_some_and(|(generation, _)| *generation == self.generation) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_reused_request_id_keeps_the_later_call_cancellable() {
+        let session = Arc::new(Session::default());
+        let (first, second) = (Cancellation::default(), Cancellation::default());
+        let first_guard = session.track_request(&json!(1), first.clone());
+        let _second_guard = session.track_request(&json!(1), second.clone());
+
+        // 先に終わった呼び出しのガードは後の登録を解除しない
+        drop(first_guard);
+        assert!(session.cancel_request(&json!(1)));
+        assert!(second.is_cancelled());
+        assert!(!first.is_cancelled());
+    }
+}
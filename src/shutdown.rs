@@ -0,0 +1,240 @@
+/**
+ * グレースフルシャットダウン
+ *
+ * 概要:
+ *   SIGINT / SIGTERM、または stdio の入力終了（EOF）で終了処理を始め、
+ *   新しい tools/call の受付を止めてから、実行中のツール呼び出しの完了を期限まで待つ。
+ *
+ * 主な仕様:
+ *   - request() で終了処理を開始する（何度呼んでもよい）。トランスポートは requested() で
+ *     新しいメッセージ・接続の受付を止める
+ *   - 終了処理の開始後の tools/call は ToolError::ShuttingDown で拒否する
+ *   - 実行中のツール呼び出しは track() で登録し、drain() は登録がなくなるまで待つ
+ *   - 期限（AFFINITY_MCP_SHUTDOWN_TIMEOUT_MS、既定: 10000）を過ぎた呼び出しはキャンセルし、
+ *     osascript の子プロセスを終了させる（tools::backend の kill_on_drop 参照）
+ */
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tracing::{info, warn};
+
+use crate::tools::context::Cancellation;
+
+/// 実行中の呼び出しを待つ既定の期限
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 期限後にキャンセルした呼び出しが終了するまでの猶予
+const CANCEL_GRACE: Duration = Duration::from_secs(2);
+
+/**
+ * 実行中のツール呼び出しの登録表
+ *
+ * 概要:
+ *   プロセス全体では state() の1つを使う（テストでは個別に作成できる）。
+ */
+struct CallRegistry {
+    /// 実行中のツール呼び出し（登録番号 → キャンセル状態）
+    calls: Mutex<HashMap<u64, Cancellation>>,
+    next_id: AtomicU64,
+    /// 実行中の呼び出しがなくなったときの通知
+    idle: Notify,
+}
+
+impl CallRegistry {
+    fn new() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            idle: Notify::new(),
+        }
+    }
+
+    fn track(&self, cancellation: Cancellation) -> CallGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).insert(id, cancellation);
+        CallGuard { registry: self, id }
+    }
+
+    /// 実行中のツール呼び出しの数
+    fn active_calls(&self) -> usize {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// 実行中のツール呼び出しがなくなるまで待機
+    async fn wait_idle(&self) {
+        loop {
+            // 件数の確認より先に待機を登録し、間に完了した通知を取りこぼさない
+            let idle = self.idle.notified();
+            if self.active_calls() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    async fn drain(&self, timeout: Duration) {
+        let active = self.active_calls();
+        if active > 0 {
+            info!(active, timeout_ms = timeout.as_millis() as u64, "実行中のツール呼び出しの完了を待機します");
+        }
+        if tokio::time::timeout(timeout, self.wait_idle()).await.is_ok() {
+            return;
+        }
+
+        let cancellations: Vec<Cancellation> = self
+            .calls
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        warn!(remaining = cancellations.len(), "期限までに完了しなかったツール呼び出しをキャンセルします");
+        for cancellation in &cancellations {
+            cancellation.cancel();
+        }
+        if tokio::time::timeout(CANCEL_GRACE, self.wait_idle()).await.is_err() {
+            warn!(remaining = self.active_calls(), "キャンセル後も終了しないツール呼び出しを残して終了します");
+        }
+    }
+}
+
+/**
+ * 終了処理の状態（プロセス全体で1つ）
+ */
+struct State {
+    requested: watch::Sender<bool>,
+    calls: CallRegistry,
+}
+
+fn state() -> &'static State {
+    static STATE: OnceLock<State> = OnceLock::new();
+    STATE.get_or_init(|| State {
+        requested: watch::Sender::new(false),
+        calls: CallRegistry::new(),
+    })
+}
+
+/// 終了処理を開始する
+pub fn request() {
+    if !state().requested.send_replace(true) {
+        info!("終了処理を開始しました。新しいツール呼び出しの受付を停止します");
+    }
+}
+
+/// 終了処理が始まっているかどうか
+pub fn is_requested() -> bool {
+    *state().requested.borrow()
+}
+
+/// 終了処理が始まるまで待機
+pub async fn requested() {
+    let mut rx = state().requested.subscribe();
+    // 送信側は静的に保持しているため閉じることはない
+    let _ = rx.wait_for(|requested| *requested).await;
+}
+
+/**
+ * 実行中のツール呼び出しを登録
+ *
+ * 戻り値:
+ *   CallGuard - 破棄時に登録を解除する
+ */
+pub fn track(cancellation: Cancellation) -> CallGuard<'static> {
+    state().calls.track(cancellation)
+}
+
+/**
+ * 実行中のツール呼び出しの登録（破棄時に解除）
+ */
+pub struct CallGuard<'a> {
+    registry: &'a CallRegistry,
+    id: u64,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        let mut calls = self.registry.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.remove(&self.id);
+        if calls.is_empty() {
+            self.registry.idle.notify_waiters();
+        }
+    }
+}
+
+/// 実行中の呼び出しを待つ期限（AFFINITY_MCP_SHUTDOWN_TIMEOUT_MS）
+pub fn timeout_from_env() -> Duration {
+    std::env::var("AFFINITY_MCP_SHUTDOWN_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/**
+ * 実行中のツール呼び出しを期限まで待つ
+ *
+ * 概要:
+ *   期限を過ぎても終わらない呼び出しはキャンセルし（osascript は終了される）、
+ *   短い猶予の間に終了を待つ。
+ *
+ * 引数:
+ *   timeout: 完了を待つ期限
+ */
+pub async fn drain(timeout: Duration) {
+    state().calls.drain(timeout).await
+}
+
+/**
+ * 終了シグナル（SIGINT / SIGTERM）を待機
+ *
+ * 戻り値:
+ *   &'static str - 受信したシグナル名
+ */
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = sigterm.recv() => "SIGTERM",
+            },
+            Err(e) => {
+                warn!(error = %e, "SIGTERM のハンドラーを登録できませんでした");
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_cancels_calls_that_miss_the_deadline() {
+        // プロセス全体の登録表は並行する tools/call のテストも使うため、個別の登録表で確かめる
+        let registry: &'static CallRegistry = Box::leak(Box::new(CallRegistry::new()));
+        let cancellation = Cancellation::default();
+        let guard = registry.track(cancellation.clone());
+
+        // キャンセルされたら登録を解除する呼び出し
+        let call = tokio::spawn(async move {
+            cancellation.cancelled().await;
+            drop(guard);
+        });
+
+        registry.drain(Duration::from_millis(50)).await;
+        call.await.unwrap();
+        assert_eq!(registry.active_calls(), 0);
+    }
+}
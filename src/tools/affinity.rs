@@ -3,7 +3,8 @@
  * 
  * 概要:
  *   Affinityアプリケーションを操作するための実装。
- *   macOSではAppleScriptを使用してアプリケーションを制御する。
+ *   各ツールは AppleScript を生成し、登録時に渡されたスクリプト実行バックエンド
+ *   （tools::backend::ScriptBackend）で実行する。
  * 
  * 主な仕様:
  *   - open_file: ファイルを開く
@@ -12,6 +13,21 @@
  *   - apply_filter: フィルター適用
  *   - get_active_document: アクティブドキュメント取得
  *   - close_document: ドキュメントを閉じる
+ *   - ドキュメントを操作するツール（export / apply_filter / get_active_document / close_document）は
+ *     対象のアプリを app で指定できる。省略時は最前面の、なければ起動中のAffinityアプリ
+ *     （統合版 v3 の Affinity を含む）を対象にする（resolve_app 参照）
+ *   - 未保存のドキュメントを閉じる・既存ファイルを上書きする前に、方針に応じて
+ *     elicitation/create でユーザーに確認する（tools::confirm 参照）
+ *   - 読み書きするパスはクライアントのルート・許可リストの内側に制限する（tools::roots 参照）
+ *   - バックエンドを差し替えれば、生成したスクリプトの検証やカセットの再生により
+ *     macOS 以外でもツールの処理をそのまま実行できる
+ *   - スクリプトは tools::applescript のビルダーで組み立て、引数（パス・フィルター名・
+ *     テキスト・色など）は必ずエスケープした文字列リテラルとして埋め込む
+ * 
+ * 制限事項:
+ *   - osascript の実行はプロセス全体で直列化する（複数セッションから同時に呼ばれても
+ *     1つずつ実行され、バッチ処理の各項目も受付は並行・実行は順番になる）
+ *   - macOS 以外で osascript のバックエンドを使うと ToolError::UnsupportedPlatform になる
  * 
  * エラー処理:
  *   - 詳細なエラーメッセージを出力
@@ -20,45 +36,47 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
-use std::process::Command;
 use tracing::{error, debug, info};
 use futures::future::join_all;
-use std::sync::Arc;
-use tokio::task;
 use std::fs;
-use std::path::PathBuf;
-
-#[cfg(target_os = "macos")]
-async fn run_applescript(script: &str) -> Result<String> {
-    let script = Arc::new(script.to_string());
-    let script_clone = script.clone();
-    
-    task::spawn_blocking(move || {
-        let output = Command::new("osascript")
-            .arg("-e")
-            .arg(script_clone.as_str())
-            .output()
-            .context("osascriptコマンドの実行に失敗しました")?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!(
-                script = %script_clone,
-                stderr = %stderr,
-                "AppleScript実行エラー"
-            );
-            anyhow::bail!("AppleScript実行エラー: {}", stderr);
-        }
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::completion::{self, Completer};
+use crate::error::ToolError;
+use crate::mcp::{Content, ToolAnnotations};
+use crate::resources;
+use super::applescript::{self, fill, Script};
+use super::backend::ScriptBackend;
+use super::confirm;
+use super::context::Progress;
+use super::roots;
+use super::registry::{ToolOutput, ToolRegistry, ToolSpec};
 
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    })
-    .await
-    .context("AppleScript実行タスクの完了待機に失敗しました")?
+/**
+ * 既存ファイルの上書きを確認
+ *
+ * 概要:
+ *   既存ファイルの内容は元に戻せないため、既定の方針（unsaved-only）でも確認する。
+ *
+ * 引数:
+ *   paths: 書き出し先のパス（存在しないものは確認の対象外）
+ *
+ * エラー:
+ *   上書きが承認されなかった場合は ToolError::NotConfirmed を返す
+ */
+async fn confirm_overwrite(paths: &[&str]) -> Result<()> {
+    let existing: Vec<&str> = paths.iter().copied().filter(|p| Path::new(p).exists()).collect();
+    if existing.is_empty() {
+        return Ok(());
+    }
+    confirm::confirm(&format!("既存のファイルを上書きします: {}", existing.join(", ")), true).await
 }
 
-#[cfg(not(target_os = "macos"))]
-async fn run_applescript(_script: &str) -> Result<String> {
-    anyhow::bail!("AppleScriptはmacOSでのみ利用可能です")
+/// キャンセルによるエラーかどうか
+fn is_cancelled(err: &anyhow::Error) -> bool {
+    matches!(crate::error::classify(err), Some(ToolError::Cancelled(_)))
 }
 
 /**
@@ -85,16 +103,115 @@ pub enum AffinityApp {
     Designer,
     /// Affinity Publisher
     Publisher,
+    /// Affinity（Photo・Designer・Publisher を統合した v3）
+    Affinity,
 }
 
 impl AffinityApp {
+    /// 優先順（起動中のアプリから選ぶときに統合版を優先する）
+    const PREFERENCE: [AffinityApp; 4] = [
+        AffinityApp::Affinity,
+        AffinityApp::Photo,
+        AffinityApp::Designer,
+        AffinityApp::Publisher,
+    ];
+
     fn app_name(&self) -> &'static str {
         match self {
             AffinityApp::Photo => "Affinity Photo",
             AffinityApp::Designer => "Affinity Designer",
             AffinityApp::Publisher => "Affinity Publisher",
+            AffinityApp::Affinity => "Affinity",
         }
     }
+
+    /**
+     * プロセス名がこのアプリかどうか
+     *
+     * 概要:
+     *   アプリ名そのもの、またはバージョン番号付きの名前（例: Affinity Photo 2）を同じアプリとみなす。
+     */
+    fn matches_process(&self, process_name: &str) -> bool {
+        match process_name.strip_prefix(self.app_name()) {
+            Some("") => true,
+            Some(rest) => rest
+                .strip_prefix(' ')
+                .is_some_and(|version| !version.is_empty() && version.chars().all(|c| c.is_ascii_digit())),
+            None => false,
+        }
+    }
+
+    /// プロセス名に対応するアプリ
+    fn from_process_name(process_name: &str) -> Option<AffinityApp> {
+        Self::PREFERENCE.into_iter().find(|app| app.matches_process(process_name))
+    }
+}
+
+/**
+ * ドキュメントを操作するツールの対象アプリの指定
+ */
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct DocumentParams {
+    /// 対象のAffinityアプリ（省略時は最前面または起動中のアプリ）
+    #[serde(default)]
+    pub app: Option<AffinityApp>,
+}
+
+/**
+ * 操作対象のAffinityアプリを決める
+ *
+ * 概要:
+ *   指定があればそのアプリを使う。なければ最前面のAffinityアプリ、それもなければ
+ *   起動中のAffinityアプリ（統合版 Affinity・Photo・Designer・Publisher の順に優先）を使う。
+ *   起動中のアプリはバージョン番号付きのプロセス名（例: Affinity Photo 2）のまま返す。
+ *
+ * 引数:
+ *   backend: スクリプト実行バックエンド
+ *   requested: 引数で指定されたアプリ
+ *
+ * 戻り値:
+ *   Result<Option<String>> - tell application に渡すアプリ名（Affinityアプリが起動していなければ None）
+ *
+ * エラー:
+ *   起動中のアプリを取得できない場合（System Events の権限がない場合など）はエラーを返す
+ */
+async fn resolve_app(backend: &dyn ScriptBackend, requested: Option<&AffinityApp>) -> Result<Option<String>> {
+    if let Some(app) = requested {
+        return Ok(Some(app.app_name().to_string()));
+    }
+
+    let script = Script::new()
+        .tell_application("System Events", |s| {
+            s.line("set frontName to name of first application process whose frontmost is true")
+                .line("set runningNames to name of every application process whose name starts with \"Affinity\"")
+        })
+        .line("set AppleScript's text item delimiters to linefeed")
+        .line("return frontName & linefeed & (runningNames as text)")
+        .build();
+    let output = backend.run(&script).await
+        .context("起動中のAffinityアプリの取得に失敗しました")?;
+
+    let app = pick_app(&output);
+    debug!(function = "resolve_app", app = ?app, "操作対象のAffinityアプリを決めました");
+    Ok(app)
+}
+
+/**
+ * 最前面のアプリと起動中のアプリの一覧から対象のAffinityアプリを選ぶ
+ *
+ * 引数:
+ *   output: 1行目が最前面のプロセス名、2行目以降が起動中のプロセス名
+ */
+fn pick_app(output: &str) -> Option<String> {
+    let mut names = output.lines().map(str::trim);
+    let frontmost = names.next().unwrap_or_default();
+    if AffinityApp::from_process_name(frontmost).is_some() {
+        return Some(frontmost.to_string());
+    }
+    let running: Vec<&str> = names.filter(|name| !name.is_empty()).collect();
+    AffinityApp::PREFERENCE.iter().find_map(|app| {
+        running.iter().find(|name| app.matches_process(name)).map(|name| name.to_string())
+    })
 }
 
 /**
@@ -107,61 +224,52 @@ impl AffinityApp {
  *   Result<OpenFileResult> - 実行結果
  * 
  * エラー:
- *   ファイルを開けない場合はエラーを返す
+ *   ファイルを開けない場合、パスが許可されたルートの外にある場合はエラーを返す
  */
-pub async fn open_file(params: OpenFileParams) -> Result<OpenFileResult> {
+pub async fn open_file(backend: Arc<dyn ScriptBackend>, params: OpenFileParams) -> Result<OpenFileResult> {
     debug!(
         function = "open_file",
         path = %params.path,
         app = ?params.app,
         "Affinityファイルを開きます"
     );
+    roots::check_paths(&[params.path.as_str()]).await?;
 
-    #[cfg(target_os = "macos")]
-    {
-        let app_name = params.app.as_ref()
-            .map(|a| a.app_name())
-            .unwrap_or_else(|| detect_app_from_path(&params.path));
-
-        let script = format!(
-            r#"
-            tell application "{}"
-                activate
-                open POSIX file "{}"
-            end tell
-            "#,
-            app_name,
-            std::fs::canonicalize(&params.path)
-                .context(format!("パスの正規化に失敗しました: {}", params.path))?
-                .to_string_lossy()
-        );
+    let app_name = params.app.as_ref()
+        .map(|a| a.app_name())
+        .unwrap_or_else(|| detect_app_from_path(&params.path));
 
-        run_applescript(&script).await
-            .context(format!("ファイルを開く処理に失敗しました: {}", params.path))?;
+    let path = std::fs::canonicalize(&params.path)
+        .context(format!("パスの正規化に失敗しました: {}", params.path))?;
+    let script = open_script(app_name, &path.to_string_lossy());
 
-        debug!(
-            function = "open_file",
-            path = %params.path,
-            app = %app_name,
-            "ファイルを開きました"
-        );
+    backend.run(&script).await
+        .context(format!("ファイルを開く処理に失敗しました: {}", params.path))?;
 
-        Ok(OpenFileResult {
-            opened: true,
-            app: app_name.to_string(),
-            path: params.path,
-        })
-    }
+    debug!(
+        function = "open_file",
+        path = %params.path,
+        app = %app_name,
+        "ファイルを開きました"
+    );
+
+    Ok(OpenFileResult {
+        opened: true,
+        app: app_name.to_string(),
+        path: params.path,
+    })
+}
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではファイルを開く機能は未実装です");
-        Ok(OpenFileResult {
-            opened: false,
-            app: "Unsupported".to_string(),
-            path: params.path,
+/**
+ * ファイルを開くAppleScriptを生成
+ */
+fn open_script(app_name: &str, path: &str) -> String {
+    Script::new()
+        .tell_application(app_name, |s| {
+            s.line("activate")
+                .line(fill("open POSIX file {}", &[applescript::string(path)]))
         })
-    }
+        .build()
 }
 
 /**
@@ -196,6 +304,16 @@ pub struct OpenFileResult {
     pub path: String,
 }
 
+impl ToolOutput for OpenFileResult {
+    fn summary(&self) -> String {
+        if self.opened {
+            format!("{} で {} を開きました", self.app, self.path)
+        } else {
+            format!("{} を開けませんでした（{}）", self.path, self.app)
+        }
+    }
+}
+
 /**
  * 新規作成パラメータ
  */
@@ -222,6 +340,16 @@ pub struct CreateNewResult {
     pub app: String,
 }
 
+impl ToolOutput for CreateNewResult {
+    fn summary(&self) -> String {
+        if self.created {
+            format!("{} で新規ドキュメントを作成しました", self.app)
+        } else {
+            format!("新規ドキュメントを作成できませんでした（{}）", self.app)
+        }
+    }
+}
+
 /**
  * 新規ドキュメントを作成
  * 
@@ -234,7 +362,7 @@ pub struct CreateNewResult {
  * エラー:
  *   作成に失敗した場合はエラーを返す
  */
-pub async fn create_new(params: CreateNewParams) -> Result<CreateNewResult> {
+pub async fn create_new(backend: Arc<dyn ScriptBackend>, params: CreateNewParams) -> Result<CreateNewResult> {
     debug!(
         function = "create_new",
         app = ?params.app,
@@ -243,45 +371,34 @@ pub async fn create_new(params: CreateNewParams) -> Result<CreateNewResult> {
         "Affinity新規ドキュメントを作成します"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        let app_name = params.app.app_name();
-        let width = params.width.unwrap_or(1920);
-        let height = params.height.unwrap_or(1080);
-
-        let script = format!(
-            r#"
-            tell application "{}"
-                activate
-                make new document with properties {{width:{}, height:{}}}
-            end tell
-            "#,
-            app_name, width, height
-        );
+    let app_name = params.app.app_name();
+    let width = params.width.unwrap_or(1920);
+    let height = params.height.unwrap_or(1080);
+
+    let properties = applescript::record(&[
+        ("width", applescript::integer(width)),
+        ("height", applescript::integer(height)),
+    ])?;
+    let script = Script::new()
+        .tell_application(app_name, |s| {
+            s.line("activate")
+                .line(fill("make new document with properties {}", &[properties]))
+        })
+        .build();
 
-        run_applescript(&script).await
-            .context(format!("新規ドキュメント作成に失敗しました: {}", app_name))?;
+    backend.run(&script).await
+        .context(format!("新規ドキュメント作成に失敗しました: {}", app_name))?;
 
-        debug!(
-            function = "create_new",
-            app = %app_name,
-            "新規ドキュメントを作成しました"
-        );
-
-        Ok(CreateNewResult {
-            created: true,
-            app: app_name.to_string(),
-        })
-    }
+    debug!(
+        function = "create_new",
+        app = %app_name,
+        "新規ドキュメントを作成しました"
+    );
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外では新規作成機能は未実装です");
-        Ok(CreateNewResult {
-            created: false,
-            app: "Unsupported".to_string(),
-        })
-    }
+    Ok(CreateNewResult {
+        created: true,
+        app: app_name.to_string(),
+    })
 }
 
 /**
@@ -295,7 +412,11 @@ pub struct ExportParams {
     pub format: ExportFormat,
     /// 品質（1-100、画像形式の場合）
     #[serde(default)]
+    #[schemars(range(min = 1, max = 100))]
     pub quality: Option<u8>,
+    /// 対象のAffinityアプリ（省略時は最前面または起動中のアプリ）
+    #[serde(default)]
+    pub app: Option<AffinityApp>,
 }
 
 /**
@@ -322,6 +443,24 @@ pub struct ExportResult {
     pub path: String,
 }
 
+impl ToolOutput for ExportResult {
+    fn summary(&self) -> String {
+        if self.exported {
+            format!("{} にエクスポートしました", self.path)
+        } else {
+            format!("{} へのエクスポートは行われませんでした", self.path)
+        }
+    }
+
+    fn links(&self) -> Vec<Content> {
+        if self.exported {
+            vec![resources::file_link(&self.path)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
 /**
  * エクスポート
  * 
@@ -332,70 +471,82 @@ pub struct ExportResult {
  *   Result<ExportResult> - 実行結果
  * 
  * エラー:
- *   エクスポートに失敗した場合はエラーを返す
+ *   エクスポートに失敗した場合、パスが許可されたルートの外にある場合、
+ *   既存ファイルの上書きが承認されなかった場合はエラーを返す
+ */
+pub async fn export(backend: Arc<dyn ScriptBackend>, params: ExportParams) -> Result<ExportResult> {
+    roots::check_paths(&[params.path.as_str()]).await?;
+    confirm_overwrite(&[params.path.as_str()]).await?;
+    export_document(backend.as_ref(), params).await
+}
+
+/**
+ * 最前面のドキュメントに対して操作するAppleScriptを生成
+ *
+ * 概要:
+ *   アプリを前面に出し、ドキュメントが開いていなければ「開いているドキュメントがありません」
+ *   のエラーにする（ToolError::NoOpenDocument として判定される）。
+ */
+fn with_front_document(app_name: &str, body: impl FnOnce(Script) -> Script) -> String {
+    Script::new()
+        .tell_application(app_name, |s| {
+            s.line("activate").if_else(
+                "(count of documents) > 0",
+                |s| s.tell("front document", body),
+                |s| s.line("error \"開いているドキュメントがありません\""),
+            )
+        })
+        .build()
+}
+
+/**
+ * 確認なしでエクスポート（上書きの確認は呼び出し側で行う）
  */
-pub async fn export(params: ExportParams) -> Result<ExportResult> {
+async fn export_document(backend: &dyn ScriptBackend, params: ExportParams) -> Result<ExportResult> {
     debug!(
         function = "export",
         path = %params.path,
         format = ?params.format,
         quality = params.quality,
+        app = ?params.app,
         "Affinityドキュメントをエクスポートします"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        let format_str = match params.format {
-            ExportFormat::Pdf => "pdf",
-            ExportFormat::Png => "png",
-            ExportFormat::Jpg => "jpg",
-            ExportFormat::Tiff => "tiff",
-            ExportFormat::Svg => "svg",
-        };
+    let app_name = resolve_app(backend, params.app.as_ref()).await?
+        .ok_or(ToolError::NoOpenDocument)?;
 
-        let script = format!(
-            r#"
-            tell application "Affinity Photo"
-                activate
-                if (count of documents) > 0 then
-                    tell front document
-                        export in file "{}" as "{}" with options {{quality:{}}}
-                    end tell
-                else
-                    error "開いているドキュメントがありません"
-                end if
-            end tell
-            "#,
-            std::fs::canonicalize(&params.path)
-                .unwrap_or_else(|_| std::path::PathBuf::from(&params.path))
-                .to_string_lossy(),
-            format_str,
-            params.quality.unwrap_or(90)
-        );
+    let format_str = match params.format {
+        ExportFormat::Pdf => "pdf",
+        ExportFormat::Png => "png",
+        ExportFormat::Jpg => "jpg",
+        ExportFormat::Tiff => "tiff",
+        ExportFormat::Svg => "svg",
+    };
 
-        run_applescript(&script).await
-            .context(format!("エクスポートに失敗しました: {}", params.path))?;
+    let path = std::fs::canonicalize(&params.path)
+        .unwrap_or_else(|_| std::path::PathBuf::from(&params.path));
+    let options = applescript::record(&[("quality", applescript::integer(params.quality.unwrap_or(90)))])?;
+    let export = fill(
+        "export in file {} as {} with options {}",
+        &[applescript::string(&path.to_string_lossy()), applescript::string(format_str), options],
+    );
+    let script = with_front_document(&app_name, |s| s.line(export));
 
-        debug!(
-            function = "export",
-            path = %params.path,
-            "エクスポートしました"
-        );
+    backend.run(&script).await
+        .context(format!("エクスポートに失敗しました: {}", params.path))?;
+    resources::record_file(&params.path, "affinity.export");
 
-        Ok(ExportResult {
-            exported: true,
-            path: params.path,
-        })
-    }
+    debug!(
+        function = "export",
+        path = %params.path,
+        app = %app_name,
+        "エクスポートしました"
+    );
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではエクスポート機能は未実装です");
-        Ok(ExportResult {
-            exported: false,
-            path: params.path,
-        })
-    }
+    Ok(ExportResult {
+        exported: true,
+        path: params.path,
+    })
 }
 
 /**
@@ -407,7 +558,39 @@ pub struct ApplyFilterParams {
     pub filter_name: String,
     /// 強度（0-100）
     #[serde(default)]
+    #[schemars(range(min = 0, max = 100))]
     pub intensity: Option<u8>,
+    /// 対象のAffinityアプリ（省略時は最前面または起動中のアプリ）
+    #[serde(default)]
+    pub app: Option<AffinityApp>,
+}
+
+/**
+ * 補完候補として返すフィルター名（Affinity Photo のフィルターメニューの表記）
+ */
+const KNOWN_FILTERS: &[&str] = &[
+    "Gaussian Blur",
+    "Box Blur",
+    "Motion Blur",
+    "Radial Blur",
+    "Zoom Blur",
+    "Lens Blur",
+    "Unsharp Mask",
+    "Clarity",
+    "High Pass",
+    "Add Noise",
+    "Denoise",
+    "Vignette",
+    "Halftone",
+    "Diffuse Glow",
+    "Lighting",
+    "Twirl",
+    "Ripple",
+];
+
+/// filter_name の補完
+fn complete_filter_name(value: &str) -> Vec<String> {
+    completion::matching(KNOWN_FILTERS, value)
 }
 
 /**
@@ -421,6 +604,16 @@ pub struct ApplyFilterResult {
     pub filter_name: String,
 }
 
+impl ToolOutput for ApplyFilterResult {
+    fn summary(&self) -> String {
+        if self.applied {
+            format!("フィルター {} を適用しました", self.filter_name)
+        } else {
+            format!("フィルター {} は適用されませんでした", self.filter_name)
+        }
+    }
+}
+
 /**
  * フィルターを適用
  * 
@@ -433,56 +626,38 @@ pub struct ApplyFilterResult {
  * エラー:
  *   フィルター適用に失敗した場合はエラーを返す
  */
-pub async fn apply_filter(params: ApplyFilterParams) -> Result<ApplyFilterResult> {
+pub async fn apply_filter(backend: Arc<dyn ScriptBackend>, params: ApplyFilterParams) -> Result<ApplyFilterResult> {
     debug!(
         function = "apply_filter",
         filter_name = %params.filter_name,
         intensity = params.intensity,
+        app = ?params.app,
         "Affinityにフィルターを適用します"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        let script = format!(
-            r#"
-            tell application "Affinity Photo"
-                activate
-                if (count of documents) > 0 then
-                    tell front document
-                        -- フィルター適用の例（実際のAppleScriptコマンドはAffinityの実装に依存）
-                        log "フィルター {} を適用します"
-                    end tell
-                else
-                    error "開いているドキュメントがありません"
-                end if
-            end tell
-            "#,
-            params.filter_name
-        );
+    let app_name = resolve_app(backend.as_ref(), params.app.as_ref()).await?
+        .ok_or(ToolError::NoOpenDocument)?;
 
-        run_applescript(&script).await
-            .context(format!("フィルター適用に失敗しました: {}", params.filter_name))?;
+    let message = format!("フィルター {} を適用します", params.filter_name);
+    let script = with_front_document(&app_name, |s| {
+        // フィルター適用の例（実際のAppleScriptコマンドはAffinityの実装に依存）
+        s.line(fill("log {}", &[applescript::string(&message)]))
+    });
 
-        debug!(
-            function = "apply_filter",
-            filter_name = %params.filter_name,
-            "フィルターを適用しました"
-        );
+    backend.run(&script).await
+        .context(format!("フィルター適用に失敗しました: {}", params.filter_name))?;
 
-        Ok(ApplyFilterResult {
-            applied: true,
-            filter_name: params.filter_name,
-        })
-    }
+    debug!(
+        function = "apply_filter",
+        filter_name = %params.filter_name,
+        app = %app_name,
+        "フィルターを適用しました"
+    );
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではフィルター適用機能は未実装です");
-        Ok(ApplyFilterResult {
-            applied: false,
-            filter_name: params.filter_name,
-        })
-    }
+    Ok(ApplyFilterResult {
+        applied: true,
+        filter_name: params.filter_name,
+    })
 }
 
 /**
@@ -492,66 +667,99 @@ pub async fn apply_filter(params: ApplyFilterParams) -> Result<ApplyFilterResult
 pub struct ActiveDocumentInfo {
     /// ドキュメントが開いているかどうか
     pub is_open: bool,
+    /// 問い合わせたアプリ（Affinityアプリが起動していなければ None）
+    pub app: Option<String>,
     /// ドキュメント名
     pub name: Option<String>,
     /// ドキュメントパス
     pub path: Option<String>,
+    /// 未保存の変更があるかどうか
+    pub modified: Option<bool>,
+}
+
+impl ActiveDocumentInfo {
+    /// ドキュメントが開いていない状態
+    fn closed(app: Option<String>) -> Self {
+        Self {
+            is_open: false,
+            app,
+            name: None,
+            path: None,
+            modified: None,
+        }
+    }
+}
+
+impl ToolOutput for ActiveDocumentInfo {
+    fn summary(&self) -> String {
+        if self.is_open {
+            format!(
+                "アクティブなドキュメント: {}（{}、{}）",
+                self.name.as_deref().unwrap_or("名称未設定"),
+                self.path.as_deref().unwrap_or("未保存"),
+                self.app.as_deref().unwrap_or("Affinity")
+            )
+        } else {
+            "開いているドキュメントはありません".to_string()
+        }
+    }
 }
 
 /**
  * アクティブドキュメント情報を取得
  * 
+ * 引数:
+ *   params: 対象のアプリ（省略時は最前面または起動中のアプリ）
+ * 
  * 戻り値:
- *   Result<ActiveDocumentInfo> - ドキュメント情報
+ *   Result<ActiveDocumentInfo> - ドキュメント情報（Affinityアプリが起動していなければ開いていない状態）
  * 
  * エラー:
  *   情報取得に失敗した場合はエラーを返す
  */
-pub async fn get_active_document() -> Result<ActiveDocumentInfo> {
-    debug!(function = "get_active_document", "アクティブドキュメント情報を取得します");
-
-    #[cfg(target_os = "macos")]
-    {
-        let script = r#"
-            tell application "Affinity Photo"
-                if (count of documents) > 0 then
-                    tell front document
-                        set docName to name
-                        set docPath to path
-                        return docName & "|" & docPath
-                    end tell
-                else
-                    return "||"
-                end if
-            end tell
-        "#;
-
-        let result = run_applescript(script).await
-            .context("アクティブドキュメント情報取得に失敗しました")?;
-
-        if result == "||" {
-            Ok(ActiveDocumentInfo {
-                is_open: false,
-                name: None,
-                path: None,
-            })
-        } else {
-            let parts: Vec<&str> = result.split('|').collect();
-            Ok(ActiveDocumentInfo {
-                is_open: true,
-                name: parts.get(0).map(|s| s.to_string()),
-                path: parts.get(1).map(|s| s.to_string()),
-            })
-        }
+pub async fn get_active_document(backend: Arc<dyn ScriptBackend>, params: DocumentParams) -> Result<ActiveDocumentInfo> {
+    debug!(function = "get_active_document", app = ?params.app, "アクティブドキュメント情報を取得します");
+
+    match resolve_app(backend.as_ref(), params.app.as_ref()).await? {
+        Some(app_name) => active_document(backend.as_ref(), app_name).await,
+        None => Ok(ActiveDocumentInfo::closed(None)),
     }
+}
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではアクティブドキュメント取得機能は未実装です");
+/**
+ * 指定したアプリのアクティブドキュメント情報を取得
+ */
+async fn active_document(backend: &dyn ScriptBackend, app_name: String) -> Result<ActiveDocumentInfo> {
+    let script = Script::new()
+        .tell_application(&app_name, |s| {
+            s.if_else(
+                "(count of documents) > 0",
+                |s| {
+                    s.tell("front document", |s| {
+                        s.line("set docName to name")
+                            .line("set docPath to path")
+                            .line("set docModified to modified")
+                            .line("return docName & \"|\" & docPath & \"|\" & (docModified as string)")
+                    })
+                },
+                |s| s.line("return \"||\""),
+            )
+        })
+        .build();
+
+    let result = backend.run(&script).await
+        .context("アクティブドキュメント情報取得に失敗しました")?;
+
+    if result == "||" {
+        Ok(ActiveDocumentInfo::closed(Some(app_name)))
+    } else {
+        let parts: Vec<&str> = result.split('|').collect();
         Ok(ActiveDocumentInfo {
-            is_open: false,
-            name: None,
-            path: None,
+            is_open: true,
+            app: Some(app_name),
+            name: parts.first().map(|s| s.to_string()),
+            path: parts.get(1).map(|s| s.to_string()),
+            modified: parts.get(2).map(|s| s.trim() == "true"),
         })
     }
 }
@@ -563,47 +771,71 @@ pub async fn get_active_document() -> Result<ActiveDocumentInfo> {
 pub struct CloseDocumentResult {
     /// 閉じたかどうか
     pub closed: bool,
+    /// 対象のアプリ（Affinityアプリが起動していなければ None）
+    pub app: Option<String>,
+}
+
+impl ToolOutput for CloseDocumentResult {
+    fn summary(&self) -> String {
+        match (self.closed, &self.app) {
+            (true, Some(app)) => format!("{} のドキュメントを閉じました", app),
+            (true, None) => "ドキュメントを閉じました".to_string(),
+            (false, None) => "起動中のAffinityアプリがないため、閉じるドキュメントはありません".to_string(),
+            (false, Some(_)) => "ドキュメントは閉じられませんでした".to_string(),
+        }
+    }
 }
 
 /**
  * ドキュメントを閉じる
  * 
+ * 引数:
+ *   params: 対象のアプリ（省略時は最前面または起動中のアプリ）
+ * 
  * 戻り値:
- *   Result<CloseDocumentResult> - 実行結果
+ *   Result<CloseDocumentResult> - 実行結果（Affinityアプリが起動していなければ closed: false）
  * 
  * エラー:
- *   ドキュメントを閉じる処理に失敗した場合はエラーを返す
+ *   ドキュメントを閉じる処理に失敗した場合、閉じることが承認されなかった場合はエラーを返す
  */
-pub async fn close_document() -> Result<CloseDocumentResult> {
-    debug!(function = "close_document", "ドキュメントを閉じます");
-
-    #[cfg(target_os = "macos")]
-    {
-        let script = r#"
-            tell application "Affinity Photo"
-                if (count of documents) > 0 then
-                    close front document
-                end if
-            end tell
-        "#;
-
-        run_applescript(script).await
-            .context("ドキュメントを閉じる処理に失敗しました")?;
-
-        debug!(function = "close_document", "ドキュメントを閉じました");
-
-        Ok(CloseDocumentResult {
-            closed: true,
-        })
-    }
+pub async fn close_document(backend: Arc<dyn ScriptBackend>, params: DocumentParams) -> Result<CloseDocumentResult> {
+    debug!(function = "close_document", app = ?params.app, "ドキュメントを閉じます");
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではドキュメントを閉じる機能は未実装です");
-        Ok(CloseDocumentResult {
+    let Some(app_name) = resolve_app(backend.as_ref(), params.app.as_ref()).await? else {
+        return Ok(CloseDocumentResult {
             closed: false,
-        })
+            app: None,
+        });
+    };
+
+    // 未保存の変更が失われる場合は方針に従って確認する（確認と閉じる操作は同じアプリに対して行う）
+    let document = active_document(backend.as_ref(), app_name.clone()).await?;
+    if document.is_open {
+        let name = document.name.as_deref().unwrap_or("無題");
+        let unsaved = document.modified.unwrap_or(false);
+        let message = if unsaved {
+            format!("ドキュメント「{}」を閉じます。保存されていない変更は失われます。", name)
+        } else {
+            format!("ドキュメント「{}」を閉じます。", name)
+        };
+        confirm::confirm(&message, unsaved).await?;
     }
+
+    let script = Script::new()
+        .tell_application(&app_name, |s| {
+            s.if_then("(count of documents) > 0", |s| s.line("close front document"))
+        })
+        .build();
+
+    backend.run(&script).await
+        .context("ドキュメントを閉じる処理に失敗しました")?;
+
+    debug!(function = "close_document", app = %app_name, "ドキュメントを閉じました");
+
+    Ok(CloseDocumentResult {
+        closed: true,
+        app: Some(app_name),
+    })
 }
 
 /**
@@ -635,161 +867,88 @@ pub struct DrawPikachuResult {
     pub app: String,
 }
 
-/**
- * ピカチュウのSVGを生成してAffinityで開く
- */
-pub async fn draw_pikachu(params: DrawPikachuParams) -> Result<DrawPikachuResult> {
-    info!(
-        function = "draw_pikachu",
-        "ピカチュウを描画します"
-    );
-
-    #[cfg(target_os = "macos")]
-    {
-        let width = params.width.unwrap_or(800);
-        let height = params.height.unwrap_or(800);
-        
-        // 一時ファイルパスを生成
-        let output_path = if let Some(path) = params.output_path {
-            PathBuf::from(path)
+impl ToolOutput for DrawPikachuResult {
+    fn summary(&self) -> String {
+        if self.created {
+            format!("ピカチュウを {} に描画し、{} で開きました", self.file_path, self.app)
         } else {
-            let mut temp_path = std::env::temp_dir();
-            temp_path.push("pikachu.svg");
-            temp_path
-        };
-
-        // ピカチュウのSVGを生成
-        let svg_content = generate_pikachu_svg(width, height);
-        
-        // SVGファイルを保存
-        fs::write(&output_path, svg_content)
-            .context(format!("SVGファイルの保存に失敗しました: {}", output_path.display()))?;
-
-        info!(
-            svg_path = %output_path.display(),
-            "ピカチュウのSVGを生成しました"
-        );
-
-        // macOSのopenコマンドを使用してAffinityで開く（より確実）
-        let file_path = output_path.canonicalize()
-            .unwrap_or_else(|_| output_path.clone())
-            .to_string_lossy()
-            .to_string();
-        
-        // openコマンドでAffinityアプリで開く
-        let file_path_clone = file_path.clone();
-        let open_result = task::spawn_blocking(move || {
-            Command::new("open")
-                .arg("-a")
-                .arg("Affinity Photo")  // まずPhotoを試す
-                .arg(&file_path_clone)
-                .output()
-        })
-        .await
-        .context("openコマンドの実行待機に失敗しました")?
-        .context("openコマンドの実行に失敗しました")?;
-        
-        // Photoで開けなかった場合、Designerを試す
-        if !open_result.status.success() {
-            let file_path_clone2 = file_path.clone();
-            task::spawn_blocking(move || {
-                Command::new("open")
-                    .arg("-a")
-                    .arg("Affinity Designer")
-                    .arg(&file_path_clone2)
-                    .output()
-            })
-            .await
-            .context("openコマンドの実行待機に失敗しました")?
-            .context("openコマンドの実行に失敗しました")?;
+            format!("ピカチュウは描画されませんでした（{}）", self.app)
         }
-        
-        let app_name = "Affinity Photo/Designer";
-
-        info!(
-            function = "draw_pikachu",
-            path = %output_path.display(),
-            "ピカチュウをAffinityで開きました"
-        );
-
-        Ok(DrawPikachuResult {
-            created: true,
-            file_path: output_path.to_string_lossy().to_string(),
-            app: app_name.to_string(),
-        })
     }
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではピカチュウ描画機能は未実装です");
-        Ok(DrawPikachuResult {
-            created: false,
-            file_path: "".to_string(),
-            app: "Unsupported".to_string(),
-        })
+    fn links(&self) -> Vec<Content> {
+        if self.created {
+            vec![resources::file_link(&self.file_path)]
+        } else {
+            Vec::new()
+        }
     }
 }
 
 /**
- * 利用可能なAffinityアプリを検出
+ * ピカチュウのSVGを生成してAffinityで開く
  */
-#[cfg(target_os = "macos")]
-async fn detect_available_affinity_app() -> Option<String> {
-    // まず、アプリケーションがインストールされているか確認
-    let apps = vec!["Affinity Photo", "Affinity Designer", "Affinity Publisher"];
+pub async fn draw_pikachu(backend: Arc<dyn ScriptBackend>, params: DrawPikachuParams) -> Result<DrawPikachuResult> {
+    info!(
+        function = "draw_pikachu",
+        "ピカチュウを描画します"
+    );
+
+    let width = params.width.unwrap_or(800);
+    let height = params.height.unwrap_or(800);
     
-    for app in &apps {
-        let script = format!(
-            r#"
-            try
-                tell application "Finder"
-                    exists application file "{}" of folder "Applications" of startup disk
-                end tell
-            on error
-                false
-            end try
-            "#,
-            format!("{}:{}", app, app)
-        );
-        
-        match run_applescript(&script).await {
-            Ok(result) if result.trim() == "true" => {
-                return Some(app.to_string());
-            }
-            _ => {}
-        }
-    }
+    // 一時ファイルパスを生成（指定されたパスの既存ファイルは上書きの確認をする）
+    let output_path = if let Some(path) = params.output_path {
+        roots::check_paths(&[path.as_str()]).await?;
+        confirm_overwrite(&[path.as_str()]).await?;
+        PathBuf::from(path)
+    } else {
+        let mut temp_path = std::env::temp_dir();
+        temp_path.push("pikachu.svg");
+        temp_path
+    };
+
+    // ピカチュウのSVGを生成
+    let svg_content = generate_pikachu_svg(width, height);
     
-    // アプリケーションが起動しているか確認
-    for app in &apps {
-        let script = format!(
-            r#"
-            try
-                tell application "System Events"
-                    exists application process "{}"
-                end tell
-            on error
-                false
-            end try
-            "#,
-            app
-        );
-        
-        match run_applescript(&script).await {
-            Ok(result) if result.trim() == "true" => {
-                return Some(app.to_string());
-            }
-            _ => {}
+    // SVGファイルを保存
+    fs::write(&output_path, svg_content)
+        .context(format!("SVGファイルの保存に失敗しました: {}", output_path.display()))?;
+
+    info!(
+        svg_path = %output_path.display(),
+        "ピカチュウのSVGを生成しました"
+    );
+    resources::record_file(&output_path.to_string_lossy(), "affinity.draw_pikachu");
+
+    let file_path = output_path.canonicalize()
+        .unwrap_or_else(|_| output_path.clone())
+        .to_string_lossy()
+        .to_string();
+
+    // Affinityで開く（AppleScript経由にして他の操作と直列化し、カセットにも記録する）
+    // まずPhotoを試し、開けなかった場合はDesignerを試す
+    let app_name = match backend.run(&open_script("Affinity Photo", &file_path)).await {
+        Ok(_) => "Affinity Photo",
+        Err(e) if is_cancelled(&e) => return Err(e),
+        Err(_) => {
+            backend.run(&open_script("Affinity Designer", &file_path)).await
+                .context(format!("ピカチュウのSVGを開けませんでした: {}", file_path))?;
+            "Affinity Designer"
         }
-    }
-    
-    // デフォルトはPhoto（通常はインストールされている）
-    Some("Affinity Photo".to_string())
-}
+    };
 
-#[cfg(not(target_os = "macos"))]
-async fn detect_available_affinity_app() -> Option<String> {
-    None
+    info!(
+        function = "draw_pikachu",
+        path = %output_path.display(),
+        "ピカチュウをAffinityで開きました"
+    );
+
+    Ok(DrawPikachuResult {
+        created: true,
+        file_path: output_path.to_string_lossy().to_string(),
+        app: app_name.to_string(),
+    })
 }
 
 /**
@@ -923,6 +1082,7 @@ fn generate_pikachu_svg(width: u32, height: u32) -> String {
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct BatchOpenFilesParams {
     /// 開くファイルのパスリスト（最大16個まで）
+    #[schemars(length(max = 16))]
     pub paths: Vec<String>,
     /// 使用するAffinityアプリ（省略時は自動判定）
     #[serde(default)]
@@ -938,10 +1098,26 @@ pub struct BatchOpenFilesResult {
     pub success_count: usize,
     /// 失敗したファイル数
     pub failure_count: usize,
+    /// キャンセルにより開かなかったファイル数
+    pub cancelled_count: usize,
     /// 結果の詳細
     pub results: Vec<OpenFileResult>,
 }
 
+impl ToolOutput for BatchOpenFilesResult {
+    fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "{}件のファイルを開きました（失敗: {}件）",
+            self.success_count, self.failure_count
+        )];
+        if self.cancelled_count > 0 {
+            lines.push(format!("キャンセルにより{}件を中断しました", self.cancelled_count));
+        }
+        lines.extend(self.results.iter().map(|r| format!("- {}", r.summary())));
+        lines.join("\n")
+    }
+}
+
 /**
  * 複数のファイルを16並列で開く（自然言語: 「複数のファイルを同時に開いて」）
  * 
@@ -951,7 +1127,7 @@ pub struct BatchOpenFilesResult {
  * 戻り値:
  *   Result<BatchOpenFilesResult> - 実行結果
  */
-pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenFilesResult> {
+pub async fn batch_open_files(backend: Arc<dyn ScriptBackend>, params: BatchOpenFilesParams) -> Result<BatchOpenFilesResult> {
     info!(
         function = "batch_open_files",
         file_count = params.paths.len(),
@@ -960,12 +1136,22 @@ pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenF
 
     // 最大16並列に制限
     let paths: Vec<String> = params.paths.into_iter().take(16).collect();
+
+    // ルートの検証は開始前にまとめて行う（ルート外のパスを含むバッチは実行しない）
+    roots::check_paths(&paths.iter().map(String::as_str).collect::<Vec<_>>()).await?;
     
-    // 16並列でファイルを開く
+    // 16並列でファイルを開く（1件完了するごとに進捗を通知）
+    let progress = Progress::start(paths.len());
     let tasks: Vec<_> = paths.into_iter().map(|path| {
+        let backend = backend.clone();
         let app = params.app.clone();
+        let progress = &progress;
         async move {
-            open_file(OpenFileParams { path, app }).await
+            let result = open_file(backend, OpenFileParams { path: path.clone(), app }).await;
+            if !result.as_ref().err().is_some_and(is_cancelled) {
+                progress.advance(&path);
+            }
+            (path, result)
         }
     }).collect();
 
@@ -973,9 +1159,10 @@ pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenF
     
     let mut success_count = 0;
     let mut failure_count = 0;
+    let mut cancelled_count = 0;
     let mut file_results = Vec::new();
 
-    for result in results {
+    for (path, result) in results {
         match result {
             Ok(r) => {
                 if r.opened {
@@ -986,12 +1173,19 @@ pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenF
                 file_results.push(r);
             }
             Err(e) => {
-                error!(error = %e, "ファイルを開く処理でエラーが発生しました");
-                failure_count += 1;
+                // キャンセルで中断・未開始となった項目は失敗と区別して数える
+                let app = if is_cancelled(&e) {
+                    cancelled_count += 1;
+                    "Cancelled"
+                } else {
+                    error!(error = %e, "ファイルを開く処理でエラーが発生しました");
+                    failure_count += 1;
+                    "Error"
+                };
                 file_results.push(OpenFileResult {
                     opened: false,
-                    app: "Error".to_string(),
-                    path: "unknown".to_string(),
+                    app: app.to_string(),
+                    path,
                 });
             }
         }
@@ -1001,12 +1195,14 @@ pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenF
         function = "batch_open_files",
         success_count = success_count,
         failure_count = failure_count,
+        cancelled_count = cancelled_count,
         "16並列でのファイルオープン処理が完了しました"
     );
 
     Ok(BatchOpenFilesResult {
         success_count,
         failure_count,
+        cancelled_count,
         results: file_results,
     })
 }
@@ -1017,6 +1213,7 @@ pub async fn batch_open_files(params: BatchOpenFilesParams) -> Result<BatchOpenF
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct BatchExportParams {
     /// エクスポート設定のリスト（最大16個まで）
+    #[schemars(length(max = 16))]
     pub exports: Vec<ExportParams>,
 }
 
@@ -1029,10 +1226,30 @@ pub struct BatchExportResult {
     pub success_count: usize,
     /// 失敗したエクスポート数
     pub failure_count: usize,
+    /// キャンセルにより実行しなかったエクスポート数
+    pub cancelled_count: usize,
     /// 結果の詳細
     pub results: Vec<ExportResult>,
 }
 
+impl ToolOutput for BatchExportResult {
+    fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "{}件をエクスポートしました（失敗: {}件）",
+            self.success_count, self.failure_count
+        )];
+        if self.cancelled_count > 0 {
+            lines.push(format!("キャンセルにより{}件を中断しました", self.cancelled_count));
+        }
+        lines.extend(self.results.iter().map(|r| format!("- {}", r.summary())));
+        lines.join("\n")
+    }
+
+    fn links(&self) -> Vec<Content> {
+        self.results.iter().flat_map(|r| r.links()).collect()
+    }
+}
+
 /**
  * 複数のドキュメントを16並列でエクスポート（自然言語: 「複数のファイルを同時にエクスポートして」）
  * 
@@ -1042,7 +1259,7 @@ pub struct BatchExportResult {
  * 戻り値:
  *   Result<BatchExportResult> - 実行結果
  */
-pub async fn batch_export(params: BatchExportParams) -> Result<BatchExportResult> {
+pub async fn batch_export(backend: Arc<dyn ScriptBackend>, params: BatchExportParams) -> Result<BatchExportResult> {
     info!(
         function = "batch_export",
         export_count = params.exports.len(),
@@ -1051,11 +1268,24 @@ pub async fn batch_export(params: BatchExportParams) -> Result<BatchExportResult
 
     // 最大16並列に制限
     let exports: Vec<ExportParams> = params.exports.into_iter().take(16).collect();
+
+    // ルートの検証と上書きの確認は開始前にまとめて1回だけ行う
+    let paths: Vec<&str> = exports.iter().map(|e| e.path.as_str()).collect();
+    roots::check_paths(&paths).await?;
+    confirm_overwrite(&paths).await?;
     
-    // 16並列でエクスポート
+    // 16並列でエクスポート（1件完了するごとに進捗を通知）
+    let progress = Progress::start(exports.len());
     let tasks: Vec<_> = exports.into_iter().map(|export_params| {
+        let backend = backend.as_ref();
+        let progress = &progress;
         async move {
-            export(export_params).await
+            let path = export_params.path.clone();
+            let result = export_document(backend, export_params).await;
+            if !result.as_ref().err().is_some_and(is_cancelled) {
+                progress.advance(&path);
+            }
+            (path, result)
         }
     }).collect();
 
@@ -1063,9 +1293,10 @@ pub async fn batch_export(params: BatchExportParams) -> Result<BatchExportResult
     
     let mut success_count = 0;
     let mut failure_count = 0;
+    let mut cancelled_count = 0;
     let mut export_results = Vec::new();
 
-    for result in results {
+    for (path, result) in results {
         match result {
             Ok(r) => {
                 if r.exported {
@@ -1076,11 +1307,16 @@ pub async fn batch_export(params: BatchExportParams) -> Result<BatchExportResult
                 export_results.push(r);
             }
             Err(e) => {
-                error!(error = %e, "エクスポート処理でエラーが発生しました");
-                failure_count += 1;
+                // キャンセルで中断・未開始となった項目は失敗と区別して数える
+                if is_cancelled(&e) {
+                    cancelled_count += 1;
+                } else {
+                    error!(error = %e, "エクスポート処理でエラーが発生しました");
+                    failure_count += 1;
+                }
                 export_results.push(ExportResult {
                     exported: false,
-                    path: "unknown".to_string(),
+                    path,
                 });
             }
         }
@@ -1090,12 +1326,14 @@ pub async fn batch_export(params: BatchExportParams) -> Result<BatchExportResult
         function = "batch_export",
         success_count = success_count,
         failure_count = failure_count,
+        cancelled_count = cancelled_count,
         "16並列でのエクスポート処理が完了しました"
     );
 
     Ok(BatchExportResult {
         success_count,
         failure_count,
+        cancelled_count,
         results: export_results,
     })
 }
@@ -1157,143 +1395,92 @@ pub struct DrawShapeResult {
     pub shape_type: String,
 }
 
+impl ToolOutput for DrawShapeResult {
+    fn summary(&self) -> String {
+        if self.drawn {
+            format!("図形 {} を描画しました", self.shape_type)
+        } else {
+            format!("図形 {} は描画されませんでした", self.shape_type)
+        }
+    }
+}
+
 /**
  * Affinityアプリケーション内で図形を描画（自然言語: 「円を描いて」「矩形を作って」など）
  */
-pub async fn draw_shape(params: DrawShapeParams) -> Result<DrawShapeResult> {
+pub async fn draw_shape(backend: Arc<dyn ScriptBackend>, params: DrawShapeParams) -> Result<DrawShapeResult> {
     info!(
         function = "draw_shape",
         shape_type = ?params.shape_type,
         "Affinityで図形を描画します"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        // 起動中のAffinityアプリを検出、なければPhotoを起動
-        let app_name = detect_running_affinity_app().await
-            .unwrap_or_else(|| "Affinity".to_string());
-        
-        // アプリケーションが起動していない場合、起動を試みる
-        let process_name = if app_name == "Affinity" {
-            "Affinity".to_string()
-        } else {
-            app_name.replace("Affinity ", "")
-        };
-        
-        let app_name_for_launch = if app_name == "Affinity" {
-            "Affinity".to_string()
-        } else {
-            app_name.clone()
-        };
-        
-        let launch_script = format!(
-            r#"
-            tell application "System Events"
-                set processName to "{}"
-                set appName to "{}"
-                -- プロセス名で検索
-                set found to false
-                try
-                    set processList to name of every process whose name contains processName
-                    if (count of processList) > 0 then
-                        set found to true
-                    end if
-                end try
-                -- 見つからない場合、アプリケーションを起動
-                if not found then
-                    try
-                        tell application appName
-                            activate
-                        end tell
-                        delay 1.5
-                    on error
-                        -- Affinity.appとして起動を試みる
-                        try
-                            tell application "Affinity"
-                                activate
-                            end tell
-                            delay 1.5
-                        end try
-                    end try
-                end if
-            end tell
-            "#,
-            process_name,
-            app_name_for_launch
-        );
-        
-        // アプリケーションを起動
-        run_applescript(&launch_script).await
-            .context("Affinityアプリケーションの起動に失敗しました")?;
-        
-        let script = generate_shape_drawing_script(
-            &app_name,
-            &params,
-        )?;
-
-        run_applescript(&script).await
-            .context(format!("図形描画に失敗しました: {:?}", params.shape_type))?;
-
-        info!(
-            function = "draw_shape",
-            shape_type = ?params.shape_type,
-            "図形を描画しました"
-        );
+    // 起動中のAffinityアプリを検出（なければ統合版の Affinity）
+    let app_name = detect_running_affinity_app(backend.as_ref()).await?;
 
-        Ok(DrawShapeResult {
-            drawn: true,
-            shape_type: format!("{:?}", params.shape_type),
-        })
-    }
+    // アプリケーションが起動していない場合、起動を試みる
+    backend.run(&launch_script(&app_name, &app_name)).await
+        .context("Affinityアプリケーションの起動に失敗しました")?;
+    
+    let script = generate_shape_drawing_script(
+        &app_name,
+        &params,
+    )?;
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外では図形描画機能は未実装です");
-        Ok(DrawShapeResult {
-            drawn: false,
-            shape_type: format!("{:?}", params.shape_type),
-        })
-    }
+    backend.run(&script).await
+        .context(format!("図形描画に失敗しました: {:?}", params.shape_type))?;
+
+    info!(
+        function = "draw_shape",
+        shape_type = ?params.shape_type,
+        "図形を描画しました"
+    );
+
+    Ok(DrawShapeResult {
+        drawn: true,
+        shape_type: format!("{:?}", params.shape_type),
+    })
 }
 
 /**
- * 起動中のAffinityアプリを検出
+ * Affinityアプリが起動していなければ起動するAppleScriptを生成
  */
-#[cfg(target_os = "macos")]
-async fn detect_running_affinity_app() -> Option<String> {
-    let apps = vec!["Affinity Photo", "Affinity Designer", "Affinity Publisher"];
-    
-    for app in &apps {
-        let script = format!(
-            r#"
-            tell application "System Events"
-                set appList to name of every process whose name contains "{}"
-                if (count of appList) > 0 then
-                    return "{}"
-                end if
-            end tell
-            return ""
-            "#,
-            app.replace("Affinity ", ""),
-            app
-        );
-        
-        match run_applescript(&script).await {
-            Ok(result) if !result.trim().is_empty() && result.trim() != "false" && !result.trim().contains("error") => {
-                return Some(app.to_string());
-            }
-            _ => {}
-        }
-    }
-    
-    // アプリケーションが起動していない場合、デフォルトでPhotoを返す（起動を試みる）
-    // Affinity.appが存在する場合は、それを優先
-    Some("Affinity".to_string())
+fn launch_script(process_name: &str, app_name: &str) -> String {
+    let activate = |s: Script| s.line("activate");
+    Script::new()
+        .tell_application("System Events", |s| {
+            s.line(fill("set processName to {}", &[applescript::string(process_name)]))
+                .line(fill("set appName to {}", &[applescript::string(app_name)]))
+                // プロセス名で検索
+                .line("set found to false")
+                .try_block(|s| {
+                    s.line("set processList to name of every process whose name contains processName")
+                        .if_then("(count of processList) > 0", |s| s.line("set found to true"))
+                })
+                // 見つからない場合、アプリケーションを起動
+                .if_then("not found", |s| {
+                    s.try_catch(
+                        |s| s.tell("application appName", activate).line("delay 1.5"),
+                        // Affinity.appとして起動を試みる
+                        |s| s.try_block(|s| s.tell_application("Affinity", activate).line("delay 1.5")),
+                    )
+                })
+        })
+        .build()
 }
 
-#[cfg(not(target_os = "macos"))]
-async fn detect_running_affinity_app() -> Option<String> {
-    None
+/**
+ * 起動中のAffinityアプリを検出（最前面のアプリを優先。resolve_app 参照）
+ *
+ * 戻り値:
+ *   Result<String> - System Events のプロセス名（例: Affinity Photo 2）。
+ *   tell application にもそのまま使える。起動中のアプリがなければ統合版の Affinity
+ *
+ * エラー:
+ *   起動中のアプリを取得できない場合（キャンセル・System Events の権限がない場合など）はエラーを返す
+ */
+async fn detect_running_affinity_app(backend: &dyn ScriptBackend) -> Result<String> {
+    Ok(resolve_app(backend, None).await?.unwrap_or_else(|| AffinityApp::Affinity.app_name().to_string()))
 }
 
 /**
@@ -1302,121 +1489,87 @@ async fn detect_running_affinity_app() -> Option<String> {
  * 注意: AffinityのAppleScript APIは限定的なため、キーボードショートカットと
  * System Eventsを使用してUI操作をシミュレートします。
  */
-#[cfg(target_os = "macos")]
 fn generate_shape_drawing_script(app_name: &str, params: &DrawShapeParams) -> Result<String> {
     let x = params.x.unwrap_or(100.0);
     let y = params.y.unwrap_or(100.0);
     let width = params.width.unwrap_or(200.0);
     let height = params.height.unwrap_or(200.0);
     let _color = params.color.as_deref().unwrap_or("#FFD700");
-    
-    // Affinity.appの場合は、実際のプロセス名を取得
-    let process_name = if app_name == "Affinity" {
-        "Affinity".to_string()
-    } else {
-        app_name.replace("Affinity ", "")
-    };
-    
+    // 検出したアプリ名は System Events のプロセス名でもある
+    let process_name = app_name;
+
     // Affinity Designer/Photoでは、キーボードショートカットとUI操作を使用
     let script = match params.shape_type {
         ShapeType::Circle => {
             // 楕円ツールを使用（Affinity Designer/Photo: Mキー）
-            format!(
-                r#"
-                tell application "{}"
-                    activate
-                end tell
-                delay 0.8
-                tell application "System Events"
-                    tell process "{}"
-                        -- 楕円ツールを選択（Mキー）
-                        key code 46
-                        delay 0.5
-                        -- キャンバス上でクリック＆ドラッグで円を描画
-                        -- 注意: 実際の座標での描画はマウス操作が必要
-                        log "Circle tool activated. Click at ({}, {}) and drag to draw circle with radius {}"
-                    end tell
-                end tell
-                "#,
-                app_name, 
-                process_name,
+            let message = format!(
+                "Circle tool activated. Click at ({}, {}) and drag to draw circle with radius {}",
                 x, y, (width.min(height)) / 2.0
-            )
+            );
+            ui_script(app_name, process_name, "delay 0.8", |s| {
+                // 楕円ツールを選択（Mキー）
+                // キャンバス上でクリック＆ドラッグで円を描画（実際の座標での描画はマウス操作が必要）
+                s.line("key code 46")
+                    .line("delay 0.5")
+                    .line(fill("log {}", &[applescript::string(&message)]))
+            })
         }
         ShapeType::Rectangle => {
             // 矩形ツールを使用（Affinity Designer/Photo: Mキーでツールを切り替え）
-            format!(
-                r#"
-                tell application "{}"
-                    activate
-                end tell
-                delay 0.8
-                tell application "System Events"
-                    tell process "{}"
-                        -- 矩形ツールを選択（Mキーでツールを切り替え）
-                        key code 46
-                        delay 0.5
-                        log "Rectangle tool activated. Click at ({}, {}) and drag to ({}, {})"
-                    end tell
-                end tell
-                "#,
-                app_name,
-                process_name,
+            let message = format!(
+                "Rectangle tool activated. Click at ({}, {}) and drag to ({}, {})",
                 x, y, x + width, y + height
-            )
+            );
+            ui_script(app_name, process_name, "delay 0.8", |s| {
+                s.line("key code 46")
+                    .line("delay 0.5")
+                    .line(fill("log {}", &[applescript::string(&message)]))
+            })
         }
         ShapeType::Ellipse => {
             // 楕円ツールを使用
-            format!(
-                r#"
-                tell application "{}"
-                    activate
-                end tell
-                delay 0.8
-                tell application "System Events"
-                    tell process "{}"
-                        -- 楕円ツールを選択
-                        key code 46
-                        delay 0.5
-                        log "Ellipse tool activated. Click at ({}, {}) and drag to ({}, {})"
-                    end tell
-                end tell
-                "#,
-                app_name,
-                process_name,
+            let message = format!(
+                "Ellipse tool activated. Click at ({}, {}) and drag to ({}, {})",
                 x, y, x + width, y + height
-            )
+            );
+            ui_script(app_name, process_name, "delay 0.8", |s| {
+                s.line("key code 46")
+                    .line("delay 0.5")
+                    .line(fill("log {}", &[applescript::string(&message)]))
+            })
         }
         ShapeType::Line => {
-            // ペンツールまたはラインツールを使用
-            format!(
-                r#"
-                tell application "{}"
-                    activate
-                end tell
-                delay 0.8
-                tell application "System Events"
-                    tell process "{}"
-                        -- ペンツールを選択（Pキー）
-                        key code 35
-                        delay 0.5
-                        log "Pen tool activated. Click at ({}, {}) then at ({}, {}) to draw line"
-                    end tell
-                end tell
-                "#,
-                app_name,
-                process_name,
+            // ペンツールまたはラインツールを使用（Pキー）
+            let message = format!(
+                "Pen tool activated. Click at ({}, {}) then at ({}, {}) to draw line",
                 x, y, x + width, y + height
-            )
+            );
+            ui_script(app_name, process_name, "delay 0.8", |s| {
+                s.line("key code 35")
+                    .line("delay 0.5")
+                    .line(fill("log {}", &[applescript::string(&message)]))
+            })
         }
     };
     
     Ok(script)
 }
 
-#[cfg(not(target_os = "macos"))]
-fn generate_shape_drawing_script(_app_name: &str, _params: &DrawShapeParams) -> Result<String> {
-    anyhow::bail!("macOS以外では図形描画スクリプト生成は未実装です")
+/**
+ * アプリを前面に出してから System Events でプロセスを操作するAppleScriptを生成
+ *
+ * 引数:
+ *   app_name: 前面に出すアプリ
+ *   process_name: 操作するプロセス
+ *   wait: アプリを前面に出した後の待機（delay 文）
+ *   body: プロセスに対する操作
+ */
+fn ui_script(app_name: &str, process_name: &str, wait: &'static str, body: impl FnOnce(Script) -> Script) -> String {
+    Script::new()
+        .tell_application(app_name, |s| s.line("activate"))
+        .line(wait)
+        .tell_application("System Events", |s| s.tell_process(process_name, body))
+        .build()
 }
 
 /**
@@ -1449,71 +1602,54 @@ pub struct AddTextResult {
     pub added: bool,
 }
 
+impl ToolOutput for AddTextResult {
+    fn summary(&self) -> String {
+        if self.added {
+            "テキストを追加しました".to_string()
+        } else {
+            "テキストは追加されませんでした".to_string()
+        }
+    }
+}
+
 /**
  * Affinityアプリケーション内にテキストを追加（自然言語: 「テキストを追加して」「文字を書いて」など）
  */
-pub async fn add_text(params: AddTextParams) -> Result<AddTextResult> {
+pub async fn add_text(backend: Arc<dyn ScriptBackend>, params: AddTextParams) -> Result<AddTextResult> {
     info!(
         function = "add_text",
         text = %params.text,
         "Affinityにテキストを追加します"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        let app_name = detect_running_affinity_app().await
-            .unwrap_or_else(|| "Affinity Photo".to_string());
-        
-        let x = params.x.unwrap_or(100.0);
-        let y = params.y.unwrap_or(100.0);
-        let _font_size = params.font_size.unwrap_or(24.0);
-        let _color = params.color.as_deref().unwrap_or("#000000");
-        
-        let process_name = app_name.replace("Affinity ", "");
-        let script = format!(
-            r#"
-            tell application "{}"
-                activate
-            end tell
-            delay 0.5
-            tell application "System Events"
-                tell process "{}"
-                    -- テキストツールを選択（Tキー）
-                    key code 17
-                    delay 0.3
-                    -- キャンバス上でクリック
-                    -- 注意: 実際の座標でのクリックは座標変換が必要
-                    log "Text tool activated. Click at ({}, {}) to add text: \"{}\""
-                    -- テキストを入力（実際の入力は手動またはさらなるオートメーションが必要）
-                end tell
-            end tell
-            "#,
-            app_name,
-            process_name,
-            x, y, params.text
-        );
+    let app_name = detect_running_affinity_app(backend.as_ref()).await?;
 
-        run_applescript(&script).await
-            .context(format!("テキスト追加に失敗しました: {}", params.text))?;
+    let x = params.x.unwrap_or(100.0);
+    let y = params.y.unwrap_or(100.0);
+    let _font_size = params.font_size.unwrap_or(24.0);
+    let _color = params.color.as_deref().unwrap_or("#000000");
 
-        info!(
-            function = "add_text",
-            text = %params.text,
-            "テキストを追加しました"
-        );
+    let message = format!("Text tool activated. Click at ({}, {}) to add text: \"{}\"", x, y, params.text);
+    // テキストツールを選択（Tキー）してキャンバス上でクリックする
+    // 注意: 実際の座標でのクリックは座標変換が、テキストの入力は手動またはさらなるオートメーションが必要
+    let script = ui_script(&app_name, &app_name, "delay 0.5", |s| {
+        s.line("key code 17")
+            .line("delay 0.3")
+            .line(fill("log {}", &[applescript::string(&message)]))
+    });
 
-        Ok(AddTextResult {
-            added: true,
-        })
-    }
+    backend.run(&script).await
+        .context(format!("テキスト追加に失敗しました: {}", params.text))?;
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外ではテキスト追加機能は未実装です");
-        Ok(AddTextResult {
-            added: false,
-        })
-    }
+    info!(
+        function = "add_text",
+        text = %params.text,
+        "テキストを追加しました"
+    );
+
+    Ok(AddTextResult {
+        added: true,
+    })
 }
 
 /**
@@ -1537,63 +1673,51 @@ pub struct ChangeColorResult {
     pub changed: bool,
 }
 
+impl ToolOutput for ChangeColorResult {
+    fn summary(&self) -> String {
+        if self.changed {
+            "色を変更しました".to_string()
+        } else {
+            "色は変更されませんでした".to_string()
+        }
+    }
+}
+
 /**
  * Affinityアプリケーション内で色を変更（自然言語: 「色を黄色に変更して」「選択範囲を赤くして」など）
  */
-pub async fn change_color(params: ChangeColorParams) -> Result<ChangeColorResult> {
+pub async fn change_color(backend: Arc<dyn ScriptBackend>, params: ChangeColorParams) -> Result<ChangeColorResult> {
     info!(
         function = "change_color",
         color = %params.color,
         "Affinityで色を変更します"
     );
 
-    #[cfg(target_os = "macos")]
-    {
-        let app_name = detect_running_affinity_app().await
-            .unwrap_or_else(|| "Affinity Photo".to_string());
-        
-        let script = format!(
-            r#"
-            tell application "{}"
-                activate
-            end tell
-            delay 0.5
-            tell application "System Events"
-                tell process "{}"
-                    -- カラーパネルを開く（Cmd+Shift+C またはその他のショートカット）
-                    -- 実際の色変更はUI操作が必要
-                    log "Color change requested: color={}, fill_selection={}"
-                    -- 色の変更は実際のUI操作で実現する必要があります
-                end tell
-            end tell
-            "#,
-            app_name,
-            app_name.replace("Affinity ", ""),
-            params.color,
-            params.fill_selection.unwrap_or(false)
-        );
+    let app_name = detect_running_affinity_app(backend.as_ref()).await?;
 
-        run_applescript(&script).await
-            .context(format!("色変更に失敗しました: {}", params.color))?;
+    let message = format!(
+        "Color change requested: color={}, fill_selection={}",
+        params.color,
+        params.fill_selection.unwrap_or(false)
+    );
+    // カラーパネルを開く（Cmd+Shift+C またはその他のショートカット）
+    // 色の変更は実際のUI操作で実現する必要があります
+    let script = ui_script(&app_name, &app_name, "delay 0.5", |s| {
+        s.line(fill("log {}", &[applescript::string(&message)]))
+    });
 
-        info!(
-            function = "change_color",
-            color = %params.color,
-            "色を変更しました"
-        );
+    backend.run(&script).await
+        .context(format!("色変更に失敗しました: {}", params.color))?;
 
-        Ok(ChangeColorResult {
-            changed: true,
-        })
-    }
+    info!(
+        function = "change_color",
+        color = %params.color,
+        "色を変更しました"
+    );
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        error!("macOS以外では色変更機能は未実装です");
-        Ok(ChangeColorResult {
-            changed: false,
-        })
-    }
+    Ok(ChangeColorResult {
+        changed: true,
+    })
 }
 
 /**
@@ -1603,3 +1727,360 @@ pub async fn init_stub() -> anyhow::Result<()> {
     debug!("affinity bridge initialized. macOS: AppleScript support enabled. 16-parallel processing ready. Pikachu drawing ready. Shape drawing ready.");
     Ok(())
 }
+
+/**
+ * バックエンドを渡してツール関数を呼ぶハンドラーを作る
+ */
+fn with_backend<P, Fut>(
+    backend: &Arc<dyn ScriptBackend>,
+    tool: impl Fn(Arc<dyn ScriptBackend>, P) -> Fut + Send + Sync + 'static,
+) -> impl Fn(P) -> Fut + Send + Sync + 'static {
+    let backend = backend.clone();
+    move |params| tool(backend.clone(), params)
+}
+
+/**
+ * Affinityツールをレジストリに登録
+ * 
+ * 引数:
+ *   registry: 登録先のツールレジストリ
+ *   backend: AppleScript を実行するバックエンド（全ツールで共有）
+ * 
+ * エラー:
+ *   ツール名が重複している場合はエラーを返す
+ */
+pub fn register(registry: &mut ToolRegistry, backend: Arc<dyn ScriptBackend>) -> Result<()> {
+    // アノテーション: export 系・draw_pikachu は出力先の既存ファイルを上書きし、
+    // apply_filter・change_color は内容を書き換え、close_document は未保存の変更を失う可能性がある
+    // 実行時間の上限: 既定は60秒。書き出し・確認待ちのある操作は長め、バッチは全件分
+    let paths = Completer::Paths;
+    let apps = Completer::Values(completion::schema_values::<AffinityApp>);
+    registry.register(
+        ToolSpec::new(
+            "affinity.open_file",
+            "Affinityアプリケーションでファイルを開く（自然言語で「ファイルを開いて」などの指示に対応）",
+        )
+        .annotations(ToolAnnotations::mutating("ファイルを開く", false, true))
+        .complete("path", paths)
+        .complete("app", apps),
+        with_backend(&backend, open_file),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.create_new",
+            "新しいAffinityドキュメントを作成（自然言語で「新しいドキュメントを作成して」などの指示に対応）",
+        )
+        .annotations(ToolAnnotations::mutating("新規ドキュメント作成", false, false))
+        .complete("app", apps),
+        with_backend(&backend, create_new),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.export",
+            "現在開いているAffinityドキュメントをエクスポート（自然言語で「PDFでエクスポートして」などの指示に対応）",
+        )
+        .annotations(ToolAnnotations::mutating("エクスポート", true, true))
+        .timeout(Duration::from_secs(120))
+        .complete("path", paths)
+        .complete("format", Completer::Values(completion::schema_values::<ExportFormat>))
+        .complete("app", apps),
+        with_backend(&backend, export),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.apply_filter",
+            "画像にフィルターを適用（自然言語で「ぼかしを適用して」などの指示に対応）",
+        )
+        .annotations(ToolAnnotations::mutating("フィルター適用", true, false))
+        .timeout(Duration::from_secs(120))
+        .complete("filter_name", Completer::Values(complete_filter_name))
+        .complete("app", apps),
+        with_backend(&backend, apply_filter),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.get_active_document",
+            "現在アクティブなドキュメントの情報を取得",
+        )
+        .annotations(ToolAnnotations::read_only("アクティブドキュメント情報"))
+        .timeout(Duration::from_secs(15))
+        .complete("app", apps),
+        with_backend(&backend, get_active_document),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.close_document",
+            "現在開いているドキュメントを閉じる",
+        )
+        .annotations(ToolAnnotations::mutating("ドキュメントを閉じる", true, false))
+        .timeout(Duration::from_secs(120))
+        .complete("app", apps),
+        with_backend(&backend, close_document),
+    )?;
+
+    // 16並列バッチ処理ツール
+    registry.register(
+        ToolSpec::new(
+            "affinity.batch_open_files",
+            "複数のファイルを16並列で同時に開く（自然言語: 「複数のファイルを同時に開いて」など）",
+        )
+        .annotations(ToolAnnotations::mutating("複数ファイルを開く", false, true))
+        .timeout(Duration::from_secs(600))
+        .complete("app", apps),
+        with_backend(&backend, batch_open_files),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.batch_export",
+            "複数のドキュメントを16並列で同時にエクスポート（自然言語: 「複数のファイルを同時にエクスポートして」など）",
+        )
+        .annotations(ToolAnnotations::mutating("一括エクスポート", true, true))
+        .timeout(Duration::from_secs(600)),
+        with_backend(&backend, batch_export),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.draw_pikachu",
+            "ピカチュウを描画してAffinityで開く（自然言語: 「ピカチュウを描いて」「ピカチュウを作って」など）",
+        )
+        .annotations(ToolAnnotations::mutating("ピカチュウを描画", true, false))
+        .timeout(Duration::from_secs(120))
+        .complete("output_path", paths),
+        with_backend(&backend, draw_pikachu),
+    )?;
+
+    // 実際のAffinity操作ツール
+    registry.register(
+        ToolSpec::new(
+            "affinity.draw_shape",
+            "Affinityアプリケーション内で図形を描画（自然言語: 「円を描いて」「矩形を作って」「線を引いて」など）",
+        )
+        .annotations(ToolAnnotations::mutating("図形を描画", false, false))
+        .complete("shape_type", Completer::Values(completion::schema_values::<ShapeType>)),
+        with_backend(&backend, draw_shape),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.add_text",
+            "Affinityアプリケーション内にテキストを追加（自然言語: 「テキストを追加して」「文字を書いて」「ここにタイトルを書いて」など）",
+        )
+        .annotations(ToolAnnotations::mutating("テキストを追加", false, false)),
+        with_backend(&backend, add_text),
+    )?;
+    registry.register(
+        ToolSpec::new(
+            "affinity.change_color",
+            "Affinityアプリケーション内で色を変更（自然言語: 「色を黄色に変更して」「選択範囲を赤くして」など）",
+        )
+        .annotations(ToolAnnotations::mutating("色を変更", true, true)),
+        with_backend(&backend, change_color),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::backend::{CassetteBackend, MockBackend, ScriptOutput};
+    use crate::tools::cassette::{Cassette, Interaction};
+
+    /// スクリプトを比較しやすいよう各行の前後の空白と空行を除く
+    fn lines(script: &str) -> Vec<&str> {
+        script.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+    }
+
+    #[tokio::test]
+    async fn create_new_targets_the_requested_app() {
+        let backend = Arc::new(MockBackend::default());
+        let params = CreateNewParams {
+            app: AffinityApp::Designer,
+            width: Some(800),
+            height: Some(600),
+        };
+
+        let result = create_new(backend.clone(), params).await.unwrap();
+        assert!(result.created);
+        assert_eq!(
+            lines(&backend.scripts()[0]),
+            vec![
+                "tell application \"Affinity Designer\"",
+                "activate",
+                "make new document with properties {width:800, height:600}",
+                "end tell",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn close_document_checks_the_document_before_closing() {
+        let backend = Arc::new(
+            MockBackend::default()
+                .then(ScriptOutput::ok("Finder\nAffinity Designer"))
+                .then(ScriptOutput::ok("Poster.afdesign|/Users/a/Poster.afdesign|false")),
+        );
+
+        // 未保存の変更がないため確認なしで、起動中の Designer のドキュメントを閉じる
+        let result = close_document(backend.clone(), DocumentParams::default()).await.unwrap();
+        assert!(result.closed);
+        assert_eq!(result.app.as_deref(), Some("Affinity Designer"));
+        let scripts = backend.scripts();
+        assert_eq!(scripts.len(), 3);
+        for script in &scripts[1..] {
+            assert_eq!(lines(script)[0], "tell application \"Affinity Designer\"");
+        }
+        assert!(lines(&scripts[1]).contains(&"set docModified to modified"));
+        assert!(lines(&scripts[2]).contains(&"close front document"));
+    }
+
+    #[test]
+    fn the_frontmost_or_preferred_running_app_is_targeted() {
+        assert_eq!(pick_app("Affinity Publisher 2\nAffinity Photo 2\nAffinity Publisher 2").as_deref(), Some("Affinity Publisher 2"));
+        assert_eq!(pick_app("Finder\nAffinity Photo\nAffinity").as_deref(), Some("Affinity"));
+        assert_eq!(pick_app("Finder\nAffinity Designer 2").as_deref(), Some("Affinity Designer 2"));
+        assert_eq!(pick_app("Finder\nAffinity Photo Helper"), None);
+        assert_eq!(pick_app("Finder\n"), None);
+        assert_eq!(pick_app(""), None);
+    }
+
+    #[tokio::test]
+    async fn document_tools_use_the_requested_app_or_report_no_document() {
+        let backend = Arc::new(MockBackend::default().then(ScriptOutput::ok("||")));
+        let params = DocumentParams { app: Some(AffinityApp::Affinity) };
+        let document = get_active_document(backend.clone(), params).await.unwrap();
+        assert!(!document.is_open);
+        assert_eq!(lines(&backend.scripts()[0])[0], "tell application \"Affinity\"");
+
+        // 指定がなく Affinity アプリも起動していなければ Photo を起動せずにエラーにする
+        let backend = Arc::new(MockBackend::default().then(ScriptOutput::ok("Finder\n")));
+        let params = ApplyFilterParams {
+            filter_name: "Gaussian Blur".to_string(),
+            intensity: None,
+            app: None,
+        };
+        let err = apply_filter(backend.clone(), params).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::NoOpenDocument)));
+        assert_eq!(backend.scripts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ui_tools_drive_the_detected_process_or_stop_on_detection_errors() {
+        let params = || ChangeColorParams {
+            color: "#FF0000".to_string(),
+            fill_selection: None,
+        };
+
+        // バージョン番号付きのプロセス名をそのまま操作する
+        let backend = Arc::new(MockBackend::default().then(ScriptOutput::ok("Affinity Photo 2\nAffinity Photo 2")));
+        change_color(backend.clone(), params()).await.unwrap();
+        let scripts = backend.scripts();
+        let script = lines(&scripts[1]);
+        assert_eq!(script[0], "tell application \"Affinity Photo 2\"");
+        assert!(script.contains(&"tell process \"Affinity Photo 2\""), "{:?}", script);
+
+        // 権限がなく検出できなければ、既定のアプリを操作せずにエラーにする
+        let backend = Arc::new(
+            MockBackend::default().then(ScriptOutput::failed("execution error: Not authorized to send Apple events (-1743)")),
+        );
+        let err = change_color(backend.clone(), params()).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::PermissionDenied(_))));
+        assert_eq!(backend.scripts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn overwriting_an_existing_file_needs_confirmation() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-overwrite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("existing.png");
+        std::fs::write(&path, b"keep").unwrap();
+
+        // 既定の方針でも確認が必要で、確認できなければ書き出さない
+        let backend = Arc::new(MockBackend::default());
+        let params = ExportParams {
+            path: path.to_string_lossy().into_owned(),
+            format: ExportFormat::Png,
+            quality: None,
+            app: Some(AffinityApp::Photo),
+        };
+        let err = export(backend.clone(), params).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::NotConfirmed(_))));
+        assert!(backend.scripts().is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_export_runs_one_script_per_item() {
+        let backend = Arc::new(MockBackend::default());
+        let exports = ["a.pdf", "b.pdf"]
+            .iter()
+            .map(|name| ExportParams {
+                path: format!("/tmp/affinity-mcp-batch-test/{}", name),
+                format: ExportFormat::Pdf,
+                quality: Some(70),
+                app: Some(AffinityApp::Photo),
+            })
+            .collect();
+
+        let result = batch_export(backend.clone(), BatchExportParams { exports }).await.unwrap();
+        assert_eq!(result.success_count, 2);
+        let scripts = backend.scripts();
+        for (script, name) in scripts.iter().zip(["a.pdf", "b.pdf"]) {
+            let expected = format!(
+                "export in file \"/tmp/affinity-mcp-batch-test/{}\" as \"pdf\" with options {{quality:70}}",
+                name
+            );
+            assert!(lines(script).contains(&expected.as_str()), "{}", script);
+        }
+    }
+
+    #[tokio::test]
+    async fn arguments_cannot_inject_applescript() {
+        let backend = Arc::new(MockBackend::default());
+        let text = "\"\nend tell\nend tell\ndo shell script \"touch /tmp/pwned\" --";
+        let params = AddTextParams {
+            text: text.to_string(),
+            x: None,
+            y: None,
+            font_size: None,
+            color: None,
+        };
+        add_text(backend.clone(), params).await.unwrap();
+
+        // 検出用のスクリプトの後に実行したスクリプトも、行が増えずに値がリテラルに収まる
+        let scripts = backend.scripts();
+        let script = lines(scripts.last().unwrap());
+        assert_eq!(script.len(), 11, "{:?}", script);
+        assert!(script.iter().all(|line| !line.starts_with("do shell script")));
+        assert!(script.contains(
+            &r#"log "Text tool activated. Click at (100, 100) to add text: \"\"\nend tell\nend tell\ndo shell script \"touch /tmp/pwned\" --\"""#
+        ));
+    }
+
+    #[tokio::test]
+    async fn replayed_failures_keep_their_error_kind() {
+        let cassette = Cassette::replay(vec![Interaction::new(
+            r#"tell application "Affinity Photo"
+               activate
+               if (count of documents) > 0 then
+               tell front document
+               export in file "/tmp/affinity-cassette-test/out.png" as "png" with options {quality:90}
+               end tell
+               else
+               error "開いているドキュメントがありません"
+               end if
+               end tell"#,
+            ScriptOutput::failed("execution error: 開いているドキュメントがありません (-2700)"),
+        )]);
+        let backend = CassetteBackend::new(Arc::new(MockBackend::default()), cassette);
+
+        let params = ExportParams {
+            path: "/tmp/affinity-cassette-test/out.png".to_string(),
+            format: ExportFormat::Png,
+            quality: None,
+            app: Some(AffinityApp::Photo),
+        };
+        let err = export_document(&backend, params).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::NoOpenDocument)));
+    }
+}
@@ -0,0 +1,340 @@
+/**
+ * AppleScript ビルダー
+ *
+ * 概要:
+ *   ツールの引数（パス・フィルター名・テキスト・色など）を AppleScript に埋め込むときに、
+ *   値が文字列リテラルの外に出てスクリプトとして解釈されないようにする。
+ *   スクリプトは Code（ソース片）だけを組み合わせて作り、Code は次のいずれかからしか作れない。
+ *     - &'static str: ソースコードに書いた定数（信頼できる構文）
+ *     - string(): エスケープした文字列リテラル
+ *     - integer(): 整数リテラル
+ *     - Identifier: 検証済みの識別子
+ *     - record(): 識別子をキーにしたレコード
+ *     - fill(): 定数のテンプレートの {} を上記の値で置き換えたもの
+ *
+ * 主な仕様:
+ *   - 文字列リテラルは \ と " をエスケープし、改行・復帰・タブは \n \r \t にする
+ *     （リテラルは必ず1行に収まり、行を追加することもできない）
+ *   - 識別子は英字または _ で始まり英数字と _ だけからなる予約語以外の名前に限る
+ *   - Script は tell / if / try のブロックを字下げして組み立てる
+ *
+ * 制限事項:
+ *   - テンプレートの {} は値の位置を表すため、空のリストはテンプレートに書けない
+ */
+use anyhow::Result;
+use std::fmt;
+
+use crate::error::ToolError;
+
+/// 識別子として使えない AppleScript の予約語
+const RESERVED_WORDS: &[&str] = &[
+    "about", "above", "after", "against", "and", "apart", "around", "as", "aside", "at", "back",
+    "before", "beginning", "behind", "below", "beneath", "beside", "between", "but", "by",
+    "considering", "contain", "contains", "continue", "copy", "div", "does", "eighth", "else", "end",
+    "equal", "equals", "error", "every", "exit", "false", "fifth", "first", "for", "fourth", "from",
+    "front", "get", "given", "global", "if", "ignoring", "in", "instead", "into", "is", "it", "its",
+    "last", "local", "me", "middle", "mod", "my", "ninth", "not", "of", "on", "onto", "or", "out",
+    "over", "prop", "property", "put", "ref", "reference", "repeat", "return", "returning", "script",
+    "second", "set", "seventh", "since", "sixth", "some", "tell", "tenth", "that", "the", "then",
+    "third", "through", "thru", "timeout", "times", "to", "transaction", "true", "try", "until",
+    "where", "while", "whose", "with", "without",
+];
+
+/**
+ * AppleScript のソース片
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code(String);
+
+impl From<&'static str> for Code {
+    fn from(source: &'static str) -> Self {
+        Code(source.to_string())
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/**
+ * 文字列リテラル
+ *
+ * 引数:
+ *   value: 埋め込む文字列（任意の内容）
+ *
+ * 戻り値:
+ *   Code - 引用符で囲んだリテラル（value がリテラルの外に出ることはない）
+ */
+pub fn string(value: &str) -> Code {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    Code(literal)
+}
+
+/// 整数リテラル
+pub fn integer(value: impl Into<i64>) -> Code {
+    Code(value.into().to_string())
+}
+
+/**
+ * 検証済みの識別子
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identifier(String);
+
+impl Identifier {
+    /**
+     * 識別子を検証
+     *
+     * エラー:
+     *   英字または _ で始まり英数字と _ だけからなる名前でない場合、予約語の場合は
+     *   ToolError::InvalidParams を返す
+     */
+    pub fn new(name: &str) -> Result<Self> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid || RESERVED_WORDS.contains(&name.to_ascii_lowercase().as_str()) {
+            return Err(ToolError::InvalidParams(format!("AppleScriptの識別子として使えない名前です: {}", name)).into());
+        }
+        Ok(Identifier(name.to_string()))
+    }
+}
+
+impl From<Identifier> for Code {
+    fn from(identifier: Identifier) -> Self {
+        Code(identifier.0)
+    }
+}
+
+/**
+ * レコード（{key:value, ...}）
+ *
+ * エラー:
+ *   キーが識別子として不正な場合はエラーを返す
+ */
+pub fn record(fields: &[(&str, Code)]) -> Result<Code> {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| Ok(format!("{}:{}", Code::from(Identifier::new(key)?), value)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Code(format!("{{{}}}", fields.join(", "))))
+}
+
+/**
+ * 定数のテンプレートの {} を順に値で置き換える
+ *
+ * 引数:
+ *   template: ソースコードに書いた定数（{} の数と values の数は一致させる）
+ *   values: 埋め込む値
+ */
+pub fn fill(template: &'static str, values: &[Code]) -> Code {
+    let pieces: Vec<&str> = template.split("{}").collect();
+    assert_eq!(pieces.len(), values.len() + 1, "テンプレートの {{}} と値の数が一致しません: {}", template);
+    let mut source = pieces[0].to_string();
+    for (value, piece) in values.iter().zip(&pieces[1..]) {
+        source.push_str(&value.0);
+        source.push_str(piece);
+    }
+    Code(source)
+}
+
+/**
+ * 字下げしながら組み立てるスクリプト
+ */
+#[derive(Debug, Default)]
+pub struct Script {
+    lines: Vec<String>,
+    depth: usize,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 1行（文）を追加
+    pub fn line(mut self, code: impl Into<Code>) -> Self {
+        self.lines.push(format!("{}{}", "    ".repeat(self.depth), code.into()));
+        self
+    }
+
+    /// 開始行と終了行で囲んだブロックを追加
+    fn block(self, open: Code, close: &'static str, body: impl FnOnce(Self) -> Self) -> Self {
+        let mut script = self.line(open);
+        script.depth += 1;
+        let mut script = body(script);
+        script.depth -= 1;
+        script.line(close)
+    }
+
+    /// tell <対象> ... end tell
+    pub fn tell(self, target: impl Into<Code>, body: impl FnOnce(Self) -> Self) -> Self {
+        self.block(fill("tell {}", &[target.into()]), "end tell", body)
+    }
+
+    /// tell application "<名前>" ... end tell
+    pub fn tell_application(self, name: &str, body: impl FnOnce(Self) -> Self) -> Self {
+        self.tell(fill("application {}", &[string(name)]), body)
+    }
+
+    /// tell process "<名前>" ... end tell（System Events の中で使う）
+    pub fn tell_process(self, name: &str, body: impl FnOnce(Self) -> Self) -> Self {
+        self.tell(fill("process {}", &[string(name)]), body)
+    }
+
+    /// if <条件> then ... else ... end if
+    pub fn if_else(
+        self,
+        condition: impl Into<Code>,
+        then: impl FnOnce(Self) -> Self,
+        otherwise: impl FnOnce(Self) -> Self,
+    ) -> Self {
+        self.block(fill("if {} then", &[condition.into()]), "end if", |script| {
+            let mut script = then(script);
+            script.depth -= 1;
+            let mut script = script.line("else");
+            script.depth += 1;
+            otherwise(script)
+        })
+    }
+
+    /// if <条件> then ... end if
+    pub fn if_then(self, condition: impl Into<Code>, then: impl FnOnce(Self) -> Self) -> Self {
+        self.block(fill("if {} then", &[condition.into()]), "end if", then)
+    }
+
+    /// try ... end try（エラーは無視する）
+    pub fn try_block(self, body: impl FnOnce(Self) -> Self) -> Self {
+        self.block(Code::from("try"), "end try", body)
+    }
+
+    /// try ... on error ... end try
+    pub fn try_catch(self, body: impl FnOnce(Self) -> Self, on_error: impl FnOnce(Self) -> Self) -> Self {
+        self.block(Code::from("try"), "end try", |script| {
+            let mut script = body(script);
+            script.depth -= 1;
+            let mut script = script.line("on error");
+            script.depth += 1;
+            on_error(script)
+        })
+    }
+
+    /// スクリプトの文字列
+    pub fn build(self) -> String {
+        let mut source = self.lines.join("\n");
+        source.push('\n');
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /**
+     * ソースの先頭の文字列リテラルを読む（AppleScript の字句規則）
+     *
+     * 戻り値:
+     *   Option<(String, usize)> - リテラルの内容と、リテラルの直後の位置
+     */
+    fn read_literal(source: &str) -> Option<(String, usize)> {
+        let mut chars = source.char_indices();
+        if chars.next()?.1 != '"' {
+            return None;
+        }
+        let mut value = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((value, i + 1)),
+                '\\' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    other => other,
+                }),
+                c => value.push(c),
+            }
+        }
+        None
+    }
+
+    proptest! {
+        #[test]
+        fn no_string_escapes_its_literal(value in any::<String>()) {
+            let literal = string(&value).to_string();
+            let (read, end) = read_literal(&literal).expect("リテラルが閉じていません");
+            prop_assert_eq!(end, literal.len());
+            prop_assert_eq!(read, value);
+            prop_assert!(!literal.contains('\n') && !literal.contains('\r'));
+        }
+
+        #[test]
+        fn quotes_and_backslashes_stay_inside_templates(value in "[\"\\\\\n a-z]{0,40}") {
+            let script = Script::new()
+                .tell_application("Affinity Photo", |s| s.line(fill("log {}", &[string(&value)])))
+                .build();
+            let lines: Vec<&str> = script.lines().collect();
+            prop_assert_eq!(lines.len(), 3);
+            let log = lines[1].trim_start().strip_prefix("log ").unwrap();
+            let (read, end) = read_literal(log).unwrap();
+            prop_assert_eq!(end, log.len());
+            prop_assert_eq!(read, value);
+        }
+
+        #[test]
+        fn identifiers_are_plain_words(name in any::<String>()) {
+            if Identifier::new(&name).is_ok() {
+                prop_assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+                prop_assert!(!name.starts_with(|c: char| c.is_ascii_digit()));
+            }
+        }
+    }
+
+    #[test]
+    fn identifiers_reject_reserved_words_and_symbols() {
+        assert!(Identifier::new("quality").is_ok());
+        assert!(Identifier::new("doc_name2").is_ok());
+        for name in ["", "end", "Tell", "2d", "a b", "a\"", "|x|", "quality:1}"] {
+            assert!(Identifier::new(name).is_err(), "{}", name);
+        }
+        assert!(record(&[("quality:0} & (do shell script \"x\") & {a", integer(1))]).is_err());
+    }
+
+    #[test]
+    fn blocks_are_indented() {
+        let script = Script::new()
+            .tell_application("Affinity \"Photo\"", |s| {
+                s.if_else(
+                    "(count of documents) > 0",
+                    |s| s.line(fill("export as {} with options {}", &[string("png"), record(&[("quality", integer(90))]).unwrap()])),
+                    |s| s.line(fill("error {}", &[string("なし")])),
+                )
+            })
+            .build();
+        assert_eq!(
+            script,
+            "tell application \"Affinity \\\"Photo\\\"\"\n\
+             \x20   if (count of documents) > 0 then\n\
+             \x20       export as \"png\" with options {quality:90}\n\
+             \x20   else\n\
+             \x20       error \"なし\"\n\
+             \x20   end if\n\
+             end tell\n"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+/**
+ * スクリプト実行バックエンド
+ *
+ * 概要:
+ *   Affinity ツールが生成した AppleScript を実行する手段を抽象化する。
+ *   ツールはバックエンドを受け取ってスクリプトを渡すだけにし、
+ *   実行方法（osascript / JXA 経由 / テスト用のモック / カセット）と切り離す。
+ *
+ * 主な仕様:
+ *   - OsascriptBackend: osascript -e で AppleScript として実行する（既定、macOS のみ）
+ *   - AppleScriptViaJxaBackend: JXA（osascript -l JavaScript）の runScript に同じ AppleScript を渡して評価する
+ *     （スクリプト自体は AppleScript のままで、JXA で書かれたツールがあるわけではない）
+ *   - MockBackend: 受け取ったスクリプトを記録し、用意した結果を返す（テスト用）
+ *   - CassetteBackend: 記録・再生のためにほかのバックエンドを包む（tools::cassette 参照）
+ *   - 実行するバックエンドは AFFINITY_MCP_SCRIPT_BACKEND（osascript / applescript-via-jxa）で選ぶ
+ *   - osascript の実行はプロセス全体で直列化する（複数セッションから同時に呼ばれても
+ *     1つずつ実行され、バッチ処理の各項目も受付は並行・実行は順番になる）
+ *
+ * エラー処理:
+ *   - 失敗したスクリプトは stderr から ToolError の種別を判定して返す
+ *   - macOS 以外で osascript を使うバックエンドは ToolError::UnsupportedPlatform を返す
+ */
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+
+use super::cassette::{self, Cassette};
+use super::context;
+use crate::error::ToolError;
+
+/**
+ * スクリプト1回分の実行結果
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptOutput {
+    /// 成功したかどうか
+    pub success: bool,
+    /// 標準出力（前後の空白を除く）
+    #[serde(default)]
+    pub stdout: String,
+    /// 標準エラー出力
+    #[serde(default)]
+    pub stderr: String,
+}
+
+// 結果を作るのは osascript を実行する経路（macOS）とテストのみ
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+impl ScriptOutput {
+    /// 成功した実行
+    pub fn ok(stdout: &str) -> Self {
+        Self {
+            success: true,
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        }
+    }
+
+    /// 失敗した実行
+    pub fn failed(stderr: &str) -> Self {
+        Self {
+            success: false,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    /**
+     * 標準出力、またはエラーに変換
+     *
+     * 引数:
+     *   script: 実行したスクリプト（ログとアプリ名の判定に使用）
+     *
+     * エラー:
+     *   失敗した実行は stderr から判定した ToolError（判定できなければ stderr）を返す
+     */
+    pub fn into_result(self, script: &str) -> Result<String> {
+        if self.success {
+            return Ok(self.stdout);
+        }
+        let stderr = self.stderr;
+        error!(
+            script = %script,
+            stderr = %stderr,
+            "AppleScript実行エラー"
+        );
+        // 既知のエラーは種別を付けて返す（stderr はコンテキストとして残す）
+        if let Some(kind) = ToolError::from_applescript_stderr(script, &stderr) {
+            return Err(anyhow::Error::new(kind).context(format!("AppleScript実行エラー: {}", stderr.trim())));
+        }
+        anyhow::bail!("AppleScript実行エラー: {}", stderr)
+    }
+}
+
+/**
+ * スクリプト実行バックエンド
+ */
+pub trait ScriptBackend: Send + Sync {
+    /// ログに表示する名前
+    fn name(&self) -> &'static str;
+
+    /**
+     * AppleScript を実行して結果をそのまま返す
+     *
+     * エラー:
+     *   実行できなかった場合（キャンセル・未対応のOSなど）はエラーを返す。
+     *   スクリプト自体の失敗は ScriptOutput::success で表す
+     */
+    fn execute<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<ScriptOutput>>;
+
+    /**
+     * AppleScript を実行して標準出力を返す
+     *
+     * エラー:
+     *   実行できなかった場合、スクリプトが失敗した場合はエラーを返す
+     */
+    fn run<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { self.execute(script).await?.into_result(script) })
+    }
+}
+
+/**
+ * osascript で AppleScript として実行するバックエンド
+ */
+#[derive(Debug, Default)]
+pub struct OsascriptBackend;
+
+impl ScriptBackend for OsascriptBackend {
+    fn name(&self) -> &'static str {
+        "osascript"
+    }
+
+    fn execute<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<ScriptOutput>> {
+        Box::pin(osascript(&[], script, script))
+    }
+}
+
+/**
+ * JXA（osascript -l JavaScript）を経由して AppleScript を実行するバックエンド
+ *
+ * 概要:
+ *   ツールが生成するのは常に AppleScript であり、このバックエンドもそれを JavaScript に
+ *   書き換えるわけではない。AppleScript のソースを JSON 文字列として JXA の文字列リテラルに
+ *   埋め込み、Standard Additions の runScript で AppleScript として評価する。
+ *   osascript の起動言語だけが異なるため、JavaScript の OSA コンポーネントを経由した
+ *   実行が必要な環境（自動化の許可が osascript -l JavaScript に与えられている場合など）向け。
+ *
+ * 主な仕様:
+ *   - AppleScript のエラーは JavaScript の例外として stderr に出る（種別の判定は osascript と同じ）
+ *   - AFFINITY_MCP_SCRIPT_BACKEND=applescript-via-jxa（互換のため jxa も可）で選ぶ
+ */
+#[derive(Debug, Default)]
+pub struct AppleScriptViaJxaBackend;
+
+impl AppleScriptViaJxaBackend {
+    /// AppleScript を runScript で評価する JXA スクリプト
+    fn wrap(script: &str) -> String {
+        // JSON の文字列リテラルは JavaScript の文字列リテラルとしてもそのまま使える
+        let source = serde_json::Value::String(script.to_string()).to_string();
+        format!(
+            "const app = Application.currentApplication();\n\
+             app.includeStandardAdditions = true;\n\
+             app.runScript({}, {{ in: \"AppleScript\" }});",
+            source
+        )
+    }
+}
+
+impl ScriptBackend for AppleScriptViaJxaBackend {
+    fn name(&self) -> &'static str {
+        "applescript-via-jxa"
+    }
+
+    fn execute<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<ScriptOutput>> {
+        Box::pin(async move { osascript(&["-l", "JavaScript"], &Self::wrap(script), script).await })
+    }
+}
+
+/// osascript の実行をプロセス全体で直列化するロック（全セッション・全トランスポートで共有）
+#[cfg(target_os = "macos")]
+static OSASCRIPT_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/**
+ * osascript を実行
+ *
+ * 引数:
+ *   args: -e より前に渡す引数（言語の指定など）
+ *   source: osascript に渡すソース
+ *   script: ログに出す元の AppleScript
+ */
+#[cfg(target_os = "macos")]
+async fn osascript(args: &[&str], source: &str, script: &str) -> Result<ScriptOutput> {
+    use anyhow::Context;
+    use std::process::Stdio;
+    use tracing::info;
+
+    // 同じアプリへの UI 操作が混ざらないよう、osascript は1つずつ実行する
+    // （待機中にキャンセルされた場合は実行せずに終了する）
+    let _bridge = tokio::select! {
+        guard = OSASCRIPT_LOCK.lock() => guard,
+        err = context::cancelled() => return Err(err),
+    };
+
+    // キャンセル済みの呼び出しでは osascript を起動しない
+    context::check_cancelled()?;
+
+    // kill_on_drop: キャンセル・タイムアウトで待機 future を破棄したときに子プロセスも終了させる
+    let child = tokio::process::Command::new("osascript")
+        .args(args)
+        .arg("-e")
+        .arg(source)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("osascriptコマンドの実行に失敗しました")?;
+
+    let output = tokio::select! {
+        output = child.wait_with_output() => output.context("osascriptの完了待機に失敗しました")?,
+        err = context::cancelled() => {
+            info!(script = %script, "キャンセルされたためosascriptを終了しました");
+            return Err(err);
+        }
+    };
+
+    if output.status.success() {
+        Ok(ScriptOutput::ok(String::from_utf8_lossy(&output.stdout).trim()))
+    } else {
+        Ok(ScriptOutput::failed(&String::from_utf8_lossy(&output.stderr)))
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+async fn osascript(_args: &[&str], _source: &str, _script: &str) -> Result<ScriptOutput> {
+    context::check_cancelled()?;
+    Err(ToolError::UnsupportedPlatform("AppleScriptはmacOSでのみ利用可能です".to_string()).into())
+}
+
+/**
+ * カセットに記録・再生するバックエンド
+ *
+ * 概要:
+ *   再生中は内側のバックエンドを呼ばずに記録した結果を返し、
+ *   記録中は内側のバックエンドの結果をカセットに追記する。
+ */
+pub struct CassetteBackend {
+    inner: Arc<dyn ScriptBackend>,
+    cassette: Cassette,
+}
+
+impl CassetteBackend {
+    pub fn new(inner: Arc<dyn ScriptBackend>, cassette: Cassette) -> Self {
+        Self { inner, cassette }
+    }
+}
+
+impl ScriptBackend for CassetteBackend {
+    fn name(&self) -> &'static str {
+        "cassette"
+    }
+
+    fn execute<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<ScriptOutput>> {
+        Box::pin(async move {
+            if self.cassette.mode() == cassette::CassetteMode::Replay {
+                context::check_cancelled()?;
+                return self.cassette.take(script);
+            }
+            let output = self.inner.execute(script).await?;
+            self.cassette.record(script, &output)?;
+            Ok(output)
+        })
+    }
+}
+
+/**
+ * 環境変数の設定でバックエンドを作成
+ *
+ * 概要:
+ *   AFFINITY_MCP_SCRIPT_BACKEND（osascript / applescript-via-jxa、既定: osascript）で選び、
+ *   AFFINITY_MCP_CASSETTE が設定されていればカセットで包む。
+ *
+ * エラー:
+ *   未知のバックエンド名、カセットの設定が不正な場合はエラーを返す
+ */
+pub fn from_env() -> Result<Arc<dyn ScriptBackend>> {
+    let backend: Arc<dyn ScriptBackend> = match std::env::var("AFFINITY_MCP_SCRIPT_BACKEND")
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+        .as_str()
+    {
+        "" | "osascript" | "applescript" => Arc::new(OsascriptBackend),
+        "applescript-via-jxa" | "jxa" => Arc::new(AppleScriptViaJxaBackend),
+        other => anyhow::bail!(
+            "AFFINITY_MCP_SCRIPT_BACKEND は osascript または applescript-via-jxa を指定してください: {}",
+            other
+        ),
+    };
+    Ok(match cassette::from_env()? {
+        Some(cassette) => Arc::new(CassetteBackend::new(backend, cassette)),
+        None => backend,
+    })
+}
+
+/**
+ * 受け取ったスクリプトを記録し、用意した結果を順に返すバックエンド（テスト用）
+ *
+ * 概要:
+ *   用意した結果がなくなった後は空の標準出力で成功を返す。
+ */
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockBackend {
+    outputs: std::sync::Mutex<std::collections::VecDeque<ScriptOutput>>,
+    scripts: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    /// 次の実行で返す結果を追加
+    pub fn then(self, output: ScriptOutput) -> Self {
+        self.outputs.lock().unwrap().push_back(output);
+        self
+    }
+
+    /// これまでに実行したスクリプト
+    pub fn scripts(&self) -> Vec<String> {
+        self.scripts.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl ScriptBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn execute<'a>(&'a self, script: &'a str) -> BoxFuture<'a, Result<ScriptOutput>> {
+        self.scripts.lock().unwrap().push(script.to_string());
+        let output = self.outputs.lock().unwrap().pop_front().unwrap_or_else(|| ScriptOutput::ok(""));
+        Box::pin(async move { Ok(output) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failures_are_classified_from_stderr() {
+        let backend = MockBackend::default()
+            .then(ScriptOutput::ok("done"))
+            .then(ScriptOutput::failed("execution error: Not authorized to send Apple events (-1743)"));
+
+        assert_eq!(backend.run("return \"done\"").await.unwrap(), "done");
+        let err = backend.run("tell application \"Affinity Photo\" to activate").await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::PermissionDenied(_))));
+        assert_eq!(backend.scripts().len(), 2);
+    }
+
+    #[test]
+    fn jxa_embeds_the_applescript_as_a_string_literal() {
+        let wrapped = AppleScriptViaJxaBackend::wrap("display dialog \"a\\b\"\nreturn 1");
+        assert!(wrapped.contains(r#"app.runScript("display dialog \"a\\b\"\nreturn 1", { in: "AppleScript" });"#));
+    }
+
+    #[test]
+    fn jxa_literal_decodes_back_to_the_original_applescript() {
+        let script = "set p to \"C:\\tmp\\\"x\\\"\"\r\n\tdisplay dialog \"終了\u{2028}\" & \"</script>\"";
+        let wrapped = AppleScriptViaJxaBackend::wrap(script);
+        let literal = wrapped
+            .split_once("app.runScript(")
+            .and_then(|(_, rest)| rest.rsplit_once(", { in: \"AppleScript\" });"))
+            .map(|(literal, _)| literal)
+            .unwrap();
+        assert!(!literal.contains('\n') && !literal.contains('\r'));
+        assert_eq!(serde_json::from_str::<String>(literal).unwrap(), script);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[tokio::test]
+    async fn osascript_is_unsupported_off_macos() {
+        let err = OsascriptBackend.run("return 1").await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Some(ToolError::UnsupportedPlatform(_))));
+    }
+}
@@ -18,6 +18,9 @@ use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
 use tracing::debug;
 
+use crate::mcp::ToolAnnotations;
+use super::registry::{ToolOutput, ToolRegistry, ToolSpec};
+
 // ---- I/O スキーマ例 ----
 
 /**
@@ -50,9 +53,19 @@ pub struct CreateDesignOut {
     pub url: Option<String>,
 }
 
+impl ToolOutput for CreateDesignOut {
+    fn summary(&self) -> String {
+        match &self.url {
+            Some(url) => format!("Canvaデザイン {} を作成しました: {}", self.design_id, url),
+            None => format!("Canvaデザイン {} を作成しました", self.design_id),
+        }
+    }
+}
+
 /**
  * デザインエクスポートの入力パラメータ
  */
+#[allow(dead_code)] // SDK導入時にエクスポートツールとして登録予定
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ExportDesignIn {
     /// エクスポートするデザインID
@@ -64,6 +77,7 @@ pub struct ExportDesignIn {
 /**
  * エクスポートフォーマット
  */
+#[allow(dead_code)] // SDK導入時にエクスポートツールとして登録予定
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ExportFormat {
@@ -78,6 +92,7 @@ pub enum ExportFormat {
 /**
  * デザインエクスポートの出力結果
  */
+#[allow(dead_code)] // SDK導入時にエクスポートツールとして登録予定
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ExportDesignOut {
     /// エクスポートされたファイルのパス
@@ -100,6 +115,24 @@ pub async fn init_stub() -> anyhow::Result<()> {
     Ok(())
 }
 
+/**
+ * Canvaツールをレジストリに登録
+ * 
+ * 引数:
+ *   registry: 登録先のツールレジストリ
+ * 
+ * エラー:
+ *   ツール名が重複している場合はエラーを返す
+ */
+pub fn register(registry: &mut ToolRegistry) -> anyhow::Result<()> {
+    registry.register(
+        ToolSpec::new("canva.create_design", "Canvaでデザインを作成")
+            .annotations(ToolAnnotations::mutating("Canvaデザイン作成", false, false).open_world()),
+        create_design,
+    )?;
+    Ok(())
+}
+
 /**
  * Canvaデザインを作成
  * 
@@ -125,7 +158,7 @@ pub async fn create_design(params: CreateDesignIn) -> anyhow::Result<CreateDesig
     // TODO: 実際のCanva API呼び出しを実装
     // 現在はスタブ実装
     Ok(CreateDesignOut {
-        design_id: format!("demo-{}", uuid::Uuid::new_v4().to_string()),
+        design_id: format!("demo-{}", uuid::Uuid::new_v4()),
         url: None,
     })
 }
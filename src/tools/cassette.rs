@@ -0,0 +1,233 @@
+/**
+ * AppleScript の記録・再生（カセット）
+ *
+ * 概要:
+ *   スクリプト実行バックエンドに渡したスクリプトとその結果をカセットファイルに記録し、
+ *   後から同じスクリプトに対して記録した結果を返す（tools::backend::CassetteBackend）。
+ *   macOS で記録したカセットを使えば、osascript のない環境（Linux の CI など）でも
+ *   Affinity ツールの macOS 経路をそのまま実行できる。
+ *
+ * 主な仕様:
+ *   - AFFINITY_MCP_CASSETTE にカセットファイルのパスを指定すると有効になる
+ *   - AFFINITY_MCP_CASSETTE_MODE で動作を選ぶ
+ *     - replay（既定）: osascript を実行せず、記録した結果を返す（どのOSでも動作）
+ *     - record: osascript を実行し、スクリプトと結果を追記する（macOS のみ）
+ *   - スクリプトは各行の前後の空白と空行を除いた形で記録・照合する（インデントの差は無視）
+ *   - 同じスクリプトが複数回記録されている場合は記録した順に返す
+ *   - 失敗した実行も stderr ごと記録し、再生時は実行時と同じエラー種別になる
+ *
+ * エラー処理:
+ *   - 記録されていないスクリプト（または記録回数を超えた実行）はエラーにする
+ */
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use super::backend::ScriptOutput;
+
+/**
+ * カセットの動作
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// osascript を実行して記録する
+    Record,
+    /// 記録した結果を返す
+    Replay,
+}
+
+impl CassetteMode {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "replay" => Ok(CassetteMode::Replay),
+            "record" => Ok(CassetteMode::Record),
+            other => anyhow::bail!("AFFINITY_MCP_CASSETTE_MODE は record または replay を指定してください: {}", other),
+        }
+    }
+}
+
+/**
+ * 記録した1回の実行
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// 実行したスクリプト（正規化済み）
+    pub script: String,
+    /// 実行結果
+    #[serde(flatten)]
+    pub output: ScriptOutput,
+}
+
+impl Interaction {
+    pub fn new(script: &str, output: ScriptOutput) -> Self {
+        Self {
+            script: normalize(script),
+            output,
+        }
+    }
+}
+
+/**
+ * カセットファイルの内容
+ */
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+/**
+ * カセット
+ */
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    /// 記録先（None なら記録をファイルに書き出さない）
+    path: Option<PathBuf>,
+    /// 記録した実行と、再生済みかどうか
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Cassette {
+    /**
+     * 記録した実行を再生するカセット（テスト用）
+     */
+    #[cfg(test)]
+    pub fn replay(interactions: Vec<Interaction>) -> Self {
+        Self {
+            mode: CassetteMode::Replay,
+            path: None,
+            interactions: Mutex::new(interactions.into_iter().map(|i| (i, false)).collect()),
+        }
+    }
+
+    /**
+     * ファイルからカセットを開く
+     *
+     * 引数:
+     *   path: カセットファイルのパス
+     *   mode: record なら空のカセットで始めて実行のたびに書き出す
+     *
+     * エラー:
+     *   replay でファイルを読めない・形式が不正な場合はエラーを返す
+     */
+    pub fn open(path: PathBuf, mode: CassetteMode) -> Result<Self> {
+        let file = match mode {
+            CassetteMode::Replay => {
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("カセットを読み込めません: {}", path.display()))?;
+                serde_json::from_str::<CassetteFile>(&text)
+                    .with_context(|| format!("カセットの形式が不正です: {}", path.display()))?
+            }
+            CassetteMode::Record => CassetteFile::default(),
+        };
+        Ok(Self {
+            mode,
+            path: Some(path),
+            interactions: Mutex::new(file.interactions.into_iter().map(|i| (i, false)).collect()),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /**
+     * スクリプトに対応する記録を取り出す（記録順に1回ずつ）
+     *
+     * エラー:
+     *   記録されていないスクリプトの場合はエラーを返す
+     */
+    pub fn take(&self, script: &str) -> Result<ScriptOutput> {
+        let script = normalize(script);
+        let mut interactions = self.interactions.lock().unwrap_or_else(|e| e.into_inner());
+        let Some((interaction, used)) = interactions.iter_mut().find(|(i, used)| !used && i.script == script) else {
+            anyhow::bail!("カセットに記録されていないAppleScriptです:\n{}", script);
+        };
+        *used = true;
+        debug!(success = interaction.output.success, "カセットからAppleScriptの結果を再生しました");
+        Ok(interaction.output.clone())
+    }
+
+    /**
+     * 実行結果を記録してファイルに書き出す
+     */
+    pub fn record(&self, script: &str, output: &ScriptOutput) -> Result<()> {
+        let mut interactions = self.interactions.lock().unwrap_or_else(|e| e.into_inner());
+        interactions.push((Interaction::new(script, output.clone()), true));
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = CassetteFile {
+            interactions: interactions.iter().map(|(i, _)| i.clone()).collect(),
+        };
+        let text = serde_json::to_string_pretty(&file).context("カセットのシリアライズに失敗しました")?;
+        std::fs::write(path, text).with_context(|| format!("カセットを書き出せません: {}", path.display()))
+    }
+}
+
+/**
+ * 環境変数の設定でカセットを開く
+ *
+ * 戻り値:
+ *   Result<Option<Cassette>> - AFFINITY_MCP_CASSETTE が未設定なら None
+ *
+ * エラー:
+ *   動作の指定が不正な場合、再生するカセットを読めない場合はエラーを返す
+ */
+pub fn from_env() -> Result<Option<Cassette>> {
+    let Some(path) = std::env::var_os("AFFINITY_MCP_CASSETTE").filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let mode = CassetteMode::parse(&std::env::var("AFFINITY_MCP_CASSETTE_MODE").unwrap_or_default())?;
+    let cassette = Cassette::open(PathBuf::from(path), mode)?;
+    info!(mode = ?mode, "AppleScriptのカセットを有効にしました");
+    Ok(Some(cassette))
+}
+
+/// 照合用にスクリプトを正規化（各行の前後の空白と空行を除く）
+fn normalize(script: &str) -> String {
+    script
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_are_replayed_in_recorded_order() {
+        let cassette = Cassette::replay(vec![
+            Interaction::new("tell application \"Affinity Photo\"\n  activate\nend tell", ScriptOutput::ok("1")),
+            Interaction::new("tell application \"Affinity Photo\"\n  activate\nend tell", ScriptOutput::ok("2")),
+        ]);
+
+        // インデントが違っても同じスクリプトとして照合する
+        let script = "\n    tell application \"Affinity Photo\"\n        activate\n    end tell\n";
+        assert_eq!(cassette.take(script).unwrap().stdout, "1");
+        assert_eq!(cassette.take(script).unwrap().stdout, "2");
+        assert!(cassette.take(script).is_err());
+        assert!(cassette.take("return 1").is_err());
+    }
+
+    #[test]
+    fn recordings_round_trip_through_the_file() {
+        let path = std::env::temp_dir().join(format!("affinity-mcp-cassette-{}.json", std::process::id()));
+        let recorder = Cassette::open(path.clone(), CassetteMode::Record).unwrap();
+        recorder.record("return 1", &ScriptOutput::ok("1")).unwrap();
+        recorder.record("error \"x\"", &ScriptOutput::failed("execution error: x (-2700)")).unwrap();
+
+        let player = Cassette::open(path.clone(), CassetteMode::Replay).unwrap();
+        assert!(player.take("return 1").unwrap().success);
+        assert_eq!(player.take("error \"x\"").unwrap().stderr, "execution error: x (-2700)");
+
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(CassetteMode::parse("RECORD").unwrap(), CassetteMode::Record);
+        assert!(CassetteMode::parse("rewind").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+/**
+ * 破壊的な操作の確認（elicitation/create）
+ *
+ * 概要:
+ *   ドキュメントを閉じる・既存ファイルを上書きするなど元に戻せない操作の前に、
+ *   クライアントの elicitation/create でユーザーに確認する。
+ *
+ * 主な仕様:
+ *   - 確認の方針は環境変数 AFFINITY_MCP_CONFIRM で設定する
+ *     - always: 破壊的な操作のたびに確認する
+ *     - unsaved-only（既定）: データが失われる場合（未保存の変更・既存ファイルの上書き）だけ確認する
+ *     - never: 確認しない
+ *   - 未知の値は最も安全な always として扱う
+ *   - 確認が必要なのにクライアントが elicitation に対応していない場合は、
+ *     操作を実行せずに ToolError::NotConfirmed を返す（安全側に倒す）
+ *   - ユーザーが拒否・キャンセルした場合も ToolError::NotConfirmed を返す
+ */
+use anyhow::Result;
+use serde_json::json;
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+use super::context::CallContext;
+use crate::error::ToolError;
+
+/**
+ * 確認の方針
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmPolicy {
+    /// 破壊的な操作のたびに確認する
+    Always,
+    /// 確認しない
+    Never,
+    /// データが失われる場合（未保存の変更・既存ファイルの上書き）だけ確認する
+    UnsavedOnly,
+}
+
+impl ConfirmPolicy {
+    /// 設定値から方針を決定（未知の値は always）
+    fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "" | "unsaved-only" | "unsaved_only" => ConfirmPolicy::UnsavedOnly,
+            "never" => ConfirmPolicy::Never,
+            "always" => ConfirmPolicy::Always,
+            other => {
+                warn!(value = %other, "AFFINITY_MCP_CONFIRM の値が不明なため always として扱います");
+                ConfirmPolicy::Always
+            }
+        }
+    }
+
+    /// 環境変数 AFFINITY_MCP_CONFIRM の方針（初回参照時に確定）
+    pub fn current() -> Self {
+        static POLICY: OnceLock<ConfirmPolicy> = OnceLock::new();
+        *POLICY.get_or_init(|| Self::parse(&std::env::var("AFFINITY_MCP_CONFIRM").unwrap_or_default()))
+    }
+
+    /// 確認が必要かどうか
+    fn requires(&self, data_loss: bool) -> bool {
+        match self {
+            ConfirmPolicy::Always => true,
+            ConfirmPolicy::Never => false,
+            ConfirmPolicy::UnsavedOnly => data_loss,
+        }
+    }
+}
+
+/**
+ * 破壊的な操作の前にユーザーへ確認する
+ *
+ * 引数:
+ *   message: ユーザーに表示する説明（何が失われるか）
+ *   data_loss: 未保存の変更や既存ファイルの内容が失われる操作かどうか
+ *
+ * 戻り値:
+ *   Result<()> - 実行してよければ Ok
+ *
+ * エラー:
+ *   ユーザーが承認しなかった場合、確認できない場合は ToolError::NotConfirmed を返す
+ */
+pub async fn confirm(message: &str, data_loss: bool) -> Result<()> {
+    confirm_with_policy(ConfirmPolicy::current(), message, data_loss).await
+}
+
+async fn confirm_with_policy(policy: ConfirmPolicy, message: &str, data_loss: bool) -> Result<()> {
+    if !policy.requires(data_loss) {
+        return Ok(());
+    }
+
+    let Some(context) = CallContext::current().filter(|c| c.session.supports_elicitation()) else {
+        info!(message = %message, "クライアントが確認に対応していないため操作を中止しました");
+        return Err(ToolError::NotConfirmed(format!(
+            "{}（クライアントが確認ダイアログに対応していません。AFFINITY_MCP_CONFIRM=never で確認を省略できます）",
+            message
+        ))
+        .into());
+    };
+
+    let response = context
+        .request(
+            "elicitation/create",
+            json!({
+                "message": message,
+                "requestedSchema": {
+                    "type": "object",
+                    "properties": {
+                        "confirm": {
+                            "type": "boolean",
+                            "title": "実行する",
+                            "description": "この操作を実行してよい場合はオンにしてください",
+                        },
+                    },
+                    "required": ["confirm"],
+                },
+            }),
+        )
+        .await?;
+
+    let accepted = response.get("action").and_then(|v| v.as_str()) == Some("accept")
+        && response.pointer("/content/confirm").and_then(|v| v.as_bool()) == Some(true);
+    debug!(tool = %context.tool, accepted, "確認の応答を受け取りました");
+    if accepted {
+        Ok(())
+    } else {
+        Err(ToolError::NotConfirmed(message.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::ProtocolVersion;
+    use crate::session::Session;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    fn not_confirmed(result: &Result<()>) -> bool {
+        result
+            .as_ref()
+            .err()
+            .is_some_and(|e| matches!(crate::error::classify(e), Some(ToolError::NotConfirmed(_))))
+    }
+
+    #[tokio::test]
+    async fn policy_decides_whether_to_ask() {
+        assert_eq!(ConfirmPolicy::parse("unsaved-only"), ConfirmPolicy::UnsavedOnly);
+        assert_eq!(ConfirmPolicy::parse("typo"), ConfirmPolicy::Always);
+
+        assert!(confirm_with_policy(ConfirmPolicy::Never, "閉じます", true).await.is_ok());
+        assert!(confirm_with_policy(ConfirmPolicy::UnsavedOnly, "閉じます", false).await.is_ok());
+        // elicitation が使えない場合は実行しない
+        assert!(not_confirmed(&confirm_with_policy(ConfirmPolicy::UnsavedOnly, "閉じます", true).await));
+        assert!(not_confirmed(&confirm_with_policy(ConfirmPolicy::Always, "上書きします", false).await));
+    }
+
+    #[tokio::test]
+    async fn user_answer_is_applied() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let session = Arc::new(Session::with_outbound(tx));
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "elicitation": {} }));
+
+        // クライアント役: 1回目は承認、2回目は拒否する
+        let client = session.clone();
+        tokio::spawn(async move {
+            for action in ["accept", "decline"] {
+                let request: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+                assert_eq!(request["method"], "elicitation/create");
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "action": action, "content": { "confirm": action == "accept" } },
+                });
+                assert!(client.resolve_response(&response.to_string()));
+            }
+        });
+
+        let context = Arc::new(CallContext::new("affinity.close_document", session, None));
+        let first = context.clone().scope(confirm_with_policy(ConfirmPolicy::Always, "閉じます", false)).await;
+        assert!(first.is_ok());
+        let second = context.scope(confirm_with_policy(ConfirmPolicy::Always, "閉じます", false)).await;
+        assert!(not_confirmed(&second));
+    }
+}
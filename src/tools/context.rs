@@ -0,0 +1,317 @@
+/**
+ * ツール呼び出しコンテキスト
+ *
+ * 概要:
+ *   tools/call 1回分の情報（ツール名、セッション、_meta、キャンセル状態）を
+ *   タスクローカルに保持し、ツール関数の引数を変えずに実行中の処理から参照できるようにする。
+ *
+ * 主な仕様:
+ *   - mcp.rs の tools/call が scope() でツールの future を包んで実行する
+ *   - コンテキスト外（テストや内部呼び出し）では current() が None になり、通知は送らない
+ *   - join_all で並行実行する子 future は同じタスク内なので同じコンテキストを参照できる
+ *     （tokio::spawn したタスクには引き継がれない）
+ *   - notifications/cancelled を受けると Cancellation が立ち、
+ *     実行中の osascript は終了され、未開始の処理は ToolError::Cancelled になる
+ */
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+
+use crate::error::ToolError;
+use crate::session::{self, Session};
+
+tokio::task_local! {
+    static CURRENT: Arc<CallContext>;
+}
+
+/**
+ * ツール呼び出しコンテキスト
+ */
+#[derive(Debug)]
+pub struct CallContext {
+    /// ツール名
+    pub tool: String,
+    /// 呼び出し元のセッション
+    pub session: Arc<Session>,
+    /// 進捗通知のトークン（_meta.progressToken）
+    progress_token: Option<Value>,
+    /// キャンセル状態
+    pub cancellation: Cancellation,
+    /// この呼び出しに関連する通知の送信先（None ならセッションの送信キュー）
+    stream: Option<UnboundedSender<String>>,
+}
+
+/**
+ * キャンセル状態
+ *
+ * 概要:
+ *   クローンは同じ状態を共有する（セッションの実行中リクエスト表と
+ *   ツール呼び出しコンテキストの両方から参照する）。
+ */
+#[derive(Debug, Clone)]
+pub struct Cancellation(Arc<watch::Sender<bool>>);
+
+impl Default for Cancellation {
+    fn default() -> Self {
+        Self(Arc::new(watch::Sender::new(false)))
+    }
+}
+
+impl Cancellation {
+    /// キャンセルする
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// キャンセル済みかどうか
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// キャンセルされるまで待機
+    pub async fn cancelled(&self) {
+        let mut rx = self.0.subscribe();
+        // 送信側は self が保持しているため閉じることはない
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl CallContext {
+    /**
+     * コンテキストを作成
+     *
+     * 引数:
+     *   tool: ツール名
+     *   session: 呼び出し元のセッション
+     *   meta: tools/call の _meta（省略可）
+     */
+    pub fn new(tool: &str, session: Arc<Session>, meta: Option<&Value>) -> Self {
+        let progress_token = meta
+            .and_then(|m| m.get("progressToken"))
+            .filter(|t| t.is_string() || t.is_number())
+            .cloned();
+        Self {
+            tool: tool.to_string(),
+            session,
+            progress_token,
+            cancellation: Cancellation::default(),
+            stream: None,
+        }
+    }
+
+    /**
+     * 通知の送信先を設定
+     *
+     * 引数:
+     *   stream: HTTPのSSE応答など、この呼び出しに紐づく送信先（None ならセッションへ送る）
+     */
+    pub fn with_stream(mut self, stream: Option<UnboundedSender<String>>) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /**
+     * この呼び出しに関連する通知を送信
+     */
+    pub fn notify(&self, method: &str, params: Value) -> bool {
+        match &self.stream {
+            Some(stream) => stream.send(session::notification(method, params)).is_ok(),
+            None => self.session.notify(method, params),
+        }
+    }
+
+    /**
+     * クライアントへリクエストを送信し、応答を待つ
+     *
+     * 概要:
+     *   通知と同じ送信先（HTTPならこの呼び出しのSSE応答）へ送る。
+     *   応答待ちの間にキャンセルされた場合は ToolError::Cancelled を返す。
+     *
+     * 引数:
+     *   method: メソッド名（例: elicitation/create）
+     *   params: パラメータ
+     */
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        tokio::select! {
+            result = self.session.request(method, params, self.stream.as_ref()) => result,
+            _ = self.cancellation.cancelled() => Err(ToolError::Cancelled(self.tool.clone()).into()),
+        }
+    }
+
+    /// 実行中のツール呼び出しのコンテキスト
+    pub fn current() -> Option<Arc<CallContext>> {
+        CURRENT.try_with(Arc::clone).ok()
+    }
+
+    /**
+     * コンテキストを設定して future を実行
+     */
+    pub async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /**
+     * notifications/progress を送信
+     *
+     * 概要:
+     *   クライアントが progressToken を指定していない場合は何もしない。
+     *
+     * 引数:
+     *   progress: 現在の進捗（単調増加）
+     *   total: 全体量（不明なら None）
+     *   message: 進捗メッセージ
+     */
+    pub fn report_progress(&self, progress: u64, total: Option<u64>, message: &str) {
+        let Some(token) = &self.progress_token else {
+            return;
+        };
+        let mut params = json!({
+            "progressToken": token,
+            "progress": progress,
+        });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if self.session.effective_version().supports_progress_message() {
+            params["message"] = json!(message);
+        }
+        tracing::debug!(tool = %self.tool, progress, total, "進捗を通知します");
+        self.notify("notifications/progress", params);
+    }
+}
+
+/**
+ * 現在のツール呼び出しがキャンセルされていればエラーを返す
+ *
+ * 概要:
+ *   副作用のある処理を始める直前に呼ぶ。コンテキスト外では常に Ok。
+ */
+pub fn check_cancelled() -> Result<()> {
+    match CallContext::current() {
+        Some(context) if context.cancellation.is_cancelled() => {
+            Err(ToolError::Cancelled(context.tool.clone()).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/**
+ * 現在のツール呼び出しがキャンセルされるまで待機
+ *
+ * 概要:
+ *   キャンセルされると ToolError::Cancelled を返す。コンテキスト外では完了しない
+ *   （select! の片側として使う）。
+ */
+// 外部プロセスを待機する経路（macOS）でのみ使用する
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub async fn cancelled() -> anyhow::Error {
+    match CallContext::current() {
+        Some(context) => {
+            context.cancellation.cancelled().await;
+            ToolError::Cancelled(context.tool.clone()).into()
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/**
+ * 件数ベースの進捗
+ *
+ * 概要:
+ *   バッチ処理で1件完了するごとに advance() を呼ぶと、
+ *   完了件数を progress として通知する（完了順は問わない）。
+ */
+pub struct Progress {
+    context: Option<Arc<CallContext>>,
+    total: u64,
+    completed: AtomicU64,
+}
+
+impl Progress {
+    /**
+     * 現在のツール呼び出しに対する進捗を開始
+     *
+     * 引数:
+     *   total: 全体の件数
+     */
+    pub fn start(total: usize) -> Self {
+        Self {
+            context: CallContext::current(),
+            total: total as u64,
+            completed: AtomicU64::new(0),
+        }
+    }
+
+    /**
+     * 1件完了したことを通知
+     *
+     * 引数:
+     *   message: 完了した項目の説明
+     */
+    pub fn advance(&self, message: &str) {
+        let completed = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(context) = &self.context {
+            context.report_progress(
+                completed,
+                Some(self.total),
+                &format!("{}/{} {}", completed, self.total, message),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn progress_is_sent_only_with_token() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let session = Arc::new(Session::with_outbound(tx));
+
+        let context = Arc::new(CallContext::new("affinity.batch_export", session.clone(), Some(&json!({ "progressToken": "abc" }))));
+        context
+            .scope(async {
+                let progress = Progress::start(2);
+                progress.advance("a.png");
+                progress.advance("b.png");
+            })
+            .await;
+
+        let first: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(first["method"], "notifications/progress");
+        assert_eq!(first["params"]["progressToken"], "abc");
+        assert_eq!(first["params"]["progress"], 1);
+        assert_eq!(first["params"]["total"], 2);
+        let second: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(second["params"]["progress"], 2);
+
+        let context = Arc::new(CallContext::new("affinity.batch_export", session, None));
+        context.scope(async { Progress::start(1).advance("a.png") }).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cancellation_is_visible_inside_scope() {
+        let context = Arc::new(CallContext::new("affinity.export", Arc::new(Session::default()), None));
+        let cancellation = context.cancellation.clone();
+
+        context
+            .scope(async move {
+                assert!(check_cancelled().is_ok());
+                cancellation.cancel();
+                // キャンセル済みなら待機は即座に完了する
+                let err = cancelled().await;
+                assert!(matches!(crate::error::classify(&err), Some(ToolError::Cancelled(_))));
+                assert!(check_cancelled().is_err());
+            })
+            .await;
+
+        assert!(check_cancelled().is_ok());
+    }
+}
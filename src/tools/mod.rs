@@ -2,36 +2,33 @@
  * ツールモジュール統合
  * 
  * 概要:
- *   すべてのMCPツール（canva、affinity）を統合し、初期化する。
+ *   すべてのMCPツール（canva、affinity）を初期化し、ツールレジストリに登録する。
  * 
  * 主な仕様:
- *   - register_all()で全ツールを初期化
- *   - SDK導入時には、Serverインスタンスにツールを登録する処理に置換
- * 
- * 制限事項:
- *   - 現在はスタブ実装。SDK導入時に実際の登録処理を実装する必要がある。
+ *   - register_all()で全ツールを初期化・登録したレジストリを返す
+ *   - Affinityツールは渡されたスクリプト実行バックエンド（backend::ScriptBackend）で AppleScript を実行する
+ *   - 追加のツールは registry::ToolRegistry に登録するだけで tools/list と tools/call に反映される
  */
 pub mod canva;
 pub mod affinity;
-
-pub async fn register_all() -> anyhow::Result<()> {
-    // SDK導入時：
-    // let mut server = Server::new("affinity-mcp")?;
-    // canva::register(&mut server).await?;
-    // affinity::register(&mut server).await?;
-    // server.serve_stdio().await?;
-    // Ok(())
-
-    // いまはスタブ初期化のみ
+pub mod applescript;
+pub mod backend;
+pub mod cassette;
+pub mod confirm;
+pub mod context;
+pub mod registry;
+pub mod roots;
+
+use backend::ScriptBackend;
+use registry::ToolRegistry;
+use std::sync::Arc;
+
+pub async fn register_all(backend: Arc<dyn ScriptBackend>) -> anyhow::Result<ToolRegistry> {
     canva::init_stub().await?;
     affinity::init_stub().await?;
-    Ok(())
-}
-
-
-
-
-
-
-
 
+    let mut registry = ToolRegistry::new();
+    affinity::register(&mut registry, backend)?;
+    canva::register(&mut registry)?;
+    Ok(registry)
+}
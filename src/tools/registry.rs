@@ -0,0 +1,698 @@
+/**
+ * ツールレジストリ
+ *
+ * 概要:
+ *   MCPツールの名前・説明・入力スキーマ・アノテーション・非同期ハンドラーを
+ *   1か所に登録し、tools/list と tools/call の両方をこのテーブルから駆動する。
+ *
+ * 主な仕様:
+ *   - ToolHandler トレイトを実装すれば任意のツールを追加できる（mcp.rs の変更不要）
+ *   - tools/list の名前は ToolNaming で変換し、tools/call はすべての別名を受け付ける
+ *   - register() は引数型の JsonSchema から入力スキーマを自動生成する
+ *   - 引数の補完関数（completion/complete）は ToolSpec::complete で引数ごとに登録する
+ *   - 実行時間の上限はツールごとの既定値（ToolSpec::timeout、未指定なら60秒）を
+ *     AFFINITY_MCP_TOOL_TIMEOUTS で上書きでき、tools/call の _meta.timeoutMs が最優先
+ *   - 登録順が tools/list の表示順になる
+ *
+ * エラー処理:
+ *   - 同名ツールの二重登録はエラー
+ *   - 引数パース失敗は ToolError::InvalidParams として返す
+ *   - 引数パース失敗・実行失敗にはツール名をコンテキストとして付与する
+ */
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::completion::Completer;
+use crate::error::ToolError;
+use crate::mcp::{CallToolResult, Content, Tool, ToolAnnotations};
+
+/**
+ * ツール結果
+ * 
+ * 概要:
+ *   結果構造体は structuredContent としてそのまま返し、
+ *   summary() の文字列をクライアント表示用のテキストとして返す。
+ */
+pub trait ToolOutput: Serialize {
+    /// 人が読むためのテキスト要約
+    fn summary(&self) -> String;
+
+    /// 生成したファイルなどへのリンク（resource_link コンテンツ）
+    fn links(&self) -> Vec<Content> {
+        Vec::new()
+    }
+}
+
+impl ToolOutput for Value {
+    fn summary(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_else(|_| self.to_string())
+    }
+}
+
+/**
+ * ツールハンドラー
+ *
+ * 概要:
+ *   JSON引数を受け取り、CallToolResult を返す非同期ハンドラー。
+ *   Err はツール実行の失敗として isError の結果に変換される。
+ *   サードパーティ製ツールはこのトレイトを実装して登録する。
+ */
+pub trait ToolHandler: Send + Sync {
+    /// 引数を検証する（実行はしない）
+    fn validate(&self, _arguments: &Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// ツールを実行する
+    fn call(&self, arguments: Value) -> BoxFuture<'static, Result<CallToolResult>>;
+}
+
+/**
+ * 登録時のツール情報（スキーマ以外）
+ */
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    /// ツール名
+    pub name: String,
+    /// ツール説明
+    pub description: String,
+    /// ツールアノテーション
+    pub annotations: Option<ToolAnnotations>,
+    /// 引数ごとの補完関数（completion/complete）
+    pub completions: HashMap<String, Completer>,
+    /// 実行時間の上限の既定値
+    pub timeout: Duration,
+}
+
+impl ToolSpec {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            annotations: None,
+            completions: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// アノテーションを設定する
+    pub fn annotations(mut self, annotations: ToolAnnotations) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    /// 引数の補完関数を登録する
+    pub fn complete(mut self, argument: impl Into<String>, completer: Completer) -> Self {
+        self.completions.insert(argument.into(), completer);
+        self
+    }
+
+    /// 実行時間の上限の既定値を設定する
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// ツールの実行時間の上限（ToolSpec::timeout を指定しない場合）
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/**
+ * 登録済みツール
+ */
+#[derive(Clone)]
+pub struct RegisteredTool {
+    /// MCP Tool定義
+    pub definition: Tool,
+    handler: Arc<dyn ToolHandler>,
+    completions: HashMap<String, Completer>,
+    /// 実行時間の上限（設定による上書きを反映済み）
+    pub timeout: Duration,
+}
+
+impl RegisteredTool {
+    /// 引数を検証する
+    pub fn validate(&self, arguments: &Value) -> Result<()> {
+        self.handler.validate(arguments)
+    }
+
+    /// ツールを実行する
+    pub fn call(&self, arguments: Value) -> BoxFuture<'static, Result<CallToolResult>> {
+        self.handler.call(arguments)
+    }
+
+    /**
+     * 引数の補完候補を返す
+     *
+     * 戻り値:
+     *   Vec<String> - 候補（補完関数のない引数は空）
+     */
+    pub async fn complete(&self, argument: &str, value: &str) -> Vec<String> {
+        match self.completions.get(argument) {
+            Some(completer) => completer.complete(value).await,
+            None => Vec::new(),
+        }
+    }
+}
+
+/**
+ * tools/list に出すツール名の形式
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamingScheme {
+    /// affinity.open_file（登録名のまま）
+    #[default]
+    Dotted,
+    /// affinity_open_file
+    Underscore,
+    /// <接頭辞>_affinity_open_file
+    Prefixed,
+}
+
+/**
+ * ツール名の公開形式
+ *
+ * 概要:
+ *   名前に [a-zA-Z0-9_-] しか使えないクライアント向けに、tools/list の名前を変換する。
+ *   tools/call はどの形式の名前（別名）でも受け付ける。
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolNaming {
+    /// 公開形式
+    pub scheme: NamingScheme,
+    /// prefixed 形式の接頭辞
+    pub prefix: String,
+}
+
+impl Default for ToolNaming {
+    fn default() -> Self {
+        Self {
+            scheme: NamingScheme::default(),
+            prefix: "affinity-mcp".to_string(),
+        }
+    }
+}
+
+impl ToolNaming {
+    /**
+     * 環境変数から公開形式を決定
+     *
+     * 概要:
+     *   AFFINITY_MCP_TOOL_NAMING（dotted / underscore / prefixed、既定: dotted）と
+     *   AFFINITY_MCP_TOOL_PREFIX（既定: サーバー名）を読む。
+     *   接頭辞の [a-zA-Z0-9_-] 以外の文字は _ に置き換える。
+     *
+     * 引数:
+     *   server_name: サーバー名（接頭辞の既定値）
+     *
+     * エラー:
+     *   未知の形式名の場合はエラーを返す
+     */
+    pub fn from_env(server_name: &str) -> Result<Self> {
+        let scheme = match std::env::var("AFFINITY_MCP_TOOL_NAMING")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "dotted" => NamingScheme::Dotted,
+            "underscore" => NamingScheme::Underscore,
+            "prefixed" => NamingScheme::Prefixed,
+            other => anyhow::bail!("不明なツール名の形式です: {}（dotted / underscore / prefixed）", other),
+        };
+        let prefix = std::env::var("AFFINITY_MCP_TOOL_PREFIX").unwrap_or_else(|_| server_name.to_string());
+        let prefix: String = prefix
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+            .collect();
+        Ok(Self { scheme, prefix })
+    }
+
+    /// tools/list に出す名前
+    pub fn display_name(&self, name: &str) -> String {
+        match self.scheme {
+            NamingScheme::Dotted => name.to_string(),
+            NamingScheme::Underscore => underscore_name(name),
+            NamingScheme::Prefixed => format!("{}_{}", self.prefix, underscore_name(name)),
+        }
+    }
+}
+
+/// 区切りのドットを _ に置き換えた別名
+fn underscore_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/**
+ * ツールレジストリ
+ */
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<RegisteredTool>,
+    /// 登録名と別名（underscore 形式）からツールへの索引
+    index: HashMap<String, usize>,
+    naming: ToolNaming,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ツール名の公開形式を設定する
+    pub fn with_naming(mut self, naming: ToolNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// ツール名の公開形式
+    pub fn naming(&self) -> &ToolNaming {
+        &self.naming
+    }
+
+    /**
+     * 環境変数 AFFINITY_MCP_TOOL_TIMEOUTS で実行時間の上限を上書きする
+     *
+     * エラー:
+     *   設定の書式が不正な場合はエラーを返す（with_timeout_overrides 参照）
+     */
+    pub fn with_timeouts_from_env(self) -> Result<Self> {
+        match std::env::var("AFFINITY_MCP_TOOL_TIMEOUTS") {
+            Ok(overrides) => self.with_timeout_overrides(&overrides),
+            Err(_) => Ok(self),
+        }
+    }
+
+    /**
+     * 実行時間の上限を上書きする
+     *
+     * 引数:
+     *   overrides: `ツール名=ミリ秒` のカンマ区切り。ツール名はどの公開形式でもよく、
+     *     `*` は個別の指定がないすべてのツールに適用する
+     *
+     * エラー:
+     *   書式が不正な場合、未登録のツール名を指定した場合はエラーを返す
+     */
+    pub fn with_timeout_overrides(mut self, overrides: &str) -> Result<Self> {
+        let mut specific = Vec::new();
+        for entry in overrides.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, millis) = entry
+                .split_once('=')
+                .with_context(|| format!("タイムアウトの指定は ツール名=ミリ秒 の形式で指定してください: {}", entry))?;
+            let timeout = millis
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .with_context(|| format!("タイムアウトのミリ秒が不正です: {}", entry))?;
+            match name.trim() {
+                "*" => self.tools.iter_mut().for_each(|tool| tool.timeout = timeout),
+                name => {
+                    let index = self
+                        .get(name)
+                        .map(|tool| self.index[&tool.definition.name])
+                        .with_context(|| format!("タイムアウトの指定に未登録のツールがあります: {}", name))?;
+                    specific.push((index, timeout));
+                }
+            }
+        }
+        // 個別の指定は記述順に関係なく * より優先する
+        for (index, timeout) in specific {
+            self.tools[index].timeout = timeout;
+        }
+        Ok(self)
+    }
+
+    /**
+     * 型付きハンドラーを登録
+     *
+     * 引数:
+     *   spec: ツール名・説明・アノテーション
+     *   handler: 引数構造体を受け取る非同期関数
+     *
+     * エラー:
+     *   同名のツールが登録済みの場合はエラーを返す
+     */
+    pub fn register<P, R, F, Fut>(&mut self, spec: ToolSpec, handler: F) -> Result<()>
+    where
+        P: DeserializeOwned + JsonSchema + Send + 'static,
+        R: ToolOutput + Send + 'static,
+        F: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+    {
+        let definition = Tool {
+            name: spec.name.clone(),
+            description: spec.description,
+            input_schema: input_schema_for::<P>(),
+            annotations: spec.annotations,
+        };
+        self.register_handler(
+            definition,
+            FnTool {
+                name: spec.name,
+                handler,
+                _params: PhantomData,
+            },
+        )?;
+        if let Some(tool) = self.tools.last_mut() {
+            tool.completions = spec.completions;
+            tool.timeout = spec.timeout;
+        }
+        Ok(())
+    }
+
+    /**
+     * 任意の ToolHandler を登録
+     *
+     * 概要:
+     *   入力スキーマを手動で与える必要があるツール向け。
+     *
+     * エラー:
+     *   同名のツール（別名の衝突を含む）が登録済みの場合はエラーを返す
+     */
+    pub fn register_handler<H: ToolHandler + 'static>(&mut self, definition: Tool, handler: H) -> Result<()> {
+        let alias = underscore_name(&definition.name);
+        if self.index.contains_key(&definition.name) || self.index.contains_key(&alias) {
+            anyhow::bail!("ツールが二重に登録されています: {}", definition.name);
+        }
+        self.index.insert(definition.name.clone(), self.tools.len());
+        self.index.insert(alias, self.tools.len());
+        self.tools.push(RegisteredTool {
+            definition,
+            handler: Arc::new(handler),
+            completions: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT,
+        });
+        Ok(())
+    }
+
+    /**
+     * 名前でツールを取得
+     *
+     * 概要:
+     *   公開形式に関係なく、dotted / underscore / prefixed のいずれの名前でも引ける。
+     */
+    pub fn get(&self, name: &str) -> Option<&RegisteredTool> {
+        let unprefixed = name
+            .strip_prefix(self.naming.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('_'));
+        self.index
+            .get(name)
+            .or_else(|| unprefixed.and_then(|rest| self.index.get(rest)))
+            .map(|&i| &self.tools[i])
+    }
+
+    /// 登録順のツール一覧
+    pub fn tools(&self) -> impl Iterator<Item = &RegisteredTool> {
+        self.tools.iter()
+    }
+
+    /// 登録済みツール数
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+}
+
+/**
+ * 非同期関数をラップしたハンドラー
+ */
+struct FnTool<P, F> {
+    name: String,
+    handler: F,
+    _params: PhantomData<fn(P)>,
+}
+
+impl<P, R, F, Fut> ToolHandler for FnTool<P, F>
+where
+    P: DeserializeOwned + Send + 'static,
+    R: ToolOutput + Send + 'static,
+    F: Fn(P) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+{
+    fn validate(&self, arguments: &Value) -> Result<()> {
+        P::deserialize(&normalize_arguments(arguments.clone()))
+            .map(|_| ())
+            .map_err(|e| ToolError::InvalidParams(e.to_string()))
+            .context(format!("{}: 引数のパースに失敗しました", self.name))
+    }
+
+    fn call(&self, arguments: Value) -> BoxFuture<'static, Result<CallToolResult>> {
+        let name = self.name.clone();
+        let params = serde_json::from_value::<P>(normalize_arguments(arguments))
+            .map_err(|e| ToolError::InvalidParams(e.to_string()))
+            .context(format!("{}: 引数のパースに失敗しました", name));
+        let future = params.map(|params| (self.handler)(params));
+        Box::pin(async move {
+            let result = future?
+                .await
+                .context(format!("{}: ツールの実行に失敗しました", name))?;
+            let structured = serde_json::to_value(&result)
+                .map_err(|e| anyhow::anyhow!("JSON serialization error: {}", e))?;
+            Ok(CallToolResult::success(result.summary(), result.links(), structured))
+        })
+    }
+}
+
+/**
+ * 型からMCPの入力スキーマを生成
+ *
+ * 概要:
+ *   MCPクライアントは $ref を解決できないことがあるため、
+ *   サブスキーマはインライン展開し、Option<T> は T として出力する
+ *   （省略可能かどうかは required で表現される）。
+ *
+ * 戻り値:
+ *   Value - JSON Schema（type: object）
+ */
+pub fn input_schema_for<T: JsonSchema>() -> Value {
+    let settings = SchemaSettings::draft07().with(|s| {
+        s.option_nullable = false;
+        s.option_add_null_type = false;
+        s.inline_subschemas = true;
+        s.meta_schema = None;
+    });
+    let schema = settings.into_generator().into_root_schema_for::<T>();
+    serde_json::to_value(schema).unwrap_or_else(|_| json!({ "type": "object" }))
+}
+
+/**
+ * ツール引数を正規化
+ *
+ * 概要:
+ *   arguments が省略（null）された場合は空オブジェクトとして扱う。
+ */
+pub fn normalize_arguments(arguments: Value) -> Value {
+    if arguments.is_null() {
+        json!({})
+    } else {
+        arguments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::backend::MockBackend;
+    use serde::Deserialize;
+
+    /// スキーマが許可する列挙値（enum / oneOf の両形式）
+    fn enum_values(schema: &Value) -> Vec<Value> {
+        if let Some(values) = schema.get("enum").and_then(|v| v.as_array()) {
+            return values.clone();
+        }
+        schema
+            .get("oneOf")
+            .or_else(|| schema.get("anyOf"))
+            .and_then(|v| v.as_array())
+            .map(|variants| variants.iter().flat_map(enum_values).collect())
+            .unwrap_or_default()
+    }
+
+    /// スキーマに適合するサンプル値を生成（all_properties=false なら必須項目のみ）
+    fn sample(schema: &Value, all_properties: bool) -> Value {
+        if let Some(first) = enum_values(schema).into_iter().next() {
+            return first;
+        }
+        match schema.get("type").and_then(|t| t.as_str()) {
+            Some("object") => {
+                let required: Vec<&str> = schema
+                    .get("required")
+                    .and_then(|r| r.as_array())
+                    .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+                    .unwrap_or_default();
+                let mut object = serde_json::Map::new();
+                if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                    for (key, property) in properties {
+                        if all_properties || required.contains(&key.as_str()) {
+                            object.insert(key.clone(), sample(property, all_properties));
+                        }
+                    }
+                }
+                Value::Object(object)
+            }
+            Some("array") => {
+                let items = schema.get("items").cloned().unwrap_or(json!({}));
+                json!([sample(&items, all_properties)])
+            }
+            Some("integer") | Some("number") => {
+                schema.get("minimum").and_then(|m| m.as_f64()).map(|m| json!(m.max(1.0) as u64)).unwrap_or(json!(1))
+            }
+            Some("boolean") => json!(true),
+            Some("string") => json!("/tmp/sample.png"),
+            _ => json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_schemas_match_deserializers() {
+        let registry = crate::tools::register_all(Arc::new(MockBackend::default())).await.unwrap();
+        assert!(registry.len() > 0);
+
+        for tool in registry.tools() {
+            let name = &tool.definition.name;
+            let schema = &tool.definition.input_schema;
+            assert_eq!(schema.get("type"), Some(&json!("object")), "{}: type が object ではありません", name);
+
+            // 必須項目のみ・全項目のどちらでもデシリアライズできること
+            let minimal = sample(schema, false);
+            tool.validate(&minimal)
+                .unwrap_or_else(|e| panic!("{}: 必須項目のみの引数を受け付けません: {} ({:#})", name, minimal, e));
+            let full = sample(schema, true);
+            tool.validate(&full)
+                .unwrap_or_else(|e| panic!("{}: 全項目の引数を受け付けません: {} ({:#})", name, full, e));
+
+            let properties = schema.get("properties").and_then(|p| p.as_object()).cloned().unwrap_or_default();
+            let required: Vec<String> = schema
+                .get("required")
+                .and_then(|r| r.as_array())
+                .map(|r| r.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+
+            for (key, property) in &properties {
+                // スキーマ上の必須項目を欠いた引数は拒否されること
+                if required.contains(key) {
+                    let mut missing = full.clone();
+                    missing.as_object_mut().unwrap().remove(key);
+                    assert!(
+                        tool.validate(&missing).is_err(),
+                        "{}: スキーマでは必須の {} が省略可能になっています", name, key
+                    );
+                }
+
+                // スキーマの列挙値はすべて受け付け、それ以外は拒否すること
+                let variants = enum_values(property);
+                for variant in &variants {
+                    let mut arguments = full.clone();
+                    arguments[key.as_str()] = variant.clone();
+                    tool.validate(&arguments)
+                        .unwrap_or_else(|e| panic!("{}: {} の列挙値 {} を受け付けません ({:#})", name, key, variant, e));
+                }
+                if !variants.is_empty() {
+                    let mut arguments = full.clone();
+                    arguments[key.as_str()] = json!("__not_a_variant__");
+                    assert!(
+                        tool.validate(&arguments).is_err(),
+                        "{}: {} がスキーマにない列挙値を受け付けます", name, key
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn every_tool_has_consistent_annotations() {
+        let registry = crate::tools::register_all(Arc::new(MockBackend::default())).await.unwrap();
+        for tool in registry.tools() {
+            let name = &tool.definition.name;
+            let annotations = tool.definition.annotations.as_ref()
+                .unwrap_or_else(|| panic!("{}: アノテーションがありません", name));
+            assert!(annotations.title.is_some(), "{}: title がありません", name);
+            assert!(
+                annotations.read_only_hint.is_some()
+                    && annotations.destructive_hint.is_some()
+                    && annotations.idempotent_hint.is_some()
+                    && annotations.open_world_hint.is_some(),
+                "{}: 未設定のヒントがあります", name
+            );
+            if annotations.read_only_hint == Some(true) {
+                assert_eq!(annotations.destructive_hint, Some(false), "{}: 読み取り専用なのに破壊的です", name);
+            }
+        }
+
+        let hints = |name: &str| registry.get(name).unwrap().definition.annotations.clone().unwrap();
+        assert_eq!(hints("affinity.get_active_document").read_only_hint, Some(true));
+        assert_eq!(hints("affinity.close_document").destructive_hint, Some(true));
+    }
+
+    #[tokio::test]
+    async fn every_naming_scheme_resolves_to_the_same_tool() {
+        let naming = ToolNaming {
+            scheme: NamingScheme::Prefixed,
+            prefix: "affinity-mcp".to_string(),
+        };
+        let registry = crate::tools::register_all(Arc::new(MockBackend::default())).await.unwrap().with_naming(naming);
+
+        assert_eq!(registry.naming().display_name("affinity.open_file"), "affinity-mcp_affinity_open_file");
+        for name in ["affinity.open_file", "affinity_open_file", "affinity-mcp_affinity_open_file"] {
+            let tool = registry.get(name).unwrap_or_else(|| panic!("{} が見つかりません", name));
+            assert_eq!(tool.definition.name, "affinity.open_file");
+        }
+        assert!(registry.get("other_affinity_open_file").is_none());
+
+        for tool in registry.tools() {
+            let display = registry.naming().display_name(&tool.definition.name);
+            assert!(
+                display.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                "{}: 使用できない文字を含みます", display
+            );
+            assert!(registry.get(&display).is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts_can_be_overridden_per_tool() {
+        let registry = crate::tools::register_all(Arc::new(MockBackend::default()))
+            .await
+            .unwrap()
+            .with_timeout_overrides("affinity_export=5000, *=1000")
+            .unwrap();
+        assert_eq!(registry.get("affinity.export").unwrap().timeout, Duration::from_secs(5));
+        assert_eq!(registry.get("affinity.open_file").unwrap().timeout, Duration::from_secs(1));
+
+        let registry = crate::tools::register_all(Arc::new(MockBackend::default())).await.unwrap();
+        assert!(registry.clone().with_timeout_overrides("affinity.no_such_tool=1000").is_err());
+        assert!(registry.with_timeout_overrides("affinity.export=0").is_err());
+    }
+
+    /// 引数なしツールのパラメータ
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct NoParams {}
+
+    #[test]
+    fn missing_arguments_are_treated_as_empty_object() {
+        assert_eq!(normalize_arguments(Value::Null), json!({}));
+        assert!(NoParams::deserialize(&normalize_arguments(Value::Null)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn duplicate_registration_is_rejected() {
+        let mut registry = ToolRegistry::new();
+        let handler = |_: NoParams| async { Ok(json!({})) };
+        registry.register(ToolSpec::new("test.tool", "テスト"), handler).unwrap();
+        assert!(registry.register(ToolSpec::new("test.tool", "テスト"), handler).is_err());
+        assert_eq!(registry.len(), 1);
+        let result = registry.get("test.tool").unwrap().call(Value::Null).await.unwrap();
+        assert!(!result.is_error);
+        assert_eq!(result.structured_content, Some(json!({})));
+    }
+}
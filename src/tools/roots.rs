@@ -0,0 +1,309 @@
+/**
+ * ファイルアクセスの制限（クライアントのルートと静的な許可リスト）
+ *
+ * 概要:
+ *   ツールが読み書きするパスが、クライアントが roots/list で宣言したルート、
+ *   または設定の許可リストの内側にあるかを検証する。
+ *
+ * 主な仕様:
+ *   - クライアントが roots 機能に対応している場合は roots/list を要求し、
+ *     結果をセッションにキャッシュする（notifications/roots/list_changed で破棄）
+ *   - ルートを宣言しないクライアント向けに、環境変数 AFFINITY_MCP_ALLOWED_ROOTS
+ *     （OSのパス区切り文字で区切ったディレクトリ）を許可リストとして使う
+ *   - roots 機能に対応したクライアントのルートが空ならすべてのパスを拒否し、
+ *     roots/list が失敗した場合も拒否する（許可リストや無制限にはフォールバックしない）
+ *   - roots 機能に対応していないクライアントで許可リストも空なら制限しない（従来どおりの動作）
+ *   - パスは絶対パスにし、. と .. を解決し、存在する部分はシンボリックリンクも解決してから比較する
+ *   - 違反は ToolError::OutsideRoots として返す
+ */
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, warn};
+
+use super::context::CallContext;
+use crate::error::ToolError;
+
+/**
+ * パスが許可されたルートの内側にあるかを検証
+ *
+ * 引数:
+ *   paths: ツールが読み書きするパス
+ *
+ * エラー:
+ *   いずれかのパスがルートの外にある場合は ToolError::OutsideRoots を返す
+ */
+pub async fn check_paths(paths: &[&str]) -> Result<()> {
+    let roots = match allowed_roots().await {
+        Ok(Some(roots)) => roots,
+        Ok(None) => return Ok(()),
+        Err(e) if matches!(crate::error::classify(&e), Some(ToolError::Cancelled(_))) => return Err(e),
+        Err(e) => {
+            warn!(error = %format!("{:#}", e), "クライアントのルートを取得できないためアクセスを拒否しました");
+            return Err(anyhow::Error::new(ToolError::OutsideRoots(paths.join(", ")))
+                .context(format!("クライアントのルートを取得できません: {:#}", e)));
+        }
+    };
+    for path in paths {
+        let normalized = normalize(Path::new(path));
+        if !roots.iter().any(|root| normalized.starts_with(root)) {
+            warn!(path = %path, "許可されたルートの外のパスへのアクセスを拒否しました");
+            return Err(ToolError::OutsideRoots(path.to_string()).into());
+        }
+    }
+    Ok(())
+}
+
+/**
+ * 許可されたルートの内側のパスだけを残す
+ *
+ * 概要:
+ *   リソース一覧など、拒否ではなく見せないことで制限する場合に使う。
+ *   ルートを取得できない場合は何も残さない。
+ *
+ * 引数:
+ *   paths: 対象のパス
+ */
+pub async fn filter_allowed(paths: Vec<String>) -> Vec<String> {
+    retain_paths(paths, |path, root| path.starts_with(root)).await
+}
+
+/**
+ * 許可されたルートの内側のパスと、ルートへ至る親ディレクトリだけを残す
+ *
+ * 概要:
+ *   パスの補完で、ルートまでの階層をたどれるようにしつつ、ほかの場所は見せない。
+ *   ルートを取得できない場合は何も残さない。
+ *
+ * 引数:
+ *   paths: 対象のパス
+ */
+pub async fn filter_reachable(paths: Vec<String>) -> Vec<String> {
+    retain_paths(paths, |path, root| path.starts_with(root) || root.starts_with(path)).await
+}
+
+async fn retain_paths(paths: Vec<String>, keep: impl Fn(&Path, &Path) -> bool) -> Vec<String> {
+    let roots = match allowed_roots().await {
+        Ok(Some(roots)) => roots,
+        Ok(None) => return paths,
+        Err(e) => {
+            warn!(error = %format!("{:#}", e), "クライアントのルートを取得できないためパスを表示しません");
+            return Vec::new();
+        }
+    };
+    paths
+        .into_iter()
+        .filter(|path| {
+            let normalized = normalize(Path::new(path));
+            roots.iter().any(|root| keep(&normalized, root))
+        })
+        .collect()
+}
+
+/**
+ * 現在の呼び出しで許可されたルート
+ *
+ * 戻り値:
+ *   Result<Option<Vec<PathBuf>>> - 許可されたルート（None なら制限なし、空ならすべて拒否）
+ *
+ * エラー:
+ *   roots 機能に対応したクライアントのルートを取得できない場合はエラーを返す
+ */
+async fn allowed_roots() -> Result<Option<Vec<PathBuf>>> {
+    if let Some(context) = CallContext::current().filter(|c| c.session.supports_roots()) {
+        let roots = client_roots(&context).await?;
+        if roots.is_empty() {
+            debug!("クライアントがルートを1つも宣言していないためすべてのパスを拒否します");
+        }
+        return Ok(Some(roots.iter().map(|r| normalize(r)).collect()));
+    }
+    let allowlist = static_allowlist();
+    Ok((!allowlist.is_empty()).then(|| allowlist.iter().map(|r| normalize(r)).collect()))
+}
+
+/// クライアントのルート（セッションのキャッシュがなければ roots/list を要求する）
+async fn client_roots(context: &CallContext) -> Result<Vec<PathBuf>> {
+    if let Some(roots) = context.session.roots() {
+        return Ok(roots);
+    }
+    let response = context.request("roots/list", json!({})).await?;
+    let roots = parse_roots(&response);
+    debug!(count = roots.len(), "クライアントのルートを取得しました");
+    context.session.set_roots(Some(roots.clone()));
+    Ok(roots)
+}
+
+/// roots/list の結果から file:// のルートを取り出す（他のスキームは無視する）
+fn parse_roots(response: &Value) -> Vec<PathBuf> {
+    response
+        .get("roots")
+        .and_then(|r| r.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|root| root.get("uri").and_then(|u| u.as_str()))
+        .filter_map(file_uri_to_path)
+        .collect()
+}
+
+/// AFFINITY_MCP_ALLOWED_ROOTS の許可リスト
+fn static_allowlist() -> Vec<PathBuf> {
+    std::env::var_os("AFFINITY_MCP_ALLOWED_ROOTS")
+        .map(|v| std::env::split_paths(&v).filter(|p| !p.as_os_str().is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/**
+ * file:// URI をパスに変換（%XX のデコードを含む）
+ */
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+
+    let bytes = rest.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| rest.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/**
+ * 比較用にパスを正規化
+ *
+ * 概要:
+ *   相対パスはカレントディレクトリ基準の絶対パスにし、. と .. を字句的に解決する。
+ *   存在する最も深い祖先はシンボリックリンクを解決する（書き出し先はまだ存在しないことがある）。
+ */
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+
+    let mut lexical = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            Component::CurDir => {}
+            other => lexical.push(other),
+        }
+    }
+
+    let mut existing = lexical.clone();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match existing.file_name() {
+            Some(name) => {
+                missing.push(name.to_os_string());
+                existing.pop();
+            }
+            None => break,
+        }
+    }
+    let mut resolved = existing.canonicalize().unwrap_or(existing);
+    resolved.extend(missing.iter().rev());
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::ProtocolVersion;
+    use crate::session::Session;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn file_uris_are_decoded() {
+        assert_eq!(file_uri_to_path("file:///Users/a/My%20Designs"), Some(PathBuf::from("/Users/a/My Designs")));
+        assert_eq!(file_uri_to_path("file://localhost/tmp"), Some(PathBuf::from("/tmp")));
+        assert_eq!(file_uri_to_path("https://example.com/"), None);
+    }
+
+    #[test]
+    fn parent_components_cannot_escape_a_root() {
+        let root = normalize(Path::new("/tmp/affinity-roots-test"));
+        assert!(normalize(Path::new("/tmp/affinity-roots-test/out/a.png")).starts_with(&root));
+        assert!(!normalize(Path::new("/tmp/affinity-roots-test/../etc/passwd")).starts_with(&root));
+        assert!(!normalize(Path::new("/tmp/affinity-roots-test-other/a.png")).starts_with(&root));
+    }
+
+    #[tokio::test]
+    async fn client_roots_restrict_paths() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let session = Arc::new(Session::with_outbound(tx));
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "roots": { "listChanged": true } }));
+
+        // クライアント役: roots/list に1回だけ応答する（2回目以降はキャッシュを使う）
+        let client = session.clone();
+        tokio::spawn(async move {
+            let request: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["method"], "roots/list");
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "roots": [{ "uri": "file:///tmp/affinity-roots-test", "name": "designs" }] },
+            });
+            assert!(client.resolve_response(&response.to_string()));
+        });
+
+        let context = Arc::new(CallContext::new("affinity.export", session, None));
+        let allowed = context.clone().scope(check_paths(&["/tmp/affinity-roots-test/a.png"])).await;
+        assert!(allowed.is_ok());
+        let denied = context.scope(check_paths(&["/etc/passwd"])).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&denied), Some(ToolError::OutsideRoots(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_roots_request_denies_access() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let session = Arc::new(Session::with_outbound(tx));
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        session.set_client_capabilities(json!({ "roots": {} }));
+
+        // クライアント役: roots/list にエラーで応答する
+        let client = session.clone();
+        tokio::spawn(async move {
+            let request: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["method"], "roots/list");
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": -32603, "message": "roots unavailable" },
+            });
+            assert!(client.resolve_response(&response.to_string()));
+        });
+
+        let context = Arc::new(CallContext::new("affinity.export", session.clone(), None));
+        let denied = context.scope(check_paths(&["/tmp/affinity-roots-test/a.png"])).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&denied), Some(ToolError::OutsideRoots(_))));
+        assert!(format!("{:#}", denied).contains("roots unavailable"));
+
+        // 宣言されたルートが空ならすべて拒否する
+        session.set_roots(Some(Vec::new()));
+        let context = Arc::new(CallContext::new("affinity.export", session, None));
+        let denied = context.scope(check_paths(&["/tmp/affinity-roots-test/a.png"])).await.unwrap_err();
+        assert!(matches!(crate::error::classify(&denied), Some(ToolError::OutsideRoots(_))));
+    }
+}
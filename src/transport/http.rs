@@ -0,0 +1,636 @@
+/**
+ * Streamable HTTPトランスポート
+ *
+ * 概要:
+ *   MCP の Streamable HTTP 仕様に従い、単一のエンドポイント（/mcp）で
+ *   POST（クライアント→サーバー）、GET（サーバー発の通知ストリーム）、
+ *   DELETE（セッション終了）を受け付ける。1プロセスで複数のクライアントを扱える。
+ *
+ * 主な仕様:
+ *   - initialize の応答で Mcp-Session-Id を発行し、以降のリクエストでは必須とする
+ *     （initialize が成功した場合だけセッションを登録する。失敗・ID のない initialize では発行しない）
+ *   - tools/call を含むPOSTは、クライアントが text/event-stream を受け付ける場合 SSE で応答し、
+ *     進捗・ログなどの関連通知を最終応答の前に同じストリームで送る
+ *   - それ以外のリクエストは application/json で応答し、通知・応答のみのPOSTは 202 を返す
+ *   - tools/call 中のサーバー発リクエスト（elicitation/create）はその SSE ストリームで送り、
+ *     クライアントの応答は POST で受け取る
+ *   - GET の SSE ストリームにはリソース更新などリクエストに紐づかない通知を送る
+ *     （接続していない間の通知は破棄し、新しいGETは古いストリームを置き換える）
+ *   - GET ストリームがない間のサーバー発リクエスト（roots/list など）は送信せず、
+ *     応答を待たずにエラーにする
+ *   - GET ストリームを開いておらず AFFINITY_MCP_HTTP_SESSION_TIMEOUT_MS（既定: 30分）の間
+ *     リクエストのないセッションは破棄する
+ *   - 終了処理が始まると新しい接続の受付を止め、GET ストリームを閉じる
+ *
+ * セキュリティ:
+ *   - DNSリバインディング対策として Origin ヘッダーを検証する
+ *     （localhost 系と AFFINITY_MCP_HTTP_ORIGINS にカンマ区切りで指定したオリジンのみ許可）
+ *   - 既定では 127.0.0.1 でのみ待ち受ける
+ */
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use jsonrpc_core::MetaIoHandler;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, info, warn};
+
+use crate::logging;
+use crate::mcp::{ProtocolVersion, RequestContext, RequestOrigin};
+use crate::session::Session;
+use crate::shutdown;
+
+/// セッションIDヘッダー
+const SESSION_HEADER: &str = "mcp-session-id";
+/// プロトコルバージョンヘッダー
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+/// 既定のセッションの無操作タイムアウト
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// 無操作セッションを確認する最大の間隔
+const MAX_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/**
+ * HTTPセッション
+ */
+struct HttpSession {
+    session: Arc<Session>,
+    /// 接続中のGETストリーム（なければ None）
+    listener: Mutex<Option<UnboundedSender<String>>>,
+    /// 最後にリクエストを受けた時刻
+    last_active: Mutex<Instant>,
+}
+
+impl HttpSession {
+    /**
+     * セッションの送信キューのメッセージを GET ストリームへ渡す
+     *
+     * 概要:
+     *   ストリームがなければ通知は破棄し、リクエストは応答待ちを失敗させる。
+     */
+    fn forward(&self, message: String) {
+        let mut listener = self.listener.lock().unwrap_or_else(|e| e.into_inner());
+        let undelivered = match listener.as_ref() {
+            Some(stream) => stream.send(message).err().map(|e| e.0),
+            None => Some(message),
+        };
+        if let Some(message) = undelivered {
+            *listener = None;
+            drop(listener);
+            self.session.fail_undelivered(&message, "GET /mcp のストリームが接続されていません");
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_active.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /**
+     * GET ストリームがなく、timeout 以上リクエストがないかどうか
+     *
+     * 概要:
+     *   クライアントが切断したストリーム（受信側が破棄済み）は、ここで外して接続なしとみなす。
+     */
+    fn is_idle(&self, timeout: Duration) -> bool {
+        let mut listener = self.listener.lock().unwrap_or_else(|e| e.into_inner());
+        if listener.as_ref().is_some_and(|stream| stream.is_closed()) {
+            *listener = None;
+        }
+        listener.is_none() && self.last_active.lock().unwrap_or_else(|e| e.into_inner()).elapsed() >= timeout
+    }
+}
+
+/**
+ * サーバー状態
+ */
+struct HttpState {
+    io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>,
+    sessions: Mutex<HashMap<String, Arc<HttpSession>>>,
+    allowed_origins: Vec<String>,
+    /// セッションの無操作タイムアウト
+    session_timeout: Duration,
+}
+
+impl HttpState {
+    fn new(io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>, allowed_origins: Vec<String>, session_timeout: Duration) -> Self {
+        Self {
+            io,
+            sessions: Mutex::new(HashMap::new()),
+            allowed_origins,
+            session_timeout,
+        }
+    }
+
+    fn session(&self, headers: &HeaderMap) -> Result<Arc<HttpSession>, Rejection> {
+        let id = headers
+            .get(SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| reject(StatusCode::BAD_REQUEST, "Mcp-Session-Id ヘッダーがありません"))?;
+        let session = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+            .ok_or_else(|| reject(StatusCode::NOT_FOUND, "セッションが見つかりません"))?;
+        session.touch();
+        Ok(session)
+    }
+
+    /**
+     * 新しいセッションを作成（登録は initialize の成功後に register_session で行う）
+     *
+     * 概要:
+     *   セッションの送信キューは転送タスクを経由して、接続中のGETストリームへ渡す。
+     */
+    fn create_session(&self) -> (String, Arc<HttpSession>) {
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<String>();
+        let session = Arc::new(HttpSession {
+            session: Arc::new(Session::with_outbound(outbound)),
+            listener: Mutex::new(None),
+            last_active: Mutex::new(Instant::now()),
+        });
+
+        // セッションが破棄されると送信キューが閉じてタスクも終わる
+        let forward_to = Arc::downgrade(&session);
+        tokio::spawn(async move {
+            while let Some(message) = outbound_rx.recv().await {
+                let Some(session) = forward_to.upgrade() else {
+                    break;
+                };
+                session.forward(message);
+            }
+        });
+
+        (uuid::Uuid::new_v4().to_string(), session)
+    }
+
+    /// 初期化したセッションを登録
+    fn register_session(&self, id: &str, session: Arc<HttpSession>) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.to_string(), session);
+        debug!(session_id = %id, "HTTPセッションを作成しました");
+    }
+
+    /// 無操作タイムアウトを過ぎたセッションを破棄
+    fn expire_idle_sessions(&self) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|id, session| {
+            if !session.is_idle(self.session_timeout) {
+                return true;
+            }
+            session.session.fail_pending();
+            logging::unregister(&session.session);
+            debug!(session_id = %id, "無操作のHTTPセッションを破棄しました");
+            false
+        });
+    }
+
+    /// Origin ヘッダーを検証（ヘッダーがなければ許可）
+    fn check_origin(&self, headers: &HeaderMap) -> Result<(), Rejection> {
+        let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+            return Ok(());
+        };
+        let authority = origin.split("://").nth(1).unwrap_or(origin);
+        let host = authority
+            .rsplit_once(':')
+            .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+            .map_or(authority, |(host, _)| host);
+        if matches!(host, "localhost" | "127.0.0.1" | "[::1]") || self.allowed_origins.iter().any(|o| o == origin) {
+            return Ok(());
+        }
+        warn!(origin = %origin, "許可されていないOriginからのリクエストを拒否しました");
+        Err(reject(StatusCode::FORBIDDEN, "許可されていないOriginです"))
+    }
+}
+
+/**
+ * Streamable HTTPでサーバーを実行
+ *
+ * 引数:
+ *   io: JSON-RPCハンドラー
+ *   addr: 待ち受けアドレス
+ *
+ * エラー:
+ *   ポートの確保・サーバーの実行に失敗した場合はエラーを返す
+ */
+pub async fn serve(io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>, addr: SocketAddr) -> Result<()> {
+    let allowed_origins = std::env::var("AFFINITY_MCP_HTTP_ORIGINS")
+        .map(|v| v.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect())
+        .unwrap_or_default();
+    let session_timeout = std::env::var("AFFINITY_MCP_HTTP_SESSION_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+    let state = Arc::new(HttpState::new(io, allowed_origins, session_timeout));
+    tokio::spawn(expire_sessions(Arc::downgrade(&state)));
+    let app = router(state.clone());
+
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .with_context(|| format!("HTTPポートの確保に失敗しました: {}", addr))?;
+    info!(addr = %addr, "Streamable HTTPで待ち受けています（/mcp）");
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            shutdown::requested().await;
+            // 終了しないGETストリームを閉じ、処理中のPOSTだけを待つ
+            for session in state.sessions.lock().unwrap_or_else(|e| e.into_inner()).values() {
+                *session.listener.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            }
+        })
+        .await
+        .context("HTTPサーバーの実行に失敗しました")
+}
+
+/// /mcp のルーティング
+fn router(state: Arc<HttpState>) -> Router {
+    Router::new()
+        .route("/mcp", post(handle_post).get(handle_get).delete(handle_delete))
+        .with_state(state)
+}
+
+/**
+ * 無操作セッションの破棄ループ
+ *
+ * 概要:
+ *   サーバー状態が破棄されたら終了する。
+ */
+async fn expire_sessions(state: Weak<HttpState>) {
+    while let Some(interval) = state.upgrade().map(|s| s.session_timeout.min(MAX_EXPIRY_INTERVAL)) {
+        tokio::time::sleep(interval).await;
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+        state.expire_idle_sessions();
+    }
+}
+
+/**
+ * POST /mcp（クライアントからのメッセージ）
+ */
+async fn handle_post(State(state): State<Arc<HttpState>>, headers: HeaderMap, body: String) -> Response {
+    if let Err(response) = state.check_origin(&headers).and_then(|_| check_protocol_version(&headers)) {
+        return response.into_response();
+    }
+
+    let Ok(message) = serde_json::from_str::<Value>(&body) else {
+        let error = json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32700, "message": "Parse error" },
+            "id": null,
+        });
+        return (StatusCode::BAD_REQUEST, json_body(error.to_string())).into_response();
+    };
+
+    let messages: Vec<&Value> = match &message {
+        Value::Array(items) => items.iter().collect(),
+        single => vec![single],
+    };
+    let is_initialize = messages.iter().any(|m| m.get("method").and_then(Value::as_str) == Some("initialize"));
+    let has_requests = messages.iter().any(|m| m.get("method").is_some() && m.get("id").is_some());
+    let has_tool_call = messages.iter().any(|m| m.get("method").and_then(Value::as_str) == Some("tools/call"));
+
+    let (session_id, session) = if is_initialize {
+        let (id, session) = state.create_session();
+        (Some(id), session)
+    } else {
+        match state.session(&headers) {
+            Ok(session) => (None, session),
+            Err(response) => return response.into_response(),
+        }
+    };
+
+    let context = RequestContext::for_message(session.session.clone(), &body);
+
+    // サーバー発リクエスト（elicitation/create など）への応答
+    if session.session.resolve_response(&body) {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    // 通知のみの場合は処理を待たずに受理する（initialize は結果を見てからセッションを登録する）
+    if !has_requests && !is_initialize {
+        let io = state.io.clone();
+        tokio::spawn(async move {
+            io.handle_request(&body, context).await;
+        });
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    // tools/call は関連通知（進捗・ログ）を応答前に届けるため SSE で応答する
+    if has_tool_call && !is_initialize && accepts(&headers, "text/event-stream") {
+        let (stream, stream_rx) = mpsc::unbounded_channel::<String>();
+        let io = state.io.clone();
+        let context = context.with_stream(stream.clone());
+        tokio::spawn(async move {
+            if let Some(response) = io.handle_request(&body, context).await {
+                let _ = stream.send(response);
+            }
+        });
+        return Sse::new(sse_events(stream_rx)).keep_alive(KeepAlive::default()).into_response();
+    }
+
+    let response = state.io.handle_request(&body, context).await;
+    // initialize が成功した場合だけセッションを登録してIDを返す
+    let session_id = session_id
+        .filter(|_| response.as_deref().is_some_and(|r| initialize_succeeded(&messages, r)));
+    if let Some(id) = &session_id {
+        state.register_session(id, session);
+    }
+    let mut response = match response {
+        Some(response) => json_body(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    };
+    if let Some(id) = session_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(SESSION_HEADER, id);
+    }
+    response
+}
+
+/**
+ * initialize が成功したかどうか
+ *
+ * 引数:
+ *   messages: 受信したメッセージ
+ *   response: JSON-RPCハンドラーの応答
+ *
+ * 戻り値:
+ *   bool - ID 付きの initialize に対する result の応答があれば true
+ */
+fn initialize_succeeded(messages: &[&Value], response: &str) -> bool {
+    let Some(id) = messages
+        .iter()
+        .find(|m| m.get("method").and_then(Value::as_str) == Some("initialize"))
+        .and_then(|m| m.get("id"))
+        .filter(|id| !id.is_null())
+    else {
+        return false;
+    };
+    let Ok(response) = serde_json::from_str::<Value>(response) else {
+        return false;
+    };
+    let responses = match &response {
+        Value::Array(items) => items.iter().collect(),
+        single => vec![single],
+    };
+    responses.iter().any(|r| r.get("id") == Some(id) && r.get("result").is_some())
+}
+
+/**
+ * GET /mcp（サーバー発の通知ストリーム）
+ */
+async fn handle_get(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> Response {
+    if let Err(response) = state.check_origin(&headers).and_then(|_| check_protocol_version(&headers)) {
+        return response.into_response();
+    }
+    if !accepts(&headers, "text/event-stream") {
+        return reject(StatusCode::METHOD_NOT_ALLOWED, "text/event-stream を受け付けるクライアントのみ利用できます").into_response();
+    }
+    let session = match state.session(&headers) {
+        Ok(session) => session,
+        Err(response) => return response.into_response(),
+    };
+
+    let (listener, listener_rx) = mpsc::unbounded_channel::<String>();
+    *session.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+    Sse::new(sse_events(listener_rx)).keep_alive(KeepAlive::default()).into_response()
+}
+
+/**
+ * DELETE /mcp（セッション終了）
+ */
+async fn handle_delete(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> Response {
+    if let Err(response) = state.check_origin(&headers) {
+        return response.into_response();
+    }
+    let Some(id) = headers.get(SESSION_HEADER).and_then(|v| v.to_str().ok()) else {
+        return reject(StatusCode::BAD_REQUEST, "Mcp-Session-Id ヘッダーがありません").into_response();
+    };
+    match state.sessions.lock().unwrap_or_else(|e| e.into_inner()).remove(id) {
+        Some(session) => {
+            session.session.fail_pending();
+            logging::unregister(&session.session);
+            debug!(session_id = %id, "HTTPセッションを終了しました");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => reject(StatusCode::NOT_FOUND, "セッションが見つかりません").into_response(),
+    }
+}
+
+/// MCP-Protocol-Version ヘッダーを検証（ヘッダーがなければ許可）
+fn check_protocol_version(headers: &HeaderMap) -> Result<(), Rejection> {
+    match headers.get(PROTOCOL_VERSION_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(version) if !ProtocolVersion::SUPPORTED.iter().any(|v| v.as_str() == version) => Err(reject(
+            StatusCode::BAD_REQUEST,
+            &format!("対応していないプロトコルバージョンです: {}", version),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Accept ヘッダーが指定のメディアタイプを含むかどうか
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains(media_type) || accept.contains("*/*"))
+}
+
+/// 送信キューを SSE の message イベント列にする
+fn sse_events(rx: UnboundedReceiver<String>) -> impl futures::Stream<Item = Result<Event, Infallible>> {
+    futures::stream::unfold(rx, |mut rx| async move {
+        let message = rx.recv().await?;
+        Some((Ok(Event::default().event("message").data(message)), rx))
+    })
+}
+
+fn json_body(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], Body::from(body)).into_response()
+}
+
+/**
+ * リクエストの拒否（HTTPステータスとメッセージ）
+ */
+struct Rejection {
+    status: StatusCode,
+    message: String,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+fn reject(status: StatusCode, message: &str) -> Rejection {
+    Rejection {
+        status,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::backend::{MockBackend, ScriptOutput};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn state(session_timeout: Duration) -> Arc<HttpState> {
+        let server = crate::mcp::test_server(
+            MockBackend::default()
+                .then(ScriptOutput::ok("Affinity Photo"))
+                .then(ScriptOutput::ok("a.afphoto|/x/a.afphoto|false")),
+        )
+        .await;
+        Arc::new(HttpState::new(server.io, Vec::new(), session_timeout))
+    }
+
+    fn post(session_id: Option<&str>, message: Value) -> Request<Body> {
+        let mut request = Request::post("/mcp")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/json");
+        if let Some(id) = session_id {
+            request = request.header(SESSION_HEADER, id);
+        }
+        request.body(Body::from(message.to_string())).unwrap()
+    }
+
+    async fn json(response: Response) -> Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn initialize(state: &Arc<HttpState>) -> String {
+        let response = router(state.clone())
+            .oneshot(post(
+                None,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "initialize",
+                    "params": { "protocolVersion": "2025-06-18", "capabilities": { "roots": {} } },
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let id = response.headers()[SESSION_HEADER].to_str().unwrap().to_string();
+        assert_eq!(json(response).await["result"]["protocolVersion"], "2025-06-18");
+        id
+    }
+
+    #[tokio::test]
+    async fn a_session_lives_from_initialize_until_delete() {
+        let state = state(DEFAULT_SESSION_TIMEOUT).await;
+        let id = initialize(&state).await;
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        let response = router(state.clone()).oneshot(post(Some(&id), initialized)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": { "name": "affinity.get_active_document", "arguments": {} },
+        });
+        let response = router(state.clone()).oneshot(post(Some(&id), call.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let result = &json(response).await["result"];
+        assert_eq!(result["structuredContent"]["name"], "a.afphoto");
+        assert_eq!(result["structuredContent"]["app"], "Affinity Photo");
+
+        let response = router(state.clone()).oneshot(post(None, call.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let delete = Request::delete("/mcp").header(SESSION_HEADER, &id).body(Body::empty()).unwrap();
+        let response = router(state.clone()).oneshot(delete).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = router(state.clone()).oneshot(post(Some(&id), call)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn failed_initialize_creates_no_session() {
+        let state = state(DEFAULT_SESSION_TIMEOUT).await;
+        let unsupported = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "protocolVersion": "2023-01-01" },
+        });
+        let response = router(state.clone()).oneshot(post(None, unsupported)).await.unwrap();
+        assert!(response.headers().get(SESSION_HEADER).is_none());
+        assert_eq!(json(response).await["error"]["code"], -32602);
+
+        let without_id = json!({
+            "jsonrpc": "2.0",
+            "method": "initialize",
+            "params": { "protocolVersion": "2025-06-18" },
+        });
+        let response = router(state.clone()).oneshot(post(None, without_id)).await.unwrap();
+        assert!(response.headers().get(SESSION_HEADER).is_none());
+        assert!(state.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_expire() {
+        let state = state(Duration::from_millis(1)).await;
+        let id = initialize(&state).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        state.expire_idle_sessions();
+
+        let ping = json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" });
+        let response = router(state.clone()).oneshot(post(Some(&id), ping)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn sessions_with_an_abandoned_stream_expire() {
+        let state = state(Duration::from_millis(1)).await;
+        let id = initialize(&state).await;
+
+        let get = Request::get("/mcp")
+            .header(header::ACCEPT, "text/event-stream")
+            .header(SESSION_HEADER, &id)
+            .body(Body::empty())
+            .unwrap();
+        let stream = router(state.clone()).oneshot(get).await.unwrap();
+        assert_eq!(stream.status(), StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        state.expire_idle_sessions();
+        assert!(state.sessions.lock().unwrap().contains_key(&id), "接続中のストリームがあれば残す");
+
+        // 通知を受け取らないまま切断したクライアント
+        drop(stream);
+        state.expire_idle_sessions();
+        assert!(!state.sessions.lock().unwrap().contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn server_requests_without_a_stream_fail_immediately() {
+        let state = state(DEFAULT_SESSION_TIMEOUT).await;
+        let id = initialize(&state).await;
+        let session = state.sessions.lock().unwrap()[&id].session.clone();
+
+        let error = tokio::time::timeout(Duration::from_secs(5), session.request("roots/list", json!({}), None))
+            .await
+            .expect("タイムアウトまで待たずに失敗する")
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("GET /mcp のストリームが接続されていません"), "{:#}", error);
+    }
+}
@@ -0,0 +1,112 @@
+/**
+ * 1行1メッセージのJSON-RPC接続
+ *
+ * 概要:
+ *   読み取り側から1行ずつJSON-RPCメッセージを読み、応答とサーバー発の通知を
+ *   書き込み側へ1行ずつ書き出す。stdio と Unix ソケットの各接続で共通に使う。
+ *
+ * 主な仕様:
+ *   - 1接続 = 1セッション（initialize の状態・ネゴシエーション済みバージョンは接続ごと）
+ *   - リクエストは並行に処理する（長いツール実行中も通知や他のリクエストを処理できる）
+ *   - 書き込みは専用タスクに集約し、メッセージが混ざらないようにする
+ *   - サーバー発リクエストへの応答はハンドラーに渡さずセッションに渡す
+ *   - 読み取り側のEOF、または終了処理の開始（shutdown）で新規受付を終了し、
+ *     処理中のリクエストの完了を待って終了する（期限は main の shutdown::drain が管理する）
+ *   - shutdown_on_eof の場合は EOF で終了処理を開始する（stdio: クライアントの終了 = サーバーの終了）
+ */
+use anyhow::{Context, Result};
+use jsonrpc_core::MetaIoHandler;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::logging;
+use crate::mcp::{RequestContext, RequestOrigin};
+use crate::session::Session;
+use crate::shutdown;
+
+/**
+ * 1接続分のセッションを実行
+ *
+ * 引数:
+ *   io: JSON-RPCハンドラー
+ *   reader: 受信側（1行1メッセージ）
+ *   writer: 送信側
+ *   shutdown_on_eof: EOF でプロセス全体の終了処理を開始するかどうか
+ *
+ * エラー:
+ *   読み書きに失敗した場合はエラーを返す
+ */
+pub async fn serve_connection<R, W>(
+    io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>,
+    reader: R,
+    mut writer: W,
+    shutdown_on_eof: bool,
+) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<String>();
+    let session = Arc::new(Session::with_outbound(outbound.clone()));
+
+    // 書き込みタスク
+    let writer = tokio::spawn(async move {
+        while let Some(message) = outbound_rx.recv().await {
+            writer.write_all(message.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let mut lines = reader.lines();
+    let mut in_flight = JoinSet::new();
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line.context("メッセージの読み取りに失敗しました")?,
+            _ = shutdown::requested() => {
+                tracing::debug!("終了処理のため受信を停止します");
+                break;
+            }
+        };
+        let Some(line) = line else {
+            if shutdown_on_eof {
+                shutdown::request();
+            }
+            break;
+        };
+        let line = line.trim().to_string();
+        if line.is_empty() || session.resolve_response(&line) {
+            continue;
+        }
+
+        let io = io.clone();
+        let context = RequestContext::for_message(session.clone(), &line);
+        let outbound = outbound.clone();
+        in_flight.spawn(async move {
+            if let Some(response) = io.handle_request(&line, context).await {
+                let _ = outbound.send(response);
+            }
+        });
+
+        // 完了済みのタスクを回収
+        while in_flight.try_join_next().is_some() {}
+    }
+
+    tracing::debug!(in_flight = in_flight.len(), "受信を終了しました。処理中のリクエストを待機します");
+    // 応答はもう届かないため、クライアントの応答を待っている処理を終わらせる
+    session.fail_pending();
+    while in_flight.join_next().await.is_some() {}
+    logging::unregister(&session);
+
+    // 送信キューを閉じて書き込みタスクを終了させる
+    drop(outbound);
+    drop(session);
+    writer.await
+        .context("書き込みタスクの完了待機に失敗しました")?
+        .context("メッセージの書き込みに失敗しました")?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+/**
+ * トランスポート
+ *
+ * 概要:
+ *   mcp::build_server で構築した JSON-RPC ハンドラーをクライアントに公開する。
+ *   接続ごとに Session を作成し、リクエストのメタデータとして渡す。
+ *
+ * 主な仕様:
+ *   - stdio: 標準入出力（1行1メッセージ）
+ *   - http: MCP Streamable HTTP（POST + SSE、Mcp-Session-Id によるセッション）
+ *   - unix: Unixドメインソケット（接続ごとに1セッション、1行1メッセージ）
+ *   - 選択は CLI 引数 --transport（優先）または環境変数 AFFINITY_MCP_TRANSPORT
+ *   - HTTP の待ち受けアドレスは --http-addr または AFFINITY_MCP_HTTP_ADDR（既定: 127.0.0.1:8765）
+ *   - ソケットのパスは --socket-path または AFFINITY_MCP_SOCKET（既定: 一時ディレクトリの affinity-mcp.sock）
+ */
+pub mod http;
+mod lines;
+pub mod stdio;
+#[cfg(unix)]
+pub mod unix;
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// HTTPトランスポートの既定の待ち受けアドレス
+const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:8765";
+
+/**
+ * トランスポートの種類
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportKind {
+    /// 標準入出力
+    Stdio,
+    /// Streamable HTTP
+    Http(SocketAddr),
+    /// Unixドメインソケット
+    Unix(PathBuf),
+}
+
+impl TransportKind {
+    /**
+     * CLI引数と環境変数からトランスポートを決定
+     *
+     * 引数:
+     *   args: プログラム名を除くCLI引数
+     *
+     * エラー:
+     *   未知のトランスポート名・不正なアドレスの場合はエラーを返す
+     */
+    pub fn from_args_and_env(args: &[String]) -> Result<Self> {
+        let transport = cli_option(args, "--transport")
+            .or_else(|| std::env::var("AFFINITY_MCP_TRANSPORT").ok())
+            .unwrap_or_else(|| "stdio".to_string());
+
+        match transport.to_lowercase().as_str() {
+            "stdio" => Ok(TransportKind::Stdio),
+            "http" => {
+                let addr = cli_option(args, "--http-addr")
+                    .or_else(|| std::env::var("AFFINITY_MCP_HTTP_ADDR").ok())
+                    .unwrap_or_else(|| DEFAULT_HTTP_ADDR.to_string());
+                let addr = addr
+                    .parse()
+                    .with_context(|| format!("HTTPの待ち受けアドレスが不正です: {}", addr))?;
+                Ok(TransportKind::Http(addr))
+            }
+            "unix" if cfg!(unix) => {
+                let path = cli_option(args, "--socket-path")
+                    .or_else(|| std::env::var("AFFINITY_MCP_SOCKET").ok())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| std::env::temp_dir().join("affinity-mcp.sock"));
+                Ok(TransportKind::Unix(path))
+            }
+            other => anyhow::bail!("不明なトランスポートです: {}（stdio / http / unix）", other),
+        }
+    }
+}
+
+/// `--name value` または `--name=value` 形式のCLI引数を取得
+fn cli_option(args: &[String], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == name {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(&prefix).map(str::to_string)
+        }
+    })
+}
@@ -0,0 +1,31 @@
+/**
+ * STDIOトランスポート
+ *
+ * 概要:
+ *   標準入力から1行1メッセージのJSON-RPCを読み、応答とサーバー発の通知を
+ *   標準出力へ1行ずつ書き出す（接続処理は lines モジュールと共通）。
+ *
+ * 主な仕様:
+ *   - プロセス全体で1セッション
+ *   - 標準入力のEOFはクライアントの終了とみなし、サーバー全体の終了処理を開始する
+ */
+use anyhow::Result;
+use jsonrpc_core::MetaIoHandler;
+use std::sync::Arc;
+use tokio::io::BufReader;
+
+use super::lines;
+use crate::mcp::{RequestContext, RequestOrigin};
+
+/**
+ * STDIOでサーバーを実行
+ *
+ * 引数:
+ *   io: JSON-RPCハンドラー
+ *
+ * エラー:
+ *   標準入出力の読み書きに失敗した場合はエラーを返す
+ */
+pub async fn serve(io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>) -> Result<()> {
+    lines::serve_connection(io, BufReader::new(tokio::io::stdin()), tokio::io::stdout(), true).await
+}
@@ -0,0 +1,144 @@
+/**
+ * Unixドメインソケットトランスポート
+ *
+ * 概要:
+ *   Unixソケットで待ち受け、接続ごとに独立したMCPセッションを提供する。
+ *   ローカルのツールやスクリプトが、クライアントごとにプロセスを起動せずに
+ *   稼働中のサーバーへ接続できる。
+ *
+ * 主な仕様:
+ *   - 1接続 = 1セッション（プロトコルは stdio と同じ1行1メッセージ）
+ *   - ツール（Affinityブリッジ）はすべての接続で共有し、AppleScript の実行は
+ *     プロセス全体で直列化される（tools::backend 参照）
+ *   - 起動時に応答しない古いソケットファイルは削除する（稼働中のサーバーがあればエラー）
+ *   - ソケットファイルの権限は所有者のみ（0600）。所有者のみがアクセスできる（0700）一時ディレクトリ内で
+ *     作成して権限を設定してから指定のパスへ移すため、ほかのユーザーが接続できる瞬間はない
+ *   - 終了処理が始まると新しい接続の受付を止めてソケットファイルを削除し、
+ *     各接続の処理中のリクエストが終わるまで待つ
+ */
+use anyhow::{Context, Result};
+use jsonrpc_core::MetaIoHandler;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::BufReader;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info};
+
+use super::lines;
+use crate::mcp::{RequestContext, RequestOrigin};
+use crate::shutdown;
+
+/**
+ * Unixソケットでサーバーを実行
+ *
+ * 引数:
+ *   io: JSON-RPCハンドラー
+ *   path: ソケットファイルのパス
+ *
+ * エラー:
+ *   ソケットの作成に失敗した場合、または同じパスで別のサーバーが稼働中の場合はエラーを返す
+ */
+pub async fn serve(io: Arc<MetaIoHandler<RequestContext, RequestOrigin>>, path: &Path) -> Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!("ソケットは別のサーバーが使用中です: {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("古いソケットファイルの削除に失敗しました: {}", path.display()))?;
+    }
+
+    let listener = bind_private(path)?;
+    info!(path = %path.display(), "Unixソケットで待ち受けています");
+
+    let mut connection_id: u64 = 0;
+    let mut connections = JoinSet::new();
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted.context("Unixソケットの接続受付に失敗しました")?,
+            _ = shutdown::requested() => break,
+        };
+        connection_id += 1;
+        let id = connection_id;
+        let io = io.clone();
+        connections.spawn(async move {
+            debug!(connection_id = id, "Unixソケット接続を受け付けました");
+            let (reader, writer) = stream.into_split();
+            match lines::serve_connection(io, BufReader::new(reader), writer, false).await {
+                Ok(()) => debug!(connection_id = id, "Unixソケット接続が終了しました"),
+                Err(e) => error!(connection_id = id, error = %format!("{:#}", e), "Unixソケット接続でエラーが発生しました"),
+            }
+        });
+        // 終了済みの接続を回収
+        while connections.try_join_next().is_some() {}
+    }
+
+    drop(listener);
+    if let Err(e) = std::fs::remove_file(path) {
+        debug!(error = %e, "ソケットファイルを削除できませんでした");
+    }
+    debug!(connections = connections.len(), "接続の終了を待機します");
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+/**
+ * 所有者だけが接続できるソケットを作成
+ *
+ * 概要:
+ *   同じディレクトリに 0700 の一時ディレクトリを作ってその中でソケットを作成し、
+ *   権限を 0600 にしてから指定のパスへ rename する（待ち受けは rename 後も続く）。
+ *
+ * エラー:
+ *   ソケット・一時ディレクトリの作成、権限の設定、移動に失敗した場合はエラーを返す
+ */
+fn bind_private(path: &Path) -> Result<UnixListener> {
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let staging = parent.join(format!(".affinity-mcp-{}.tmp", std::process::id()));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("一時ディレクトリの削除に失敗しました: {}", staging.display()))?;
+    }
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .with_context(|| format!("一時ディレクトリの作成に失敗しました: {}", staging.display()))?;
+
+    let staged = staging.join("socket");
+    let bound = UnixListener::bind(&staged)
+        .with_context(|| format!("Unixソケットの作成に失敗しました: {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("ソケットファイルの権限設定に失敗しました: {}", path.display()))?;
+            std::fs::rename(&staged, path)
+                .with_context(|| format!("ソケットファイルの移動に失敗しました: {}", path.display()))?;
+            Ok(listener)
+        });
+    if let Err(e) = std::fs::remove_dir_all(&staging) {
+        debug!(error = %e, "一時ディレクトリを削除できませんでした");
+    }
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn socket_is_created_owner_only() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server.sock");
+
+        let listener = bind_private(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // 一時ディレクトリは残らず、移動後のパスで接続できる
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let (connected, accepted) = tokio::join!(UnixStream::connect(&path), listener.accept());
+        assert!(connected.is_ok() && accepted.is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
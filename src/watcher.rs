@@ -0,0 +1,323 @@
+/**
+ * リソース変更監視
+ *
+ * 概要:
+ *   購読されたリソースを定期的にポーリングし、変化があれば購読中のセッションへ
+ *   notifications/resources/updated を送信する。
+ *   監視フォルダが設定されていれば、フォルダ内のファイルの追加・削除を検出して
+ *   notifications/resources/list_changed を送信する。
+ *
+ * 主な仕様:
+ *   - affinity://documents/active: 名前・パス・変更状態（modified）の変化を検出
+ *   - affinity://files/...: インデックス済みファイルの更新日時・サイズ・有無の変化を検出
+ *   - 監視フォルダは環境変数 AFFINITY_MCP_WATCH_DIRS（PATH と同じ区切り、既定: なし）。
+ *     直下のリソース対象ファイル（pdf/png/jpg/tif/svg）をインデックスに登録する
+ *   - 監視間隔は環境変数 AFFINITY_MCP_WATCH_INTERVAL_MS（既定: 2000ms）
+ *   - 監視タスクは最初の watch() 時に起動する。購読がなく監視フォルダもなければ何も取得しない
+ *
+ * 制限事項:
+ *   - 初回観測時点の状態を基準とするため、購読直後の変化は次の周期で通知される
+ *   - 監視フォルダのサブフォルダは走査しない
+ */
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::resources::{self, ACTIVE_DOCUMENT_URI};
+use crate::session::Session;
+use crate::tools::backend::ScriptBackend;
+use crate::tools::affinity;
+
+/// 既定の監視間隔
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(2000);
+
+/**
+ * リソース監視
+ */
+pub struct ResourceWatcher {
+    /// 購読しているセッション（切断済みのものは周期ごとに除去）
+    sessions: Mutex<Vec<Weak<Session>>>,
+    /// 監視タスクを起動済みかどうか
+    started: AtomicBool,
+    /// ポーリング間隔
+    interval: Duration,
+    /// 監視フォルダ
+    folders: Vec<PathBuf>,
+    /// アクティブドキュメントの取得に使うスクリプト実行バックエンド
+    backend: Arc<dyn ScriptBackend>,
+}
+
+impl ResourceWatcher {
+    /**
+     * 環境変数の設定で監視を作成
+     *
+     * 引数:
+     *   backend: アクティブドキュメントの取得に使うスクリプト実行バックエンド
+     *
+     * 戻り値:
+     *   Arc<ResourceWatcher> - 監視（タスクは watch() 時に起動）
+     */
+    pub fn from_env(backend: Arc<dyn ScriptBackend>) -> Arc<Self> {
+        let interval = std::env::var("AFFINITY_MCP_WATCH_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_INTERVAL);
+        let folders = std::env::var_os("AFFINITY_MCP_WATCH_DIRS")
+            .map(|v| std::env::split_paths(&v).filter(|p| !p.as_os_str().is_empty()).collect())
+            .unwrap_or_default();
+        Self::new(backend, interval, folders)
+    }
+
+    /**
+     * 監視を作成
+     *
+     * 引数:
+     *   backend: アクティブドキュメントの取得に使うスクリプト実行バックエンド
+     *   interval: ポーリング間隔
+     *   folders: 監視フォルダ
+     */
+    pub fn new(backend: Arc<dyn ScriptBackend>, interval: Duration, folders: Vec<PathBuf>) -> Arc<Self> {
+        Arc::new(Self {
+            sessions: Mutex::new(Vec::new()),
+            started: AtomicBool::new(false),
+            interval,
+            folders,
+            backend,
+        })
+    }
+
+    /// 監視フォルダが設定されているかどうか（resources の listChanged に使う）
+    pub fn watches_folders(&self) -> bool {
+        !self.folders.is_empty()
+    }
+
+    /**
+     * セッションを監視対象に追加
+     *
+     * 概要:
+     *   購読内容はセッション側に保持され、監視タスクは周期ごとに参照する。
+     */
+    pub fn watch(self: &Arc<Self>, session: &Arc<Session>) {
+        {
+            let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            if !sessions.iter().any(|s| std::ptr::eq(s.as_ptr(), Arc::as_ptr(session))) {
+                sessions.push(Arc::downgrade(session));
+            }
+        }
+        if !self.started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(run(Arc::downgrade(self)));
+        }
+    }
+
+    /// 生存しているセッション
+    fn live_sessions(&self) -> Vec<Arc<Session>> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|s| s.strong_count() > 0);
+        sessions.iter().filter_map(Weak::upgrade).collect()
+    }
+}
+
+/**
+ * 監視ループ
+ *
+ * 概要:
+ *   監視オブジェクトが破棄されたら終了する。
+ */
+async fn run(watcher: Weak<ResourceWatcher>) {
+    let mut state = WatchState::default();
+
+    while let Some(interval) = watcher.upgrade().map(|w| w.interval) {
+        tokio::time::sleep(interval).await;
+        let Some(watcher) = watcher.upgrade() else {
+            break;
+        };
+        watcher.poll(&mut state).await;
+    }
+}
+
+/**
+ * 前回の周期で観測した状態
+ */
+#[derive(Debug, Default)]
+struct WatchState {
+    /// 購読中のURI → 状態
+    last_seen: HashMap<String, String>,
+    /// 監視フォルダ内のファイル（未走査なら None）
+    folder_files: Option<BTreeSet<PathBuf>>,
+}
+
+impl ResourceWatcher {
+    /**
+     * 1周期分の監視
+     *
+     * 概要:
+     *   監視フォルダを走査して新しいファイルをインデックスに登録し、
+     *   購読中のリソースの状態を前回と比較して変化を通知する。
+     */
+    async fn poll(&self, state: &mut WatchState) {
+        let sessions = self.live_sessions();
+
+        if self.watches_folders() {
+            let files = scan_folders(&self.folders);
+            let previous = state.folder_files.replace(files.clone());
+            for path in files.iter().filter(|p| previous.as_ref().is_none_or(|previous| !previous.contains(*p))) {
+                resources::record_file(&path.to_string_lossy(), resources::WATCHED_FOLDER);
+            }
+            // 初回の走査は基準として登録するだけで通知しない
+            if previous.is_some_and(|previous| previous != files) {
+                debug!(file_count = files.len(), "監視フォルダの変更を検出しました");
+                for session in &sessions {
+                    session.notify("notifications/resources/list_changed", json!({}));
+                }
+            }
+        }
+
+        let subscribed: HashSet<String> = sessions.iter().flat_map(|s| s.subscriptions()).collect();
+        state.last_seen.retain(|uri, _| subscribed.contains(uri));
+
+        for uri in &subscribed {
+            let Some(fingerprint) = fingerprint(uri, &self.backend).await else {
+                continue;
+            };
+            let previous = state.last_seen.insert(uri.clone(), fingerprint.clone());
+            // 初回観測は基準として記録するだけで通知しない
+            if previous.is_none_or(|previous| previous == fingerprint) {
+                continue;
+            }
+
+            debug!(uri = %uri, "リソースの変更を検出しました");
+            for session in sessions.iter().filter(|s| s.subscriptions().contains(uri)) {
+                session.notify("notifications/resources/updated", json!({ "uri": uri }));
+            }
+        }
+    }
+}
+
+/**
+ * 監視フォルダ直下のリソース対象ファイル
+ *
+ * 概要:
+ *   読めないフォルダは警告を出して空として扱う。
+ */
+fn scan_folders(folders: &[PathBuf]) -> BTreeSet<PathBuf> {
+    let mut files = BTreeSet::new();
+    for folder in folders {
+        let entries = match std::fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(folder = %folder.display(), error = %e, "監視フォルダを読み取れません");
+                continue;
+            }
+        };
+        files.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && resources::mime_type_for(path).is_some()),
+        );
+    }
+    files
+}
+
+/**
+ * リソースの状態を比較用の文字列にする
+ *
+ * 戻り値:
+ *   Option<String> - 取得できなかった場合は None（変化なしとして扱う）
+ */
+async fn fingerprint(uri: &str, backend: &Arc<dyn ScriptBackend>) -> Option<String> {
+    if uri == ACTIVE_DOCUMENT_URI {
+        return match affinity::get_active_document(backend.clone(), affinity::DocumentParams::default()).await {
+            Ok(document) => Some(format!(
+                "{}|{:?}|{:?}|{:?}",
+                document.is_open, document.name, document.path, document.modified
+            )),
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "アクティブドキュメントの監視に失敗しました");
+                None
+            }
+        };
+    }
+    resources::file_fingerprint(uri).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::ProtocolVersion;
+    use crate::tools::backend::{MockBackend, ScriptOutput};
+    use tokio::sync::mpsc;
+
+    fn session() -> (Arc<Session>, mpsc::UnboundedReceiver<String>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let session = Arc::new(Session::with_outbound(tx));
+        session.initialize(ProtocolVersion::V2025_06_18, None);
+        (session, rx)
+    }
+
+    #[tokio::test]
+    async fn active_document_changes_are_notified_to_subscribers() {
+        // 周期ごとにアプリの解決とドキュメント情報の取得で2回スクリプトを実行する
+        let backend = Arc::new(
+            MockBackend::default()
+                .then(ScriptOutput::ok("Affinity Photo"))
+                .then(ScriptOutput::ok("a.afphoto|/x/a.afphoto|false"))
+                .then(ScriptOutput::ok("Affinity Photo"))
+                .then(ScriptOutput::ok("a.afphoto|/x/a.afphoto|false"))
+                .then(ScriptOutput::ok("Affinity Photo"))
+                .then(ScriptOutput::ok("a.afphoto|/x/a.afphoto|true")),
+        );
+        let watcher = ResourceWatcher::new(backend, Duration::from_secs(3600), Vec::new());
+        let (subscriber, mut subscriber_rx) = session();
+        let (other, mut other_rx) = session();
+        subscriber.subscribe(ACTIVE_DOCUMENT_URI);
+        watcher.watch(&subscriber);
+        watcher.watch(&other);
+
+        let mut state = WatchState::default();
+        watcher.poll(&mut state).await;
+        watcher.poll(&mut state).await;
+        assert!(subscriber_rx.try_recv().is_err(), "変化がなければ通知しない");
+
+        watcher.poll(&mut state).await;
+        let message: serde_json::Value = serde_json::from_str(&subscriber_rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/resources/updated");
+        assert_eq!(message["params"]["uri"], ACTIVE_DOCUMENT_URI);
+        assert!(other_rx.try_recv().is_err(), "購読していないセッションには通知しない");
+    }
+
+    #[tokio::test]
+    async fn new_files_in_watched_folders_are_listed() {
+        let dir = std::env::temp_dir().join(format!("affinity-mcp-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("before.png"), b"png").unwrap();
+        let watcher = ResourceWatcher::new(Arc::new(MockBackend::default()), Duration::from_secs(3600), vec![dir.clone()]);
+        let (session, mut rx) = session();
+        watcher.watch(&session);
+
+        let mut state = WatchState::default();
+        watcher.poll(&mut state).await;
+        assert!(rx.try_recv().is_err(), "初回の走査は通知しない");
+
+        std::fs::write(dir.join("after.pdf"), b"pdf").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"txt").unwrap();
+        watcher.poll(&mut state).await;
+        let message: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/resources/list_changed");
+
+        let listed: Vec<String> = resources::list_resources().await.into_iter().map(|r| r.name).collect();
+        assert!(listed.contains(&"before.png".to_string()));
+        assert!(listed.contains(&"after.pdf".to_string()));
+        assert!(!listed.contains(&"notes.txt".to_string()));
+
+        watcher.poll(&mut state).await;
+        assert!(rx.try_recv().is_err(), "変化がなければ通知しない");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}